@@ -2,26 +2,37 @@ use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
 use std::ops::RangeInclusive;
-use std::path::Path;
-use std::sync::mpsc;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
-use iced::{image, Column, Length, Text, Align, button, Checkbox, TextInput, text_input, Space, Container, slider, Row};
+use iced::keyboard::KeyCode;
+use iced::{image, svg, Column, Length, Text, Align, button, Checkbox, TextInput, text_input, Space, Container, slider, pick_list, Row, Svg};
 use iced_futures::Command;
 use iced_native::Image;
 
-use crate::comm::{Comm, Message, Receiver, Sender, Value};
-use crate::sound::play_audio;
-use crate::util::{timestamp, async_write_to_file, resource, template, output};
-use crate::global::Global;
-use crate::style::button;
+use crate::comm::{report, Comm, Message, Receiver, Sender, Value, wait_for_resume};
+use crate::config::Config;
+use crate::error::Error;
+use crate::sound::{play_audio, play_sequence, Playback, Trigger};
+use crate::logger::{async_write_to_file, append_line};
+use crate::util::{timestamp, event_time, resource, template};
+use crate::global::{Global, KeyChord};
+use crate::hardware;
+use crate::markup;
+use crate::style::{self, button, parse_color, Grid, Orientation, TextStyle};
 
 use Question::*;
 
 pub type ID = String;
 pub const MAX_DEPTH: u16 = 3;
+/// Value of `on_timeout` that records a missed response, rather than running a fallback action.
+pub const MARK_MISSED: &str = "mark_missed";
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct Info {
     #[serde(default)]
     id: ID,
@@ -29,16 +40,45 @@ pub struct Info {
     with: Option<ID>,
     #[serde(default)]
     after: Option<HashSet<ID>>,
+    #[serde(default, skip_serializing_if="HashMap::is_empty")]
+    branch_on: HashMap<String, ID>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    on_timeout: Option<ID>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    monitor_kb: Option<bool>,
+    /// Keys that are logged and passed on to the action; any other key is ignored
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    keys: Option<Vec<String>>,
+    /// Whether ignored keys are still logged (marked as INVALID)
     #[serde(default, skip_serializing_if="std::ops::Not::not")]
-    monitor_kb: bool,
+    log_invalid_keys: bool,
     #[serde(skip)]
     keystrokes: Vec<String>,
+    /// Keys held down, with the time they were pressed (from onset)
+    #[serde(skip)]
+    held_keys: Vec<(KeyCode, f64)>,
+    #[serde(skip)]
+    key_holds: Vec<KeyHold>,
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    track_mouse: bool,
+    #[serde(skip)]
+    cursor: Vec<CursorSample>,
     #[serde(default, skip_serializing_if="Option::is_none")]
     background: Option<String>,
     #[serde(skip)]
     background_image: Option<image::Handle>,
     #[serde(default, skip_serializing_if="Option::is_none")]
-    timeout: Option<u32>,
+    background_color: Option<String>,
+    #[serde(skip)]
+    backdrop: Option<iced::Color>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    style: Option<TextStyle>,
+    #[serde(default, skip_serializing_if="Option::is_none", deserialize_with="deserialize::opt_duration", serialize_with="serialize::opt_duration")]
+    timeout: Option<Duration>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    frames: Option<u32>,
+    #[serde(default, skip_serializing_if="Option::is_none", deserialize_with="deserialize::opt_duration", serialize_with="serialize::opt_duration")]
+    min_rt: Option<Duration>,
     #[serde(skip)]
     dependents: HashSet<ID>,
     #[serde(skip)]
@@ -46,8 +86,22 @@ pub struct Info {
     #[serde(skip)]
     expired: Option<bool>,
     #[serde(skip)]
+    missing: Option<String>,
+    #[serde(skip)]
+    onset: Option<Instant>,
+    #[serde(skip)]
+    block_onset: Option<Instant>,
+    #[serde(skip)]
+    responses: Vec<Duration>,
+    #[serde(skip)]
+    response_rt: Option<Duration>,
+    #[serde(skip)]
+    timed_out: bool,
+    #[serde(skip)]
     log_prefix: String,
     #[serde(skip)]
+    partial: Vec<Option<String>>,
+    #[serde(skip)]
     comm: Vec<Sender>,
 }
 
@@ -62,8 +116,12 @@ pub enum Action {
     },
     Instruction {
         prompt: String,
-        #[serde(default="default::timer")]
-        timer: u32,
+        #[serde(default, deserialize_with="deserialize::duration", serialize_with="serialize::duration")]
+        timer: Duration,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        advance_on_key: bool,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        accept_keys: Option<Vec<String>>,
         #[serde(default, flatten)]
         info: Info,
         #[serde(skip)]
@@ -71,40 +129,208 @@ pub enum Action {
     },
     Selection {
         prompt: String,
-        options: Vec<String>,
+        options: Vec<SelectionOption>,
+        #[serde(default="default::feedback_timer", deserialize_with="deserialize::duration", serialize_with="serialize::duration")]
+        feedback_timer: Duration,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        columns: Option<usize>,
+        #[serde(default="style::default_button_width")]
+        button_width: u16,
+        #[serde(default)]
+        orientation: Orientation,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        confirm: bool,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        shuffle_options: bool,
         #[serde(default, flatten)]
         info: Info,
         #[serde(skip_deserializing)]
         choice: Option<usize>,
         #[serde(skip)]
+        option_order: Vec<usize>,
+        #[serde(skip)]
+        pending: Option<usize>,
+        #[serde(skip)]
+        changes: Vec<SelectionChange>,
+        #[serde(skip)]
         handles: Vec<button::State>,
+        #[serde(skip)]
+        confirm_handle: button::State,
     },
     Audio {
         source: String,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        while_playing: Option<String>,
+        #[serde(rename="loop", default, skip_serializing_if="std::ops::Not::not")]
+        looping: bool,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        repeats: Option<u32>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        volume: Option<f32>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        trigger: Option<bool>,
         #[serde(default, flatten)]
         info: Info,
+        #[serde(skip)]
+        playback: Option<Playback>,
     },
     Image {
         source: String,
+        #[serde(default)]
+        fit: Fit,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        max_width: Option<u32>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        max_height: Option<u32>,
         #[serde(default, flatten)]
         info: Info,
         #[serde(skip)]
         handle: Option<image::Handle>,
+        #[serde(skip)]
+        size: Option<(u32, u32)>,
+    },
+    Fixation {
+        #[serde(default="default::fixation_size")]
+        size: u16,
+        #[serde(default="default::fixation_line_width")]
+        line_width: u16,
+        #[serde(default="default::fixation_color")]
+        color: String,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        handle: Option<svg::Handle>,
+    },
+    #[serde(rename = "rsvp")]
+    Rsvp {
+        sources: Vec<String>,
+        rate_hz: f32,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        handles: Vec<image::Handle>,
+        #[serde(skip)]
+        frame: usize,
+        #[serde(skip)]
+        frame_onsets: Vec<Duration>,
     },
     Question {
         list: Vec<Question>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        per_page: Option<usize>,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        handle: button::State,
+        #[serde(skip)]
+        back_handle: button::State,
+        #[serde(skip)]
+        page: usize,
+        #[serde(skip)]
+        incomplete: bool,
+    },
+    EndBlock {
+        #[serde(default, skip_serializing_if="String::is_empty")]
+        reason: String,
+        #[serde(default, flatten)]
+        info: Info,
+    },
+    SerialTrigger {
+        port: String,
+        #[serde(default="default::baud")]
+        baud: u32,
+        code: u8,
         #[serde(default, flatten)]
         info: Info,
+    },
+    WaitTrigger {
+        #[serde(default="default::wait_prompt")]
+        prompt: String,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        key: Option<String>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        serial: Option<SerialInput>,
+        #[serde(default="default::pulse_count")]
+        count: u32,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        pulses: Arc<Mutex<Vec<String>>>,
+    },
+    Break {
+        #[serde(default="default::break_prompt")]
+        prompt: String,
+        #[serde(default, deserialize_with="deserialize::duration", serialize_with="serialize::duration")]
+        min: Duration,
+        #[serde(default, deserialize_with="deserialize::opt_duration", serialize_with="serialize::opt_duration", skip_serializing_if="Option::is_none")]
+        max: Option<Duration>,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        elapsed: Duration,
+        #[serde(skip)]
+        continued: bool,
         #[serde(skip)]
         handle: button::State,
     },
-    // AudioSequence { .. },
-    // ImageSequence { .. },
-    // QuestionSequence { .. },
+    // Video { .. }, // Needs a video decoder and widget, neither of which iced 0.3 provides
+    AudioSequence {
+        sources: Vec<String>,
+        #[serde(default)]
+        isi: Interval,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        volume: Option<f32>,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        clip_durations: Vec<Duration>,
+        #[serde(skip)]
+        intervals: Vec<Duration>,
+        #[serde(skip)]
+        playback: Option<Playback>,
+    },
+    ImageSequence {
+        sources: Vec<String>,
+        #[serde(default, skip_serializing_if="Option::is_none", deserialize_with="deserialize::opt_duration", serialize_with="serialize::opt_duration")]
+        frame_duration: Option<Duration>,
+        #[serde(default, skip_serializing_if="Vec::is_empty", deserialize_with="deserialize::durations", serialize_with="serialize::durations")]
+        durations: Vec<Duration>,
+        #[serde(default, rename="loop", skip_serializing_if="std::ops::Not::not")]
+        looping: bool,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        handles: Vec<image::Handle>,
+        #[serde(skip)]
+        frame: usize,
+        #[serde(skip)]
+        frame_onsets: Vec<Duration>,
+    },
+    QuestionSequence {
+        list: Vec<Question>,
+        #[serde(default, flatten)]
+        info: Info,
+    },
     Template {
         source: String,
         #[serde(default)]
         params: HashMap<String, String>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        repeat: Option<u32>,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        shuffle: bool,
+        #[serde(default, flatten)]
+        info: Info,
+        #[serde(skip)]
+        actions: Vec<Action>,
+    },
+    FromCsv {
+        file: String,
+        template: String,
+        #[serde(default, skip_serializing_if="HashMap::is_empty")]
+        params: HashMap<String, String>,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        shuffle: bool,
         #[serde(default, flatten)]
         info: Info,
         #[serde(skip)]
@@ -120,18 +346,93 @@ pub enum Question {
     SingleChoice {
         prompt: String,
         options: Vec<String>,
+        #[serde(default)]
+        required: bool,
+        #[serde(default)]
+        show_if: Option<ShowIf>,
+        #[serde(default)]
+        shuffle_options: bool,
         #[serde(skip_deserializing)]
         answer: Option<usize>,
+        #[serde(skip_deserializing)]
+        option_order: Vec<usize>,
     },
     #[serde(serialize_with="serialize::question::multi_choice")]
     MultiChoice {
         prompt: String,
         options: Vec<String>,
+        #[serde(default)]
+        required: bool,
+        #[serde(default)]
+        min_selections: Option<usize>,
+        #[serde(default)]
+        max_selections: Option<usize>,
+        #[serde(default)]
+        none_option: Option<String>,
+        #[serde(default)]
+        show_if: Option<ShowIf>,
+        #[serde(default)]
+        shuffle_options: bool,
         #[serde(skip_deserializing)]
         answer: Vec<bool>,
+        #[serde(skip_deserializing)]
+        option_order: Vec<usize>,
+    },
+    Dropdown {
+        prompt: String,
+        options: Vec<String>,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        required: bool,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        show_if: Option<ShowIf>,
+        #[serde(skip_deserializing)]
+        answer: Option<String>,
+        #[serde(skip)]
+        handle: pick_list::State<String>,
+    },
+    #[serde(serialize_with="serialize::question::matrix")]
+    Matrix {
+        prompt: String,
+        rows: Vec<String>,
+        columns: Vec<String>,
+        #[serde(default)]
+        required: bool,
+        #[serde(default)]
+        show_if: Option<ShowIf>,
+        #[serde(skip_deserializing)]
+        answer: Vec<Option<usize>>,
+    },
+    Number {
+        prompt: String,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        min: Option<f64>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        max: Option<f64>,
+        #[serde(default="default::number_step")]
+        step: f64,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        required: bool,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        show_if: Option<ShowIf>,
+        #[serde(skip_deserializing, serialize_with="serialize::number")]
+        answer: Option<f64>,
+        #[serde(skip)]
+        text: String,
+        #[serde(skip)]
+        handle: text_input::State,
+        #[serde(skip)]
+        down_handle: button::State,
+        #[serde(skip)]
+        up_handle: button::State,
     },
     ShortAnswer {
         prompt: String,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        required: bool,
+        #[serde(default, skip_serializing_if="Validation::is_empty")]
+        validation: Validation,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        show_if: Option<ShowIf>,
         #[serde(skip_deserializing)]
         answer: String,
         #[serde(skip)]
@@ -143,233 +444,1232 @@ pub enum Question {
         range: RangeInclusive<f32>,
         #[serde(default="default::slider_step")]
         step: f32,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        required: bool,
+        #[serde(default, skip_serializing_if="std::ops::Not::not")]
+        show_value: bool,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        ticks: Option<f32>,
+        #[serde(default, skip_serializing_if="Vec::is_empty")]
+        labels: Vec<(f32, String)>,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        show_if: Option<ShowIf>,
         #[serde(skip_deserializing)]
         answer: f32,
         #[serde(skip)]
+        moved: bool,
+        #[serde(skip)]
         handle: slider::State,
     },
 }
 
 impl Question {
-    pub fn init(&mut self) {
+    pub fn init(&mut self) -> Result<(), Error> {
         match self {
-            MultiChoice { options, answer, .. } => {
-                *answer = vec![false; options.len()];
+            MultiChoice { prompt, options, min_selections, max_selections, none_option, answer, .. } => {
+                let (min, max) = (min_selections.unwrap_or(0), max_selections.unwrap_or(options.len()));
+                if min > max || max == 0 || max > options.len() {
+                    return Err(Error::Invalid(format!(
+                        "Invalid selection limits for question \"{}\": {} to {} of {} options", prompt, min, max, options.len())));
+                }
+                // The explicit "none" option is checked last, after the given options
+                *answer = vec![false; options.len() + none_option.is_some() as usize];
             }
-            Slider { answer, range, .. } => {
+            Matrix { prompt, rows, columns, answer, .. } => {
+                if rows.is_empty() || columns.is_empty() {
+                    return Err(Error::Invalid(format!("Matrix question \"{}\" needs both rows and columns", prompt)));
+                }
+                *answer = vec![None; rows.len()];
+            }
+            Number { prompt, min, max, step, .. }
+            if *step <= 0.0 || min.zip(*max).is_some_and(|(min, max)| min > max) => {
+                return Err(Error::Invalid(format!("Invalid range or step for number question \"{}\"", prompt)));
+            }
+            ShortAnswer { prompt, validation, .. } => {
+                validation.init()
+                    .map_err(|e| Error::Invalid(format!("Invalid validation of question \"{}\": {}", prompt, e)))?;
+            }
+            Slider { prompt, answer, range, ticks, labels, .. } => {
+                if let Some(interval) = ticks {
+                    if *interval <= 0.0 {
+                        return Err(Error::Invalid(format!("Invalid tick interval for slider \"{}\": {}", prompt, interval)));
+                    }
+                }
+                if let Some((x, _)) = labels.iter().find(|(x, _)| !range.contains(x)) {
+                    return Err(Error::Invalid(format!("Label of slider \"{}\" is out of range: {}", prompt, x)));
+                }
+                labels.sort_by(|a, b| a.0.total_cmp(&b.0));
                 *answer = *range.start();
             }
             _ => ()
         }
+        Ok(())
+    }
+
+    pub fn show_if(&self) -> Option<&ShowIf> {
+        match self {
+            SingleChoice { show_if, .. } |
+            MultiChoice { show_if, .. } |
+            Dropdown { show_if, .. } |
+            Matrix { show_if, .. } |
+            Number { show_if, .. } |
+            ShortAnswer { show_if, .. } |
+            Slider { show_if, .. } => show_if.as_ref()
+        }
     }
 
-    pub fn update(&mut self, value: Value) {
-        match (self, value) {
-            (SingleChoice { answer, .. }, Value::Integer(i)) => {
-                *answer = Some(i as usize);
+    /// Whether the question has the given answer (or, for a multiple choice question, has it
+    /// among the selected options).
+    fn has_answer(&self, value: &str) -> bool {
+        match self {
+            SingleChoice { options, answer, .. } => answer.is_some_and(|i| options[i] == value),
+            MultiChoice { options, none_option, answer, .. } => {
+                options.iter().chain(none_option).zip(answer).any(|(option, &checked)| checked && option == value)
             }
-            (MultiChoice { answer, .. }, Value::Integer(i)) => {
-                answer[i as usize] = !answer[i as usize];
+            Dropdown { answer, .. } => answer.as_deref() == Some(value),
+            Matrix { .. } => self.answer().as_deref() == Some(value),
+            Number { answer, .. } => answer.is_some() && value.parse() == Ok(answer.unwrap()),
+            ShortAnswer { answer, .. } => answer.trim() == value,
+            Slider { answer, moved, .. } => *moved && value.parse() == Ok(*answer),
+        }
+    }
+
+    /// Draws a new display order of the options, if they are shuffled, and returns it. An explicit
+    /// "none" option of a multiple choice question stays last.
+    fn shuffle_options(&mut self, rng: &mut StdRng) -> Option<Vec<usize>> {
+        match self {
+            SingleChoice { shuffle_options: true, options, option_order, .. } => {
+                *option_order = flow::order(options.len(), true, rng);
+                Some(option_order.clone())
             }
-            (ShortAnswer { answer, .. }, Value::String(s)) => {
-                *answer = s;
+            MultiChoice { shuffle_options: true, options, answer, option_order, .. } => {
+                *option_order = flow::order(options.len(), true, rng);
+                option_order.extend(options.len()..answer.len());
+                Some(option_order.clone())
             }
-            (Slider { answer, .. }, Value::Float(f)) => {
-                *answer = f;
+            _ => None,
+        }
+    }
+
+    /// Resets the answer, e.g. when the question is hidden by a changed answer it depends on.
+    fn clear(&mut self) {
+        match self {
+            SingleChoice { answer, .. } => *answer = None,
+            MultiChoice { answer, .. } => answer.iter_mut().for_each(|checked| *checked = false),
+            Dropdown { answer, .. } => *answer = None,
+            Matrix { answer, .. } => answer.iter_mut().for_each(|column| *column = None),
+            Number { answer, text, .. } => {
+                *answer = None;
+                text.clear();
+            }
+            ShortAnswer { answer, .. } => answer.clear(),
+            Slider { answer, range, moved, .. } => {
+                *answer = *range.start();
+                *moved = false;
             }
-            _ => panic!("Invalid answer value type")
         }
     }
-}
 
-impl Action {
-    pub fn init(
-        &mut self,
-        position: usize,
-        last_action: &Option<ID>,
-        depth: u16,
-        task_dir: &Path
-    ) -> Result<(), String> {
-        if depth > MAX_DEPTH {
-            return Err(format!("Maximum allowed template depth reached: {}.", MAX_DEPTH));
+    /// Whether the explicit "none" option of a multiple choice question is checked.
+    fn is_none(&self) -> bool {
+        match self {
+            MultiChoice { none_option: Some(_), answer, .. } => answer.last() == Some(&true),
+            _ => false,
         }
-        let info = self.info_mut();
-        if info.id.is_empty() {
-            info.id = position.to_string();
-        } else if !info.id.chars().all(|c| c.is_ascii_alphanumeric() || "_-".contains(c)) {
-            return Err("Only alphanumeric (a-z|A-Z|0-9), '-', and '_' are allowed in actions IDs.".to_string());
-        } else if info.id.chars().all(char::is_numeric) {
-            return Err("Custom action ID cannot be digits only.".to_string());
-        } else if info.id == "entry" || info.id == "exit" {
-            return Err("`entry` and `exit` are reserved action IDs.".to_string());
+    }
+
+    /// Hint on the number of options to select, or on the expected short answer, if the current
+    /// answer is not valid.
+    pub fn hint(&self) -> Option<String> {
+        if self.is_none() {
+            return None;
         }
-        match (&info.after, &info.with) {
-            (None, None) => {
-                if let Some(last_id) = last_action {
-                    info.after = Some(HashSet::from([last_id.clone()]));
-                } else {
-                    info.after = Some(HashSet::new());
+        match self {
+            ShortAnswer { validation, answer, .. } => validation.check(answer).err(),
+            Number { text, answer: None, .. } if !text.trim().is_empty() => Some(self.number_hint()),
+            MultiChoice { min_selections, max_selections, answer, .. } => {
+                let count = answer.iter().filter(|&&checked| checked).count();
+                match (*min_selections, *max_selections) {
+                    (Some(min), Some(max)) if count < min || count > max => {
+                        Some(format!("Select between {} and {} options", min, max))
+                    }
+                    (Some(min), _) if count < min => Some(format!("Select at least {} options", min)),
+                    (_, Some(max)) if count > max => Some(format!("Select at most {} options", max)),
+                    _ => None,
                 }
             }
-            _ => (),
+            _ => None,
         }
-        if let Some(file) = &info.background {
-            let file = resource(task_dir, file)?;
-            info.background_image = Some(image::Handle::from_path(file));
+    }
+
+    /// Hint on the expected number, for an answer that is not a number within range.
+    fn number_hint(&self) -> String {
+        match self {
+            Number { min: Some(min), max: Some(max), .. } => format!("Enter a number between {} and {}", min, max),
+            Number { min: Some(min), .. } => format!("Enter a number of at least {}", min),
+            Number { max: Some(max), .. } => format!("Enter a number of at most {}", max),
+            _ => "Enter a number".to_string(),
         }
-        if let Some(0) = info.timeout {
-            info.expired = Some(true);
+    }
+
+    pub fn prompt(&self) -> &str {
+        match self {
+            SingleChoice { prompt, .. } |
+            MultiChoice { prompt, .. } |
+            Dropdown { prompt, .. } |
+            Matrix { prompt, .. } |
+            Number { prompt, .. } |
+            ShortAnswer { prompt, .. } |
+            Slider { prompt, .. } => prompt
         }
+    }
 
+    pub fn prompt_mut(&mut self) -> &mut String {
         match self {
-            Action::Nothing { info, .. } => {
-                if info.timeout.is_none() {
-                    info.timeout = Some(0);
-                }
-            }
-            Action::Instruction { timer, handle, .. } => {
-                *handle = if *timer == 0 {
-                    Some(button::State::new())
-                } else {
-                    None
-                };
+            SingleChoice { prompt, .. } |
+            MultiChoice { prompt, .. } |
+            Dropdown { prompt, .. } |
+            Matrix { prompt, .. } |
+            Number { prompt, .. } |
+            ShortAnswer { prompt, .. } |
+            Slider { prompt, .. } => prompt
+        }
+    }
+
+    pub fn answer(&self) -> Option<String> {
+        match self {
+            SingleChoice { options, answer, .. } => {
+                answer.map(|i| options[i].clone())
             }
-            Action::Selection { options, handles, .. } => {
-                *handles = vec![button::State::new(); options.len()];
+            MultiChoice { options, none_option, answer, .. } => {
+                Some(options.iter()
+                    .chain(none_option)
+                    .zip(answer)
+                    .filter(|(_, &checked)| checked)
+                    .map(|(o, _)| o.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "))
             }
-            Action::Audio { .. } => {
-                ()
+            Dropdown { answer, .. } => answer.clone(),
+            Number { answer, .. } => answer.map(|x| x.to_string()),
+            // Column numbers (from 1) of the rows, e.g. the scores on a Likert scale
+            Matrix { answer, .. } => {
+                Some(answer.iter()
+                    .map(|column| column.map_or("~".to_string(), |j| (j + 1).to_string()))
+                    .collect::<Vec<_>>()
+                    .join(", "))
             }
-            Action::Image { handle, source, .. } => {
-                let source = resource(task_dir, source)?;
-                *handle = Some(image::Handle::from_path(source));
+            ShortAnswer { answer, .. } => Some(answer.clone()),
+            Slider { answer, .. } => Some(answer.to_string()),
+        }
+    }
+
+    /// Sets the answer from its textual form (as written to the response file).
+    pub fn set_answer(&mut self, value: &str) -> Result<(), Error> {
+        let error = format!("Invalid answer to question \"{}\": {}", self.prompt(), value);
+        let invalid = || Error::Invalid(error.clone());
+        match self {
+            SingleChoice { options, answer, .. } => {
+                *answer = Some(options.iter().position(|o| o == value).ok_or_else(invalid)?);
             }
-            Action::Question { list, .. } => {
-                for quest in list {
-                    quest.init();
+            MultiChoice { options, none_option, answer, .. } => {
+                for item in value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                    let i = options.iter().chain(none_option.iter()).position(|o| o == item).ok_or_else(invalid)?;
+                    answer[i] = true;
                 }
             }
-            Action::Template {
-                source,
-                params,
-                actions,
-                info,
-                ..
-            } => {
-                let file = template(task_dir, source)?;
-                let mut file = File::open(file)
-                    .or(Err(format!("Failed to open template file: {:?}", source)))?;
-
-                let mut content = String::new();
-                file.read_to_string(&mut content)
-                    .or(Err(format!("Invalid UTF-8 text in template file: {:?}", source)))?;
-
-                for (k, v) in params {
-                    let k = format!("{{{{{}}}}}", k);
-                    if !content.contains(&k) {
-                        return Err(format!("Invalid template parameter \"{}\" specified for template file: {:?}", k, source));
-                    }
-                    content = content.replace(&k, v);
+            Dropdown { options, answer, .. } => {
+                if !options.iter().any(|o| o == value) {
+                    return Err(invalid());
                 }
-                if content.contains("{{") {
-                    return Err("All parameters in a template should have specified values".to_string());
+                *answer = Some(value.to_string());
+            }
+            Matrix { columns, answer, .. } => {
+                let values: Vec<_> = value.split(',').map(str::trim).collect();
+                if values.len() != answer.len() {
+                    return Err(invalid());
                 }
-
-                *actions = serde_yaml::from_str(&content).or_else(|e|
-                    Err(format!("Failed to parse template \"{}\" at line {}: {}",
-                                source, e.location().unwrap().line(), e)))?;
-
-                let mut last_action = None;
-                let mut ids = HashSet::new();
-                for (i, action) in actions.iter_mut().enumerate() {
-                    action.init(i+1, &last_action, 1+depth, task_dir)?;
-                    last_action = Some(action.id());
-
-                    let id = action.id();
-                    if ids.contains(&id) {
-                        return Err(format!("Action ID `{}` used more than once in template: {}", id, source));
-                    } else {
-                        ids.insert(id);
-                    }
+                for (column, value) in answer.iter_mut().zip(values) {
+                    *column = match value {
+                        "~" => None,
+                        _ => Some(value.parse::<usize>().ok()
+                            .filter(|j| (1..=columns.len()).contains(j))
+                            .ok_or_else(invalid)? - 1),
+                    };
                 }
-
-                let mut i: usize = 0;
-                while i < actions.len() {
-                    if matches!(actions[i], Action::Template { .. }) {
-                        if let Action::Template { actions: inners, .. } = actions[i].clone() {
-                            actions.remove(i);
-                            for inner in inners.into_iter() {
-                                actions.insert(i, inner);
-                                i += 1;
-                            }
-                        }
-                    } else {
-                        i += 1;
-                    }
+            }
+            Number { .. } => {
+                self.update(Value::String(value.to_string())).map_err(Error::Invalid)?;
+                if let Number { answer: None, .. } = self {
+                    return Err(invalid());
                 }
-
-                for action in actions.iter_mut() {
-                    let inner_info = action.info_mut();
-                    inner_info.id = format!("{}~{}", info.id, inner_info.id);
-                    if let Some(after) = &mut inner_info.after {
-                        *after = after.iter().map(|x| format!("{}~{}", info.id, x)).collect();
-                        if let Some(ids) = &info.after {
-                            after.extend(ids.clone());
-                        }
-                    } else {
-                        info.after = info.after.clone();
-                    }
-                    if let Some(id) = &info.with {
-                        info.with = Some(format!("{}~{}", info.id, id));
-                    } else {
-                        info.with = info.with.clone();
-                    }
+            }
+            ShortAnswer { answer, .. } => {
+                *answer = value.to_string();
+            }
+            Slider { answer, range, moved, .. } => {
+                let x: f32 = value.parse().map_err(|_| invalid())?;
+                if !range.contains(&x) {
+                    return Err(invalid());
                 }
-
-                flow::add_gates(actions, info.after.clone(), info.with.clone())?;
-
-                let len = actions.len();
-                actions[0].set_id(&format!("{}~entry", info.id));
-                actions[len-1].set_id(&format!("{}~exit", info.id));
+                *answer = x;
+                *moved = true;
             }
         }
-
         Ok(())
     }
 
-    pub fn id(&self) -> ID {
-        self.info().id.clone()
-    }
-
-    pub fn set_id(&mut self, id: &ID) {
-        self.info_mut().id = id.clone();
-    }
-
-    pub fn is(&self, id: &str) -> bool {
-        self.id() == id
+    /// A random valid answer (in its textual form), for simulated sessions.
+    pub fn random_answer(&self, rng: &mut StdRng) -> String {
+        match self {
+            SingleChoice { options, .. } => {
+                options.choose(rng).cloned().unwrap_or_default()
+            }
+            MultiChoice { options, required, min_selections, max_selections, .. } => {
+                let n = options.len();
+                let max = max_selections.unwrap_or(n).min(n);
+                let min = min_selections.unwrap_or(*required as usize).min(max);
+                let count = rng.gen_range(min..=max);
+                let mut picked = rand::seq::index::sample(rng, n, count).into_vec();
+                picked.sort_unstable();
+                picked.into_iter()
+                    .map(|i| options[i].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            Dropdown { options, .. } => {
+                options.choose(rng).cloned().unwrap_or_default()
+            }
+            Matrix { rows, columns, .. } => {
+                (0..rows.len())
+                    .map(|_| rng.gen_range(1..=columns.len()).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            Number { min, max, step, .. } => {
+                // Without a minimum, answers start at 0, or below the maximum if it is negative
+                let min = min.unwrap_or_else(|| max.filter(|&max| max < 0.0).map_or(0.0, |max| max - 100.0));
+                let steps = ((max.unwrap_or(min + 100.0) - min) / *step).floor() as u32;
+                round_to_step(min + *step * rng.gen_range(0..=steps) as f64, *step).to_string()
+            }
+            ShortAnswer { validation, .. } => validation.random_answer(rng),
+            Slider { range, step, .. } => {
+                let steps = ((range.end() - range.start()) / step).floor() as u32;
+                (range.start() + step * rng.gen_range(0..=steps) as f32).to_string()
+            }
+        }
     }
 
-    pub fn info(&self) -> &Info {
+    /// Whether the question is answered, if it requires an answer. A required slider counts as
+    /// answered once it is moved from its initial position.
+    pub fn is_complete(&self) -> bool {
         match self {
-            Action::Nothing { info, .. } |
-            Action::Instruction { info, .. } |
-            Action::Selection { info, .. } |
-            Action::Audio { info, .. } |
-            Action::Image { info, .. } |
-            Action::Question { info, .. } |
-            Action::Template { info, .. } => info
+            SingleChoice { required: true, answer, .. } => answer.is_some(),
+            _ if self.hint().is_some() => false,
+            MultiChoice { required: true, answer, .. } => answer.contains(&true),
+            Dropdown { required: true, answer, .. } => answer.is_some(),
+            Matrix { required: true, answer, .. } => answer.iter().all(Option::is_some),
+            Number { required: true, answer, .. } => answer.is_some(),
+            ShortAnswer { required: true, answer, .. } => !answer.trim().is_empty(),
+            Slider { required: true, moved, .. } => *moved,
+            _ => true,
         }
     }
 
-    pub fn info_mut(&mut self) -> &mut Info {
-        match self {
-            Action::Nothing { info, .. } |
+    pub fn update(&mut self, value: Value) -> Result<(), String> {
+        match (self, value) {
+            (SingleChoice { answer, .. }, Value::Integer(i)) => {
+                *answer = Some(i as usize);
+            }
+            (MultiChoice { answer, none_option, .. }, Value::Integer(i)) => {
+                let i = i as usize;
+                answer[i] = !answer[i];
+                // The "none" option excludes all others
+                if none_option.is_some() && answer[i] {
+                    let none = answer.len() - 1;
+                    if i == none {
+                        answer.iter_mut().take(none).for_each(|checked| *checked = false);
+                    } else {
+                        answer[none] = false;
+                    }
+                }
+            }
+            (Dropdown { answer, .. }, Value::String(s)) => {
+                *answer = Some(s);
+            }
+            // Typed text is kept as is, and the answer is only set while it is a number within range
+            (Number { min, max, answer, text, .. }, Value::String(s)) => {
+                *answer = s.trim().parse::<f64>().ok()
+                    .filter(|x| x.is_finite())
+                    .filter(|x| min.is_none_or(|min| *x >= min) && max.is_none_or(|max| *x <= max));
+                *text = s;
+            }
+            // The spinner buttons step the answer up or down (from the minimum, or zero), within range
+            (Number { min, max, step, answer, text, .. }, Value::Integer(direction)) => {
+                let x = match *answer {
+                    Some(x) => x + *step * direction as f64,
+                    None => min.unwrap_or(0.0).max(max.unwrap_or(f64::INFINITY).min(0.0)),
+                };
+                let x = round_to_step(x.clamp(min.unwrap_or(f64::MIN), max.unwrap_or(f64::MAX)), *step);
+                *answer = Some(x);
+                *text = x.to_string();
+            }
+            (Matrix { columns, answer, .. }, Value::Integer(i)) => {
+                let (row, column) = (i as usize / columns.len(), i as usize % columns.len());
+                answer[row] = Some(column);
+            }
+            (ShortAnswer { answer, validation, .. }, Value::String(s)) => {
+                // Input beyond the maximum length is not accepted at all
+                if validation.max_length.is_none_or(|max| s.chars().count() <= max) {
+                    *answer = s;
+                }
+            }
+            (Slider { answer, moved, .. }, Value::Float(f)) => {
+                *answer = f;
+                *moved = true;
+            }
+            (_, value) => return Err(format!("Invalid answer value type: {:?}", value)),
+        }
+        Ok(())
+    }
+}
+
+/// Condition for showing a question: an earlier question of the same list (numbered from 1) has
+/// the given answer.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ShowIf {
+    question_index: usize,
+    answer: String,
+}
+
+/// Rounds a number to the decimal places of a step, so that stepping does not accumulate errors
+/// (e.g. 0.1 + 0.2 is shown as 0.3).
+fn round_to_step(x: f64, step: f64) -> f64 {
+    let decimals = step.to_string().split('.').nth(1).map_or(0, str::len) as i32;
+    let scale = 10f64.powi(decimals);
+    (x * scale).round() / scale
+}
+
+/// Which questions of a list are shown, given the answers so far. A question is hidden along
+/// with the question it depends on.
+pub fn shown_questions(list: &[Question]) -> Vec<bool> {
+    let mut shown: Vec<bool> = Vec::with_capacity(list.len());
+    for quest in list {
+        let visible = match quest.show_if() {
+            Some(ShowIf { question_index, answer }) => {
+                shown[question_index - 1] && list[question_index - 1].has_answer(answer)
+            }
+            None => true,
+        };
+        shown.push(visible);
+    }
+    shown
+}
+
+/// Clears the answers of hidden questions, so that only the shown ones are recorded.
+fn clear_hidden(list: &mut [Question]) {
+    let shown = shown_questions(list);
+    for (quest, shown) in list.iter_mut().zip(shown) {
+        if !shown {
+            quest.clear();
+        }
+    }
+}
+
+/// Constraints on a short answer, checked as it is typed. An empty answer is left to `required`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Validation {
+    /// Regular expression that the whole answer must match.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pattern: Option<String>,
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    integer: bool,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    min: Option<f64>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    max: Option<f64>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    max_length: Option<usize>,
+    /// Shown instead of the default message when the answer is not valid.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    message: Option<String>,
+    #[serde(skip)]
+    regex: Option<regex::Regex>,
+}
+
+impl Validation {
+    fn is_empty(&self) -> bool {
+        self.pattern.is_none() && !self.integer && self.min.is_none() && self.max.is_none()
+            && self.max_length.is_none()
+    }
+
+    fn init(&mut self) -> Result<(), String> {
+        if let Some(pattern) = &self.pattern {
+            let regex = regex::Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| format!("invalid pattern: {}", e))?;
+            self.regex = Some(regex);
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                return Err(format!("empty range: {} to {}", min, max));
+            }
+            if self.integer && min.ceil() > max.floor() {
+                return Err(format!("no whole number between {} and {}", min, max));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_numeric(&self) -> bool {
+        self.integer || self.min.is_some() || self.max.is_some()
+    }
+
+    /// Checks an answer, with a message for the participant if it is not valid.
+    fn check(&self, answer: &str) -> Result<(), String> {
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(());
+        }
+        let error = |default: String| Err(self.message.clone().unwrap_or(default));
+        if let Some(max) = self.max_length {
+            if answer.chars().count() > max {
+                return error(format!("Enter at most {} characters", max));
+            }
+        }
+        if self.is_numeric() {
+            let number = if self.integer {
+                answer.parse::<i64>().map(|n| n as f64).ok()
+            } else {
+                answer.parse::<f64>().ok().filter(|n| n.is_finite())
+            };
+            let number = match number {
+                Some(number) => number,
+                None if self.integer => return error("Enter a whole number".to_string()),
+                None => return error("Enter a number".to_string()),
+            };
+            match (self.min, self.max) {
+                (Some(min), Some(max)) if number < min || number > max => {
+                    return error(format!("Enter a number between {} and {}", min, max));
+                }
+                (Some(min), _) if number < min => return error(format!("Enter a number of at least {}", min)),
+                (_, Some(max)) if number > max => return error(format!("Enter a number of at most {}", max)),
+                _ => (),
+            }
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(answer) {
+                return error("Answer is not in the expected format".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// A random answer for simulated sessions, valid unless it has to match a pattern.
+    fn random_answer(&self, rng: &mut StdRng) -> String {
+        if !self.is_numeric() {
+            return "simulated".to_string();
+        }
+        let (min, max) = match (self.min, self.max) {
+            (Some(min), Some(max)) => (min, max),
+            (Some(min), None) => (min, min.max(0.0) + 100.0),
+            (None, Some(max)) if max < 0.0 => (max - 100.0, max),
+            (None, max) => (0.0, max.unwrap_or(100.0)),
+        };
+        if self.integer {
+            rng.gen_range(min.ceil() as i64..=max.floor() as i64).to_string()
+        } else {
+            rng.gen_range(min..=max).to_string()
+        }
+    }
+}
+
+/// An option of a selection, either a plain label or a label with the feedback shown and the
+/// outcome value recorded when it is chosen.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SelectionOption {
+    Label(String),
+    Detailed {
+        label: String,
+        #[serde(default, skip_serializing_if="Option::is_none")]
+        feedback: Option<String>,
+        #[serde(default, alias="weight", skip_serializing_if="Option::is_none")]
+        value: Option<f32>,
+    },
+}
+
+impl SelectionOption {
+    pub fn label(&self) -> &String {
+        match self {
+            SelectionOption::Label(label) |
+            SelectionOption::Detailed { label, .. } => label,
+        }
+    }
+
+    pub fn feedback(&self) -> Option<&String> {
+        match self {
+            SelectionOption::Label(_) => None,
+            SelectionOption::Detailed { feedback, .. } => feedback.as_ref(),
+        }
+    }
+
+    pub fn value(&self) -> Option<f32> {
+        match self {
+            SelectionOption::Label(_) => None,
+            SelectionOption::Detailed { value, .. } => *value,
+        }
+    }
+
+    fn texts_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            SelectionOption::Label(label) => vec![label],
+            SelectionOption::Detailed { label, feedback, .. } => {
+                let mut texts = vec![label];
+                texts.extend(feedback.iter_mut());
+                texts
+            }
+        }
+    }
+}
+
+/// The chosen option of a selection along with its outcome value.
+#[derive(Debug, Clone, Serialize)]
+struct Outcome {
+    choice: Option<usize>,
+    value: f32,
+}
+
+/// Reads images into memory up front, so that no disk access is needed in between frames.
+fn preload_images(
+    sources: &[String],
+    info: &mut Info,
+    handles: &mut Vec<image::Handle>,
+    task_dir: &Path,
+    config: &Config,
+) -> Result<(), Error> {
+    for source in sources {
+        match resource(task_dir, source) {
+            Ok(path) => {
+                let bytes = std::fs::read(&path)
+                    .map_err(|e| Error::io(format!("Failed to read image file: {:?}", path), e))?;
+                handles.push(image::Handle::from_memory(bytes));
+            }
+            Err(e) if config.skip_missing_resources() => {
+                info.mark_missing(e.to_string());
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Scheduled onset of a frame of an image sequence, relative to the onset of the first frame.
+/// Frames past the end of the sequence belong to later loops.
+fn sequence_offset(len: usize, frame_duration: Option<Duration>, durations: &[Duration], frame: usize) -> Duration {
+    let duration = |i: usize| frame_duration.unwrap_or_else(|| durations[i]);
+    let cycle: Duration = (0..len).map(duration).sum();
+    let partial: Duration = (0..frame % len).map(duration).sum();
+    cycle * (frame / len) as u32 + partial
+}
+
+/// An option highlighted in a selection that asks for confirmation, at a time from its onset.
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectionChange {
+    option: String,
+    time_ms: f64,
+}
+
+/// A key held down, from the time it was pressed to the time it was released (from onset).
+/// Either end is missing if it falls outside the action, e.g. a key held since the previous one.
+#[derive(Debug, Clone, Serialize)]
+struct KeyHold {
+    key: String,
+    press_ms: Option<f64>,
+    release_ms: Option<f64>,
+    duration_ms: Option<f64>,
+}
+
+/// A response tagged with whether it came faster than the action's `min_rt`.
+#[derive(Debug, Clone, Serialize)]
+struct Tagged<T> {
+    response: T,
+    anticipatory: bool,
+}
+
+/// Answers of a question list (or the choice of a selection) while the action is still running.
+#[derive(Debug, Clone, Serialize)]
+struct PartialAnswers<'a> {
+    time_ms: f64,
+    answers: &'a [Option<String>],
+}
+
+/// Position of the mouse cursor in the window at a time from the onset of the action.
+#[derive(Debug, Clone, Serialize)]
+struct CursorSample {
+    time_ms: f64,
+    x: f32,
+    y: f32,
+}
+
+/// Silent interval between consecutive clips of an audio sequence, either fixed or drawn
+/// uniformly (in whole milliseconds) from an inclusive range for each interval.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Interval {
+    Fixed(
+        #[serde(deserialize_with="deserialize::duration", serialize_with="serialize::duration")]
+        Duration
+    ),
+    Jittered(
+        #[serde(deserialize_with="deserialize::duration", serialize_with="serialize::duration")]
+        Duration,
+        #[serde(deserialize_with="deserialize::duration", serialize_with="serialize::duration")]
+        Duration
+    ),
+}
+
+impl Default for Interval {
+    fn default() -> Self {
+        Interval::Fixed(Duration::ZERO)
+    }
+}
+
+/// How an image is scaled into the content area, always keeping its aspect ratio:
+/// * `native`: at its size in pixels, scaled down only if it does not fit
+/// * `contain`: as large as fits
+/// * `cover`: cropped (around its center) to the shape of the content area, and filling it
+/// * `width: <px>`: at a fixed width in pixels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fit {
+    #[default]
+    Native,
+    Contain,
+    Cover,
+    Width(u16),
+}
+
+/// Reports a message that an action cannot handle, which indicates a bug rather than bad input.
+fn unexpected(id: &str, message: &Message) -> Command<Message> {
+    report(format!("Action `{}` received an unexpected message: {:?}", id, message))
+}
+
+/// Onset of a clip of an audio sequence relative to the start of playback, and the silent
+/// interval that followed it.
+#[derive(Debug, Clone, Serialize)]
+struct ClipOnset {
+    source: String,
+    onset_ms: f64,
+    isi_ms: Option<u32>,
+}
+
+/// How long a break lasted, and whether the subject ended it (rather than its `max`).
+#[derive(Debug, Clone, Serialize)]
+struct RestRecord {
+    duration_ms: f64,
+    min_ms: u32,
+    max_ms: Option<u32>,
+    continued: bool,
+}
+
+/// Outcome of sending a serial trigger, with the error if it could not be sent.
+#[derive(Debug, Clone, Serialize)]
+struct TriggerRecord {
+    port: String,
+    code: u8,
+    #[serde(skip_serializing_if="Option::is_none")]
+    error: Option<String>,
+}
+
+/// Serial port from which trigger pulses (e.g. scanner TRs) are received as a byte `code`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SerialInput {
+    port: String,
+    #[serde(default="default::baud")]
+    baud: u32,
+    code: u8,
+}
+
+/// Achieved presentation timing of a single frame of an RSVP sequence.
+#[derive(Debug, Clone, Serialize)]
+struct FrameTiming {
+    source: String,
+    onset_ms: f64,
+    interval_ms: Option<f64>,
+}
+
+impl Info {
+    /// Whether a response at the given time from onset is faster than `min_rt`.
+    fn is_anticipatory(&self, rt: Duration) -> bool {
+        matches!(self.min_rt, Some(min) if rt < min)
+    }
+
+    fn mark_missing(&mut self, reason: String) {
+        println!("Action `{}` will be skipped: {}", self.id, reason);
+        self.missing = Some(reason);
+        self.expired = Some(true);
+    }
+}
+
+/// Logs a received trigger pulse, and returns whether enough pulses have been received.
+fn record_pulse(pulses: &Mutex<Vec<String>>, time: String, source: &str, count: u32) -> bool {
+    let mut pulses = pulses.lock().unwrap();
+    if pulses.len() < count as usize {
+        pulses.push(format!("{}  {}", time, source));
+    }
+    pulses.len() >= count as usize
+}
+
+/// Whether a key is one of the accepted keys (by name, e.g. `Space`), or any key if unrestricted.
+fn accepts_key(accept_keys: &Option<Vec<String>>, key_code: KeyCode) -> bool {
+    match accept_keys {
+        Some(keys) => keys.iter().any(|k| *k == format!("{:?}", key_code)),
+        None => true,
+    }
+}
+
+impl Action {
+    pub fn init(
+        &mut self,
+        position: usize,
+        last_action: &Option<ID>,
+        depth: u16,
+        task_dir: &Path,
+        config: &Config,
+        rng: &mut StdRng,
+    ) -> Result<(), Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::Invalid(format!("Maximum allowed template depth reached: {}.", MAX_DEPTH)));
+        }
+        // A question sequence is a question list with one question per page
+        if let Action::QuestionSequence { list, info } = self {
+            *self = Action::Question {
+                list: std::mem::take(list),
+                per_page: Some(1),
+                info: std::mem::take(info),
+                handle: Default::default(),
+                back_handle: Default::default(),
+                page: 0,
+                incomplete: false,
+            };
+        }
+        let info = self.info_mut();
+        if info.id.is_empty() {
+            info.id = position.to_string();
+        } else if !info.id.chars().all(|c| c.is_ascii_alphanumeric() || "_-".contains(c)) {
+            return Err(Error::Invalid("Only alphanumeric (a-z|A-Z|0-9), '-', and '_' are allowed in actions IDs.".to_string()));
+        } else if info.id.chars().all(char::is_numeric) {
+            return Err(Error::Invalid("Custom action ID cannot be digits only.".to_string()));
+        } else if info.id == "entry" || info.id == "exit" {
+            return Err(Error::Invalid("`entry` and `exit` are reserved action IDs.".to_string()));
+        }
+        match (&info.after, &info.with) {
+            (None, None) => {
+                if let Some(last_id) = last_action {
+                    info.after = Some(HashSet::from([last_id.clone()]));
+                } else {
+                    info.after = Some(HashSet::new());
+                }
+            }
+            _ => (),
+        }
+        if let Some(file) = info.background.clone() {
+            match resource(task_dir, &file) {
+                Ok(file) => info.background_image = Some(image::Handle::from_path(file)),
+                Err(e) if config.skip_missing_resources() => info.mark_missing(e.to_string()),
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(color) = &info.background_color {
+            info.backdrop = Some(parse_color(color)
+                .map_err(|e| Error::Invalid(format!("Invalid background color of action `{}`: {}", info.id, e)))?);
+        }
+        if let Some(Duration::ZERO) = info.timeout {
+            info.expired = Some(true);
+        }
+        if let Some(style) = &info.style {
+            style.verify().map_err(Error::Invalid)?;
+        }
+        if let Some(keys) = &mut info.keys {
+            // Key names are matched ignoring case, and kept in the form they are reported in
+            for key in keys.iter_mut() {
+                let key_code = KeyChord::parse(key).ok()
+                    .and_then(|chord| chord.key_code())
+                    .ok_or_else(|| Error::Invalid(format!("Unknown key in `keys` of action `{}`: {}", info.id, key)))?;
+                *key = format!("{:?}", key_code);
+            }
+        } else if info.log_invalid_keys {
+            return Err(Error::Invalid(format!("`log_invalid_keys` of action `{}` needs a list of `keys`", info.id)));
+        }
+
+        match self {
+            Action::Nothing { info, .. } => {
+                if info.timeout.is_none() {
+                    info.timeout = Some(Duration::ZERO);
+                }
+            }
+            Action::EndBlock { info, .. } => {
+                if info.timeout.is_some() {
+                    return Err(Error::Invalid("`timeout` cannot be used with an end_block action".to_string()));
+                }
+            }
+            Action::SerialTrigger { port, baud, .. } => {
+                if port.is_empty() {
+                    return Err(Error::Invalid("Serial trigger needs a `port`".to_string()));
+                }
+                if !hardware::supports_baud(*baud) {
+                    return Err(Error::Invalid(format!("Unsupported baud rate for serial trigger: {}", baud)));
+                }
+            }
+            Action::WaitTrigger { key, serial, count, .. } => {
+                if key.is_none() && serial.is_none() {
+                    return Err(Error::Invalid("Wait trigger needs a `key` or a `serial` input to wait for".to_string()));
+                }
+                if *count == 0 {
+                    return Err(Error::Invalid("Wait trigger `count` should be at least 1".to_string()));
+                }
+                if let Some(serial) = serial {
+                    if serial.port.is_empty() {
+                        return Err(Error::Invalid("Wait trigger serial input needs a `port`".to_string()));
+                    }
+                    if !hardware::supports_baud(serial.baud) {
+                        return Err(Error::Invalid(format!("Unsupported baud rate for wait trigger: {}", serial.baud)));
+                    }
+                }
+            }
+            Action::Break { min, max: Some(max), .. } if *max < *min || max.is_zero() => {
+                return Err(Error::Invalid(format!("Break `max` ({:?}) should be positive and at least `min` ({:?})", max, min)));
+            }
+            Action::Break { .. } => (),
+            Action::Instruction { timer, handle, advance_on_key, accept_keys, .. } => {
+                if accept_keys.is_some() && !*advance_on_key {
+                    return Err(Error::Invalid("`accept_keys` can only be used with `advance_on_key`".to_string()));
+                }
+                *handle = if timer.is_zero() {
+                    Some(button::State::new())
+                } else {
+                    None
+                };
+            }
+            Action::Selection { options, handles, columns, button_width, orientation, .. } => {
+                Grid::new(*columns, *button_width, *orientation).verify().map_err(Error::Invalid)?;
+                *handles = vec![button::State::new(); options.len()];
+            }
+            Action::Audio { looping: true, repeats: Some(_), .. } => {
+                return Err(Error::Invalid("Audio cannot have both `loop` and `repeats`".to_string()));
+            }
+            Action::Audio { repeats: Some(0), .. } => {
+                return Err(Error::Invalid("Number of audio repeats should be positive".to_string()));
+            }
+            Action::Audio { volume: Some(volume), .. } |
+            Action::AudioSequence { volume: Some(volume), .. } if !(0.0..=1.0).contains(volume) => {
+                return Err(Error::Invalid(format!("Audio volume should be between 0 and 1: {}", volume)));
+            }
+            Action::Audio { looping: true, info, .. } if info.timeout.is_none() && info.with.is_none() => {
+                return Err(Error::Invalid("Looping audio needs a `timeout` or `with` to end".to_string()));
+            }
+            Action::Audio { source, .. } if flow::has_runtime_refs(source) => (),
+            Action::Image { source, .. } if flow::has_runtime_refs(source) => (),
+            Action::Audio { source, info, .. } => {
+                match resource(task_dir, source) {
+                    Ok(_) => (),
+                    Err(e) if config.skip_missing_resources() => info.mark_missing(e.to_string()),
+                    Err(e) => return Err(e),
+                }
+            }
+            Action::AudioSequence { sources, isi, info, clip_durations, .. } => {
+                if sources.is_empty() {
+                    return Err(Error::Invalid("Audio sequence should have at least one clip".to_string()));
+                }
+                if let Interval::Jittered(low, high) = isi {
+                    if low > high {
+                        return Err(Error::Invalid(format!("Invalid jittered interval for audio sequence: {:?} to {:?}", low, high)));
+                    }
+                }
+                for source in sources {
+                    match resource(task_dir, source) {
+                        Ok(path) => clip_durations.push(crate::sound::duration(&path).map_err(Error::Audio)?),
+                        Err(e) if config.skip_missing_resources() => {
+                            info.mark_missing(e.to_string());
+                            break;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Action::Image { handle, source, info, fit, size, max_width, max_height } => {
+                if *fit == Fit::Width(0) || *max_width == Some(0) || *max_height == Some(0) {
+                    return Err(Error::Invalid("Image width and maximum dimensions should be positive".to_string()));
+                }
+                match resource(task_dir, source) {
+                    Ok(source) => {
+                        *size = Some(::image::image_dimensions(&source)
+                            .map_err(|e| Error::Io(format!("Failed to read image {:?}: {}", source, e), None))?);
+                        *handle = Some(image::Handle::from_path(source));
+                    }
+                    Err(e) if config.skip_missing_resources() => info.mark_missing(e.to_string()),
+                    Err(e) => return Err(e),
+                }
+            }
+            Action::Fixation { size, line_width, color, handle, .. } => {
+                if *size == 0 || *line_width == 0 || line_width > size {
+                    return Err(Error::Invalid(format!("Invalid fixation cross size {} with line width {}", size, line_width)));
+                }
+                let color = parse_color(color).map_err(Error::Invalid)?;
+                *handle = Some(view::fixation_cross(*size, *line_width, color));
+            }
+            Action::Rsvp { sources, rate_hz, info, handles, .. } => {
+                if *rate_hz <= 0.0 {
+                    return Err(Error::Invalid(format!("RSVP frame rate should be positive: {}", rate_hz)));
+                }
+                if sources.is_empty() {
+                    return Err(Error::Invalid("RSVP action should have at least one frame".to_string()));
+                }
+                preload_images(sources, info, handles, task_dir, config)?;
+            }
+            Action::ImageSequence { sources, frame_duration, durations, looping, info, handles, .. } => {
+                if sources.is_empty() {
+                    return Err(Error::Invalid("Image sequence should have at least one frame".to_string()));
+                }
+                match (*frame_duration, durations.len()) {
+                    (Some(_), 0) => (),
+                    (None, n) if n == sources.len() => (),
+                    (None, 0) => return Err(Error::Invalid("Image sequence needs either `frame_duration` or `durations`".to_string())),
+                    (None, n) => return Err(Error::Invalid(format!("Image sequence has {} frames, but {} durations", sources.len(), n))),
+                    (Some(_), _) => return Err(Error::Invalid("Image sequence cannot have both `frame_duration` and `durations`".to_string())),
+                }
+                if *frame_duration == Some(Duration::ZERO) || durations.contains(&Duration::ZERO) {
+                    return Err(Error::Invalid("Image sequence frame durations should be positive".to_string()));
+                }
+                if *looping && info.timeout.is_none() && info.with.is_none() {
+                    return Err(Error::Invalid("A looping image sequence needs a `timeout` or `with` to end".to_string()));
+                }
+                preload_images(sources, info, handles, task_dir, config)?;
+            }
+            Action::QuestionSequence { .. } => {
+                // Question sequences are converted to question lists before they are initialized
+                return Err(Error::Invalid(format!("Question sequence `{}` was not converted to a question list", self.id())));
+            }
+            Action::Question { list, per_page, .. } => {
+                if let Some(0) = per_page {
+                    return Err(Error::Invalid("Number of questions per page should be positive".to_string()));
+                }
+                for quest in list.iter_mut() {
+                    quest.init()?;
+                }
+                for (i, quest) in list.iter().enumerate() {
+                    if let Some(ShowIf { question_index, answer }) = quest.show_if() {
+                        if *question_index == 0 || *question_index > i {
+                            return Err(Error::Invalid(format!(
+                                "Question \"{}\" can only depend on an earlier question (numbered from 1), not {}",
+                                quest.prompt(), question_index)));
+                        }
+                        let mut options = match &list[question_index - 1] {
+                            SingleChoice { options, .. } |
+                            Dropdown { options, .. } => options.iter().chain(&None),
+                            MultiChoice { options, none_option, .. } => options.iter().chain(none_option),
+                            _ => continue,
+                        };
+                        if !options.any(|option| option == answer) {
+                            return Err(Error::Invalid(format!(
+                                "Question \"{}\" depends on answer \"{}\", which is not an option of question {}",
+                                quest.prompt(), answer, question_index)));
+                        }
+                    }
+                }
+            }
+            Action::Template { repeat: Some(0), .. } => {
+                return Err(Error::Invalid("Number of template repetitions should be positive".to_string()));
+            }
+            Action::Template {
+                source,
+                params,
+                repeat: Some(n),
+                shuffle,
+                actions,
+                info,
+            } if *n > 1 => {
+                // Each repetition is a template of its own, so that inner IDs stay distinct
+                *actions = (0..*n)
+                    .map(|_| Action::Template {
+                        source: source.clone(),
+                        params: params.clone(),
+                        repeat: None,
+                        shuffle: *shuffle,
+                        info: Info::default(),
+                        actions: vec![],
+                    })
+                    .collect();
+
+                flow::expand(actions, info, depth, task_dir, config, source, false, rng)?;
+            }
+            Action::Template {
+                source,
+                params,
+                shuffle,
+                actions,
+                info,
+                ..
+            } => {
+                let mut content = flow::read_template(task_dir, source)?;
+                for (k, v) in params {
+                    let k = format!("{{{{{}}}}}", k);
+                    if !content.contains(&k) {
+                        return Err(Error::Invalid(format!("Invalid template parameter \"{}\" specified for template file: {:?}", k, source)));
+                    }
+                    content = content.replace(&k, v);
+                }
+                if !flow::placeholders(&content).is_empty() {
+                    return Err(Error::Invalid("All parameters in a template should have specified values".to_string()));
+                }
+
+                *actions = serde_yaml::from_str(&content).or_else(|e|
+                    Err(Error::Yaml(format!("Failed to parse template \"{}\" at line {}: {}",
+                                source, e.location().unwrap().line(), e))))?;
+
+                flow::expand(actions, info, depth, task_dir, config, source, *shuffle, rng)?;
+            }
+            Action::FromCsv {
+                file,
+                template,
+                params,
+                shuffle,
+                actions,
+                info,
+            } => {
+                let content = flow::read_template(task_dir, template)?;
+                let placeholders = flow::placeholders(&content);
+                let (columns, rows) = flow::read_csv(task_dir, file)?;
+                for column in &columns {
+                    if !placeholders.contains(column) {
+                        return Err(Error::Invalid(format!("CSV column \"{}\" in {:?} is not a parameter of template: {:?}", column, file, template)));
+                    }
+                }
+                for k in params.keys() {
+                    if columns.contains(k) {
+                        return Err(Error::Invalid(format!("Template parameter \"{}\" is given both as a fixed parameter and as a column of CSV file: {:?}", k, file)));
+                    } else if !placeholders.contains(k) {
+                        return Err(Error::Invalid(format!("Invalid template parameter \"{}\" specified for template file: {:?}", k, template)));
+                    }
+                }
+                for param in &placeholders {
+                    if !columns.contains(param) && !params.contains_key(param) {
+                        return Err(Error::Invalid(format!("Template parameter \"{}\" in {:?} has neither a column in CSV file {:?} nor a fixed value", param, template, file)));
+                    }
+                }
+                if rows.is_empty() {
+                    return Err(Error::Invalid(format!("CSV file has no rows: {:?}", file)));
+                }
+
+                // Fixed parameters are shared by all rows
+                *actions = rows.into_iter()
+                    .map(|mut row| {
+                        row.extend(params.clone());
+                        row
+                    })
+                    .map(|params| Action::Template {
+                        source: template.clone(),
+                        params,
+                        repeat: None,
+                        shuffle: false,
+                        info: Info::default(),
+                        actions: vec![],
+                    })
+                    .collect();
+
+                flow::expand(actions, info, depth, task_dir, config, file, *shuffle, rng)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn id(&self) -> ID {
+        self.info().id.clone()
+    }
+
+    /// Name of the action type, as written in the task file.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Action::Nothing { .. } => "nothing",
+            Action::Instruction { .. } => "instruction",
+            Action::Selection { .. } => "selection",
+            Action::Audio { .. } => "audio",
+            Action::Image { .. } => "image",
+            Action::Fixation { .. } => "fixation",
+            Action::Rsvp { .. } => "rsvp",
+            Action::Question { .. } => "question",
+            Action::EndBlock { .. } => "end_block",
+            Action::SerialTrigger { .. } => "serial_trigger",
+            Action::WaitTrigger { .. } => "wait_trigger",
+            Action::Break { .. } => "break",
+            Action::AudioSequence { .. } => "audio_sequence",
+            Action::ImageSequence { .. } => "image_sequence",
+            Action::QuestionSequence { .. } => "question_sequence",
+            Action::Template { .. } => "template",
+            Action::FromCsv { .. } => "from_csv",
+        }
+    }
+
+    pub fn inner_actions(&self) -> Option<&Vec<Action>> {
+        match self {
+            Action::Template { actions, .. } |
+            Action::FromCsv { actions, .. } => Some(actions),
+            _ => None,
+        }
+    }
+
+    pub fn set_id(&mut self, id: &ID) {
+        self.info_mut().id = id.clone();
+    }
+
+    pub fn is(&self, id: &str) -> bool {
+        self.id() == id
+    }
+
+    pub fn info(&self) -> &Info {
+        match self {
+            Action::Nothing { info, .. } |
+            Action::EndBlock { info, .. } |
+            Action::SerialTrigger { info, .. } |
+            Action::WaitTrigger { info, .. } |
+            Action::Break { info, .. } |
+            Action::Instruction { info, .. } |
+            Action::Selection { info, .. } |
+            Action::Audio { info, .. } |
+            Action::AudioSequence { info, .. } |
+            Action::Image { info, .. } |
+            Action::Fixation { info, .. } |
+            Action::Rsvp { info, .. } |
+            Action::ImageSequence { info, .. } |
+            Action::Question { info, .. } |
+            Action::QuestionSequence { info, .. } |
+            Action::Template { info, .. } |
+            Action::FromCsv { info, .. } => info
+        }
+    }
+
+    pub fn info_mut(&mut self) -> &mut Info {
+        match self {
+            Action::Nothing { info, .. } |
+            Action::EndBlock { info, .. } |
+            Action::SerialTrigger { info, .. } |
+            Action::WaitTrigger { info, .. } |
+            Action::Break { info, .. } |
             Action::Instruction { info, .. } |
             Action::Selection { info, .. } |
             Action::Audio { info, .. } |
+            Action::AudioSequence { info, .. } |
             Action::Image { info, .. } |
+            Action::Fixation { info, .. } |
+            Action::Rsvp { info, .. } |
+            Action::ImageSequence { info, .. } |
             Action::Question { info, .. } |
-            Action::Template { info, .. } => info
+            Action::QuestionSequence { info, .. } |
+            Action::Template { info, .. } |
+            Action::FromCsv { info, .. } => info
         }
     }
 
@@ -385,6 +1685,26 @@ impl Action {
         }
     }
 
+    /// Follow-up actions by the response value that leads to them. The ones that are not
+    /// chosen by the response are skipped.
+    pub fn branches(&self) -> &HashMap<String, ID> {
+        &self.info().branch_on
+    }
+
+    pub fn branches_mut(&mut self) -> &mut HashMap<String, ID> {
+        &mut self.info_mut().branch_on
+    }
+
+    /// Fallback action that follows if the action times out (and is skipped otherwise), or
+    /// `mark_missed` to record the missed response.
+    pub fn on_timeout(&self) -> Option<&ID> {
+        self.info().on_timeout.as_ref()
+    }
+
+    pub fn on_timeout_mut(&mut self) -> &mut Option<ID> {
+        &mut self.info_mut().on_timeout
+    }
+
     pub fn dependents(&self) -> &HashSet<ID> {
         &self.info().dependents
     }
@@ -410,14 +1730,14 @@ impl Action {
         self.is_ready().unwrap()
     }
 
-    pub fn verify(&mut self, id_list: &HashSet<ID>) -> Result<(), String> {
+    pub fn verify(&mut self, id_list: &HashSet<ID>) -> Result<(), Error> {
         let info = self.info_mut();
         match info {
             Info { after: Some(ids), .. } if ids.contains(&info.id) => {
-                Err(format!("Action cannot be a successor of itself: {}", info.id))
+                Err(Error::InvalidGraph(format!("Action cannot be a successor of itself: {}", info.id)))
             }
             Info { with: Some(id), .. } if *id == info.id => {
-                Err(format!("Action cannot be a dependent of itself: {}", info.id))
+                Err(Error::InvalidGraph(format!("Action cannot be a dependent of itself: {}", info.id)))
             }
             Info { after, with, .. } => {
                 // Relink template successors to exit point
@@ -429,10 +1749,10 @@ impl Action {
                             } else if id_list.contains(&format!("{}~exit", id)) {
                                 Ok(format!("{}~exit", id))
                             } else {
-                                Err(format!("Invalid action ID: {}", id))
+                                Err(Error::InvalidGraph(format!("Invalid action ID: {}", id)))
                             }
                         })
-                        .collect::<Result<HashSet<ID>, String>>()?;
+                        .collect::<Result<HashSet<ID>, Error>>()?;
                 }
                 // Relink template dependents to entry/exit points
                 if let Some(id) = with {
@@ -463,141 +1783,809 @@ impl Action {
         self.info().expired
     }
 
+    pub fn missing_resource(&self) -> Option<&String> {
+        self.info().missing.as_ref()
+    }
+
+    /// Marks the action to be skipped, e.g. when a file it names is found missing at runtime.
+    pub fn mark_missing(&mut self, reason: String) {
+        self.info_mut().mark_missing(reason);
+    }
+
+    /// Checks that the files of the action exist, once its runtime references are resolved.
+    pub fn check_resources(&self, task_dir: &Path) -> Result<(), Error> {
+        match self {
+            Action::Image { source, .. } |
+            Action::Audio { source, .. } => resource(task_dir, source).map(|_| ()),
+            Action::AudioSequence { sources, .. } => {
+                sources.iter().try_for_each(|source| resource(task_dir, source).map(|_| ()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The reason given by an end_block action, or `None` for any other action.
+    pub fn ends_block(&self) -> Option<&str> {
+        match self {
+            Action::EndBlock { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+
     pub fn has_view(&self) -> bool {
         match self {
             Action::Nothing { .. } |
-            Action::Audio { .. } => false,
+            Action::EndBlock { .. } |
+            Action::SerialTrigger { .. } |
+            Action::AudioSequence { .. } => false,
+
+            // Expanded into other actions before the block runs
+            Action::QuestionSequence { .. } |
+            Action::Template { .. } |
+            Action::FromCsv { .. } => false,
 
+            Action::Audio { while_playing, .. } => while_playing.is_some(),
+
+            Action::WaitTrigger { .. } |
+            Action::Break { .. } |
             Action::Instruction { .. } |
             Action::Selection { .. } |
             Action::Image { .. } |
+            Action::Fixation { .. } |
+            Action::Rsvp { .. } |
+            Action::ImageSequence { .. } |
+            Action::Question { .. } => true,
+        }
+    }
+
+    /// Text fields that may contain runtime references (e.g. `{{response:ID}}`), resolved right
+    /// before running.
+    pub fn runtime_texts_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Action::Instruction { prompt, .. } |
+            Action::WaitTrigger { prompt, .. } |
+            Action::Break { prompt, .. } => vec![prompt],
+            Action::Selection { prompt, options, .. } => {
+                let mut texts = vec![prompt];
+                texts.extend(options.iter_mut().flat_map(SelectionOption::texts_mut));
+                texts
+            }
+            Action::Audio { source, while_playing, .. } => {
+                let mut texts = vec![source];
+                texts.extend(while_playing.iter_mut());
+                texts
+            }
+            Action::Image { source, .. } => vec![source],
+            Action::Question { list, .. } => {
+                list.iter_mut().map(Question::prompt_mut).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn response_refs(&mut self) -> Vec<(ID, Option<usize>)> {
+        self.runtime_texts_mut()
+            .into_iter()
+            .flat_map(|text| flow::response_refs(text))
+            .collect()
+    }
+
+    pub fn sample_refs(&mut self) -> Vec<String> {
+        self.runtime_texts_mut()
+            .into_iter()
+            .flat_map(|text| flow::sample_refs(text))
+            .collect()
+    }
+
+    pub fn config_refs(&mut self) -> Vec<String> {
+        self.runtime_texts_mut()
+            .into_iter()
+            .flat_map(|text| flow::config_refs(text))
+            .collect()
+    }
+
+    pub fn resolve_runtime_refs(&mut self, values: &HashMap<String, String>) {
+        for text in self.runtime_texts_mut() {
+            for (k, v) in values {
+                *text = text.replace(k, v);
+            }
+        }
+    }
+
+    pub fn response(&self, index: Option<usize>) -> Option<String> {
+        match self {
+            Action::Selection { options, choice, .. } => {
+                choice.map(|i| options[i-1].label().clone())
+            }
+            Action::Question { list, .. } => {
+                list.get(index.unwrap_or(1).max(1) - 1)?.answer()
+            }
+            _ => None,
+        }
+    }
+
+    /// Prompts and answers of the answered questions (or the selection) of the action.
+    pub fn answers(&self) -> Vec<(String, String)> {
+        match self {
+            Action::Selection { prompt, options, choice: Some(i), .. } => {
+                vec![(prompt.clone(), options[i-1].label().clone())]
+            }
+            Action::Question { list, .. } => {
+                list.iter()
+                    .zip(shown_questions(list))
+                    .filter(|(_, shown)| *shown)
+                    .filter_map(|(quest, _)| quest.answer()
+                        .filter(|answer| !answer.is_empty())
+                        .map(|answer| (quest.prompt().to_string(), answer)))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn collects_response(&self) -> bool {
+        match self {
+            Action::Instruction { handle, .. } => handle.is_some(),
+            Action::Break { .. } |
+            Action::Selection { .. } |
             Action::Question { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Appends the current answers of a question list, or the highlighted choice of a
+    /// selection, to its `.partial` file whenever they change, so that they are not lost if the
+    /// session crashes before the action ends.
+    pub fn save_partial(&mut self) {
+        let answers: Vec<Option<String>> = match self {
+            Action::Question { list, .. } => list.iter().map(Question::answer).collect(),
+            Action::Selection { options, choice, pending, .. } => {
+                vec![choice.or(*pending).map(|i| options[i-1].label().clone())]
+            }
+            _ => return,
+        };
+        let info = self.info_mut();
+        if answers == info.partial {
+            return;
+        }
+        let record = PartialAnswers {
+            time_ms: info.onset.map_or(0.0, |onset| onset.elapsed().as_secs_f64() * 1000.0),
+            answers: &answers,
+        };
+        let file = format!("{}.partial", info.log_prefix);
+        if let Err(e) = append_line(&file, &record) {
+            println!("Warning: Failed to save partial answers to {}: {}", file, e);
+        }
+        info.partial = answers;
+    }
+
+    pub fn respond(&mut self) {
+        // A response that was recorded when it was given (before any feedback) is kept
+        if self.info().response_rt.is_some() {
+            return;
+        }
+        if let Some(onset) = self.info().onset {
+            self.respond_after(onset.elapsed());
+        }
+    }
+
+    /// Records a response at the given time from the onset of the action.
+    pub fn respond_after(&mut self, rt: Duration) {
+        let info = self.info_mut();
+        info.responses.push(rt);
+        info.response_rt = Some(rt);
+    }
+
+    pub fn min_rt(&self) -> Option<Duration> {
+        self.info().min_rt
+    }
+
+    /// Replaces the image of a `cover` image action with one cropped to the content area.
+    pub fn crop_cover(&mut self, task_dir: &Path, global: &Global) -> Result<(), Error> {
+        if let Action::Image { source, fit: Fit::Cover, handle: handle @ Some(_), .. } = self {
+            *handle = Some(view::cover_image(&resource(task_dir, source)?, global)?);
+        }
+        Ok(())
+    }
+
+    /// Frame rate of an RSVP sequence, if this is one.
+    pub fn frame_rate(&self) -> Option<f32> {
+        match self {
+            Action::Rsvp { rate_hz, .. } => Some(*rate_hz),
+            _ => None,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.info().timeout
+    }
+
+    /// Number of display frames after which the action completes, if specified.
+    pub fn frames(&self) -> Option<u32> {
+        self.info().frames
+    }
+
+    /// How long the action runs on its own, or None if it only ends on a response or timeout.
+    pub fn duration(&self, global: &Global) -> Result<Option<Duration>, Error> {
+        let duration = match self {
+            Action::Instruction { timer, .. } if !timer.is_zero() => {
+                Some(*timer)
+            }
+            Action::Break { max, .. } => *max,
+            Action::SerialTrigger { info, .. } if info.timeout.is_none() && info.frames.is_none() => {
+                Some(Duration::ZERO)
+            }
+            Action::Audio { looping: true, .. } => None,
+            Action::Audio { source, repeats, .. } => {
+                let source = resource(Path::new(global.dir()), source)?;
+                Some(crate::sound::duration(&source).map_err(Error::Audio)? * repeats.unwrap_or(1))
+            }
+            Action::Rsvp { sources, rate_hz, .. } => {
+                Some(Duration::from_secs_f64(sources.len() as f64 / *rate_hz as f64))
+            }
+            Action::ImageSequence { sources, frame_duration, durations, looping: false, .. } => {
+                Some(sequence_offset(sources.len(), *frame_duration, durations, sources.len()))
+            }
+            Action::AudioSequence { clip_durations, intervals, .. } => {
+                Some(clip_durations.iter().sum::<Duration>() + intervals.iter().sum::<Duration>())
+            }
+            _ => None,
+        };
+        let frames = self.info().frames
+            .map(|n| Duration::from_secs_f64(n as f64 / global.refresh_rate() as f64));
+        Ok(match (duration, frames) {
+            (Some(duration), Some(frames)) => Some(duration.min(frames)),
+            (duration, frames) => duration.or(frames),
+        })
+    }
+
+    /// How long the subject has to wait before they can respond, e.g. the minimum of a break.
+    pub fn earliest_response(&self) -> Duration {
+        match self {
+            Action::Break { min, .. } => *min,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Sets the response of an action from its textual form, i.e. the chosen option of a
+    /// selection, or one answer per question of a question list.
+    pub fn set_response(&mut self, response: &[String]) -> Result<(), Error> {
+        match self {
+            Action::Selection { options, choice, .. } => {
+                let answer = response.first()
+                    .ok_or_else(|| Error::Invalid("Selection response should have one value".to_string()))?;
+                let index = options.iter().position(|o| o.label() == answer)
+                    .or_else(|| answer.parse::<usize>().ok().and_then(|i| i.checked_sub(1)).filter(|&i| i < options.len()))
+                    .ok_or_else(|| Error::Invalid(format!("Invalid selection response: {}", answer)))?;
+                *choice = Some(index + 1);
+                Ok(())
+            }
+            Action::Question { list, .. } => {
+                if response.len() != list.len() {
+                    return Err(Error::Invalid(format!("Expected {} answers for question list, got {}", list.len(), response.len())));
+                }
+                for (quest, answer) in list.iter_mut().zip(response) {
+                    quest.set_answer(answer)?;
+                }
+                clear_hidden(list);
+                Ok(())
+            }
+            Action::Break { continued, .. } if response.is_empty() => {
+                *continued = true;
+                Ok(())
+            }
+            _ if response.is_empty() => Ok(()),
+            _ => Err(Error::Invalid(format!("Action `{}` does not take a response", self.id()))),
+        }
+    }
+
+    /// A random valid response for simulated sessions, for actions that wait on the subject
+    /// (or on a trigger) to end. Other actions end on their own, or with the actions they wait on.
+    pub fn random_response(&self, rng: &mut StdRng) -> Option<Vec<String>> {
+        match self {
+            Action::Selection { options, .. } => {
+                Some(options.choose(rng).map(|o| o.label().clone()).into_iter().collect())
+            }
+            Action::Question { list, .. } => {
+                Some(list.iter().map(|quest| quest.random_answer(rng)).collect())
+            }
+            Action::WaitTrigger { .. } => Some(vec![]),
+            _ if self.collects_response() => Some(vec![]),
+            _ => None,
+        }
+    }
+
+    pub fn plays_audio(&self) -> bool {
+        matches!(self, Action::Audio { .. } | Action::AudioSequence { .. })
+    }
+
+    /// Audio files the action plays (with their trigger files, if in use), except those only
+    /// known at runtime (e.g. sampled from a pool).
+    pub fn audio_sources(&self, task_dir: &Path, trigger_files: bool) -> Vec<PathBuf> {
+        let (sources, trigger_files) = match self {
+            Action::Audio { source, .. } => (vec![source], trigger_files),
+            Action::AudioSequence { sources, .. } => (sources.iter().collect(), false),
+            _ => (vec![], false),
+        };
+        let sources = sources.into_iter()
+            .filter(|source| !source.contains("{{"))
+            .filter_map(|source| resource(task_dir, source).ok());
+        if trigger_files {
+            sources.flat_map(|source| [source.with_extension("trig.wav"), source]).collect()
+        } else {
+            sources.collect()
+        }
+    }
+
+    pub fn playback(&self) -> Option<&Playback> {
+        match self {
+            Action::Audio { playback, .. } |
+            Action::AudioSequence { playback, .. } => playback.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn set_playback(&mut self, result: Playback) {
+        if let Action::Audio { playback, .. } | Action::AudioSequence { playback, .. } = self {
+            *playback = Some(result);
+        }
+    }
+
+    /// Draws the intervals between the clips of an audio sequence, given a source of
+    /// uniformly distributed integers in an inclusive range.
+    pub fn draw_intervals(&mut self, mut draw: impl FnMut(u64, u64) -> u64) {
+        if let Action::AudioSequence { sources, isi, intervals, .. } = self {
+            *intervals = (1..sources.len())
+                .map(|_| match *isi {
+                    Interval::Fixed(interval) => interval,
+                    Interval::Jittered(low, high) => {
+                        Duration::from_millis(draw(low.as_millis() as u64, high.as_millis() as u64))
+                    }
+                })
+                .collect();
+        }
+    }
+
+    /// Draws new display orders of shuffled options, returning each (with the number of its
+    /// question, for question lists) as a list of option indices in display order.
+    pub fn shuffle_options(&mut self, rng: &mut StdRng) -> Vec<(Option<usize>, Vec<usize>)> {
+        match self {
+            Action::Selection { shuffle_options: true, options, option_order, .. } => {
+                *option_order = flow::order(options.len(), true, rng);
+                vec![(None, option_order.clone())]
+            }
+            Action::Question { list, .. } => {
+                list.iter_mut()
+                    .enumerate()
+                    .filter_map(|(i, quest)| quest.shuffle_options(rng).map(|order| (Some(i + 1), order)))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn time_out(&mut self) {
+        // A highlighted choice that is not confirmed yet is taken as the response when time runs
+        // out, with the time at which it was highlighted
+        if let Action::Selection { choice: choice @ None, pending: Some(i), changes, .. } = self {
+            *choice = Some(*i);
+            if let Some(change) = changes.last() {
+                let rt = Duration::from_secs_f64(change.time_ms / 1000.0);
+                self.respond_after(rt);
+            }
+            return;
+        }
+        self.info_mut().timed_out = true;
+    }
+
+    pub fn has_started(&self) -> bool {
+        self.info().onset.is_some()
+    }
+
+    /// Time since the onset of the action, if it has started.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.info().onset.map(|onset| onset.elapsed())
+    }
+
+    pub fn response_times(&self) -> &Vec<Duration> {
+        &self.info().responses
+    }
+
+    pub fn is_timed_out(&self) -> bool {
+        self.info().timed_out
+    }
 
-            Action::Template { .. } => todo!(),
+    pub fn default_monitor_kb(&mut self, monitor_kb: bool) {
+        let info = self.info_mut();
+        if info.monitor_kb.is_none() {
+            info.monitor_kb = Some(monitor_kb);
         }
     }
 
     pub fn has_background(&self) -> bool {
-        self.info().background.is_some()
+        self.info().background.is_some() || self.info().background_color.is_some()
+    }
+
+    /// Color that the whole window is filled with while the action is shown.
+    pub fn backdrop(&self) -> Option<iced::Color> {
+        self.info().backdrop
     }
 
     pub fn captures_keystrokes(&self) -> bool {
-        self.info().monitor_kb
+        self.info().monitor_kb.unwrap_or(false)
     }
 
-    pub fn run(&mut self, writer: Sender, log_dir: &str, global: &Global) -> Command<Message> {
-        self.info_mut().log_prefix = output(log_dir, &self.id());
+    pub fn tracks_mouse(&self) -> bool {
+        self.info().track_mouse
+    }
+
+    pub fn record_cursor(&mut self, x: f32, y: f32) {
+        let info = self.info_mut();
+        if let Some(onset) = info.onset {
+            let time_ms = onset.elapsed().as_secs_f64() * 1000.0;
+            info.cursor.push(CursorSample { time_ms, x, y });
+        }
+    }
+
+    /// Sets the onset of the running block, which keystroke times are measured from.
+    pub fn set_block_onset(&mut self, onset: Option<Instant>) {
+        self.info_mut().block_onset = onset;
+    }
+
+    /// Marks the onset of the action and records its resolved definition, without starting
+    /// any of its timers or playback.
+    pub fn start(&mut self, log_prefix: String) {
+        self.info_mut().log_prefix = log_prefix;
+        self.info_mut().onset = Some(Instant::now());
+        async_write_to_file(
+            format!("{}.def", self.info().log_prefix),
+            self.clone(),
+            "Failed to write action definition to output file");
+    }
+
+    pub fn run(&mut self, writer: Sender, log_prefix: String, global: &Global) -> Command<Message> {
+        self.start(log_prefix);
 
         let mut commands = vec![];
         if let Some(timer) = self.info().timeout {
             let rx = self.new_comm_link();
             commands.push(Command::perform(
-                run::interruptible_timer(self.id(), (writer.clone(), rx), timer),
+                run::timeout(self.id(), (writer.clone(), rx), timer),
+                |msg| msg));
+        }
+        if let Some(frames) = self.info().frames {
+            let deadline = run::frame_deadline(self.info().onset.unwrap(), global.refresh_rate(), frames as usize);
+            let rx = self.new_comm_link();
+            commands.push(Command::perform(
+                run::frame_timeout(self.id(), rx, deadline),
                 |msg| msg));
         }
 
         match self {
             Action::Instruction { timer, .. } => {
-                if *timer > 0 {
-                    let timer = timer.clone();
+                if !timer.is_zero() {
+                    let timer = *timer;
                     let rx = self.new_comm_link();
                     commands.push(Command::perform(
                         run::interruptible_timer(self.id(), (writer, rx), timer),
                         |msg| msg));
                 }
             }
-            Action::Audio { source, .. } => {
-                let source = resource(Path::new(global.dir()), source).unwrap();
-                let use_trigger = global.config().use_trigger();
+            Action::Break { info, min, max, elapsed, continued, .. } => {
+                *elapsed = Duration::ZERO;
+                *continued = false;
+                let (onset, min, max) = (info.onset.unwrap(), *min, *max);
+                let rx = self.new_comm_link();
+                commands.push(run::rest(self.id(), rx, onset, Duration::ZERO, min, max));
+            }
+            Action::Image { source, handle, fit, size, .. } => {
+                if handle.is_none() {
+                    let source = match resource(Path::new(global.dir()), source) {
+                        Ok(source) => source,
+                        Err(e) => return report(e.to_string()),
+                    };
+                    *size = ::image::image_dimensions(&source).ok();
+                    *handle = Some(match fit {
+                        // Resolved at runtime, so the image is only decoded now
+                        Fit::Cover => match view::cover_image(&source, global) {
+                            Ok(handle) => handle,
+                            Err(e) => return report(e.to_string()),
+                        },
+                        _ => image::Handle::from_path(source),
+                    });
+                }
+            }
+            Action::ImageSequence { info, sources, frame_duration, durations, looping, frame, frame_onsets, .. } => {
+                *frame = 0;
+                frame_onsets.push(Duration::ZERO);
+                let deadline = info.onset.unwrap() + sequence_offset(sources.len(), *frame_duration, durations, 1);
+                let is_last = sources.len() == 1 && !*looping;
+                let rx = self.new_comm_link();
+                commands.push(if is_last {
+                    Command::perform(run::last_frame(self.id(), rx, deadline), |msg| msg)
+                } else {
+                    Command::perform(run::frame(self.id(), rx, deadline, 1), |msg| msg)
+                });
+            }
+            Action::Rsvp { info, rate_hz, sources, frame, frame_onsets, .. } => {
+                *frame = 0;
+                frame_onsets.push(Duration::ZERO);
+                let deadline = run::frame_deadline(info.onset.unwrap(), *rate_hz, 1);
+                let is_last = sources.len() == 1;
+                let rx = self.new_comm_link();
+                commands.push(if is_last {
+                    Command::perform(run::last_frame(self.id(), rx, deadline), |msg| msg)
+                } else {
+                    Command::perform(run::frame(self.id(), rx, deadline, 1), |msg| msg)
+                });
+            }
+            Action::Audio { source, looping, repeats, volume, trigger, .. } => {
+                let source = match resource(Path::new(global.dir()), source) {
+                    Ok(source) => source,
+                    Err(e) => return report(e.to_string()),
+                };
+                let trigger = match global.config().trigger(&source) {
+                    Trigger::None => Trigger::None,
+                    // The trigger channel is kept silent, e.g. for background noise
+                    _ if *trigger == Some(false) => Trigger::Silent,
+                    trigger => trigger,
+                };
+                let stream_handle = global.io().audio_stream();
+                let repeats = if *looping { None } else { Some(repeats.unwrap_or(1)) };
+                let volume = volume.unwrap_or(1.0);
+
+                let rx = self.new_comm_link();
+                commands.push(Command::perform(
+                    run::audio(self.id(), (writer, rx), source, trigger, repeats, volume, stream_handle),
+                    |msg| msg));
+            }
+            Action::AudioSequence { sources, intervals, volume, .. } => {
+                let sources = match sources.iter()
+                    .map(|source| resource(Path::new(global.dir()), source))
+                    .collect::<Result<Vec<_>, _>>() {
+                    Ok(sources) => sources,
+                    Err(e) => return report(e.to_string()),
+                };
+                let intervals = intervals.clone();
+                let volume = volume.unwrap_or(1.0);
                 let stream_handle = global.io().audio_stream();
 
-                let source = source.clone();
                 let rx = self.new_comm_link();
                 commands.push(Command::perform(
-                    run::audio(self.id(), (writer, rx), source, use_trigger, stream_handle),
+                    run::audio_sequence(self.id(), (writer, rx), sources, intervals, volume, stream_handle),
+                    |msg| msg));
+            }
+            Action::SerialTrigger { port, baud, code, info } => {
+                // Without a timeout, the action finishes as soon as the trigger is sent
+                let complete = info.timeout.is_none() && info.frames.is_none();
+                let (port, baud, code, log_prefix) = (port.clone(), *baud, *code, info.log_prefix.clone());
+                commands.push(Command::perform(
+                    run::serial_trigger(self.id(), port, baud, code, log_prefix, complete),
                     |msg| msg));
             }
-            Action::Nothing { .. } |
-            Action::Selection { .. } |
-            Action::Image { .. } |
-            Action::Question { .. } |
-            Action::Template { .. } => {}
-        }
-
-        Command::batch(commands)
-    }
+            Action::WaitTrigger { serial, count, info, pulses, .. } => {
+                // Blocks are cloned when run, so pulses are never shared with a previous run
+                *pulses = Arc::new(Mutex::new(vec![]));
+                if let Some(serial) = serial.clone() {
+                    let (count, pulses, onset) = (*count, pulses.clone(), info.block_onset);
+                    let rx = self.new_comm_link();
+                    commands.push(Command::perform(
+                        run::wait_serial(self.id(), rx, serial, count, pulses, onset),
+                        |msg| msg));
+                }
+            }
+            Action::Nothing { .. } |
+            Action::EndBlock { .. } |
+            Action::Fixation { .. } |
+            Action::Selection { .. } |
+            Action::Question { .. } |
+            Action::QuestionSequence { .. } |
+            Action::Template { .. } |
+            Action::FromCsv { .. } => {}
+        }
+
+        Command::batch(commands)
+    }
+
+    pub fn view(&mut self, global: &Global) -> Column<Message> {
+        let id = self.id();
+        let style = self.info().style.clone().unwrap_or_default();
+        match self {
+            Action::Nothing { .. } |
+            Action::EndBlock { .. } |
+            Action::SerialTrigger { .. } |
+            Action::AudioSequence { .. } => {
+                Column::new()
+            }
+            Action::Instruction { prompt, handle, advance_on_key, .. } => {
+                if let (Some(handle), false) = (handle, *advance_on_key) {
+                    let e_next = button(
+                        handle,
+                        "Next",
+                        global.text_size("XLARGE"))
+                        .on_press(Message::ActionComplete(id))
+                        .width(Length::Units(400));
+
+                    Column::new()
+                        .width(Length::Fill)
+                        .align_items(Align::Center)
+                        .push(Space::with_height(Length::Fill))
+                        .push(view::instruction(prompt, &style, global))
+                        .push(Space::with_height(Length::Fill))
+                        .push(e_next)
+                } else {
+                    Column::new()
+                        .width(Length::Fill)
+                        .align_items(Align::Center)
+                        .push(Space::with_height(Length::Fill))
+                        .push(view::instruction(prompt, &style, global))
+                        .push(Space::with_height(Length::Fill))
+                }
+            }
+            Action::Selection { options, choice: Some(i), .. } => {
+                // Feedback of the chosen option is shown until the feedback timer runs out
+                Column::new()
+                    .width(Length::Fill)
+                    .align_items(Align::Center)
+                    .push(Space::with_height(Length::Fill))
+                    .push(Text::new(options[*i-1].feedback().cloned().unwrap_or_default())
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color())
+                        .horizontal_alignment(style.horizontal_alignment(global)))
+                    .push(Space::with_height(Length::Fill))
+            }
+            Action::Selection { prompt, options, handles, columns, button_width, orientation, confirm, pending, confirm_handle, option_order, .. } => {
+                let grid = Grid::new(*columns, *button_width, *orientation);
+                let pending = *pending;
+                let order = view::display_order(option_order, options.len());
+                let buttons = handles.iter_mut()
+                    .zip(order)
+                    .map(|(handle, i)| {
+                        let e_option = button(
+                            handle,
+                            options[i].label(),
+                            global.text_size("XLARGE"))
+                            .on_press(Message::UIEvent(0x01, Value::Integer(1+i as i32)))
+                            .width(grid.button_width());
+                        // The highlighted choice is only final once confirmed
+                        if pending == Some(i+1) {
+                            e_option.style(style::Button::Active).into()
+                        } else {
+                            e_option.into()
+                        }
+                    })
+                    .collect();
+                let rows = grid.arrange(buttons);
+
+                let content = Column::new()
+                    // .width(Length::Fill)
+                    .spacing(40)
+                    .align_items(Align::Center)
+                    .push(Text::new(prompt.as_str())
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color()))
+                    .push(rows);
+                if *confirm {
+                    let mut e_confirm = button(
+                        confirm_handle,
+                        "Confirm",
+                        global.text_size("XLARGE"))
+                        .style(style::Button::Secondary)
+                        .width(Length::Units(300));
+                    if pending.is_some() {
+                        e_confirm = e_confirm.on_press(Message::UIEvent(view::SUBMIT, Value::Null));
+                    }
+                    content.push(e_confirm)
+                } else {
+                    content
+                }
+            }
+            Action::WaitTrigger { prompt, .. } => {
+                Column::new()
+                    .width(Length::Fill)
+                    .align_items(Align::Center)
+                    .push(Space::with_height(Length::Fill))
+                    .push(Text::new(prompt.clone())
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color())
+                        .horizontal_alignment(style.horizontal_alignment(global)))
+                    .push(Space::with_height(Length::Fill))
+            }
+            Action::Break { prompt, min, max, elapsed, handle, .. } => {
+                // Counts down to the end of the break if it has a `max`, and up otherwise
+                let clock = match max {
+                    Some(max) => format!("The task continues in {}", view::clock(max.saturating_sub(*elapsed))),
+                    None => format!("Break time: {}", view::clock(*elapsed)),
+                };
+                let mut e_continue = button(
+                    handle,
+                    "Continue",
+                    global.text_size("XLARGE"))
+                    .width(Length::Units(400));
+                let note = if *elapsed >= *min {
+                    e_continue = e_continue.on_press(Message::UIEvent(view::SUBMIT, Value::Null));
+                    String::new()
+                } else {
+                    format!("You can continue in {}", view::clock(min.saturating_sub(*elapsed)))
+                };
 
-    pub fn view(&mut self, global: &Global) -> Column<Message> {
-        let id = self.id();
-        match self {
-            Action::Nothing { .. } => {
                 Column::new()
+                    .width(Length::Fill)
+                    .align_items(Align::Center)
+                    .spacing(20)
+                    .push(Space::with_height(Length::Fill))
+                    .push(view::instruction(prompt, &style, global))
+                    .push(Text::new(clock)
+                        .size(style.text_size(global, "LARGE"))
+                        .color(style.color()))
+                    .push(Space::with_height(Length::Fill))
+                    .push(Text::new(note)
+                        .size(global.text_size("NORMAL")))
+                    .push(e_continue)
             }
-            Action::Instruction { prompt, handle, .. } => {
-                if let Some(handle) = handle {
-                    let e_next = button(
-                        handle,
-                        "Next",
-                        global.text_size("XLARGE"))
-                        .on_press(Message::ActionComplete(id))
-                        .width(Length::Units(400));
-
+            Action::Audio { while_playing, .. } => {
+                if let Some(prompt) = while_playing {
                     Column::new()
                         .width(Length::Fill)
                         .align_items(Align::Center)
                         .push(Space::with_height(Length::Fill))
                         .push(Text::new(prompt.clone())
-                            .size(global.text_size("XLARGE"))
-                            .horizontal_alignment(global.horizontal_alignment()))
+                            .size(style.text_size(global, "XLARGE"))
+                            .color(style.color())
+                            .horizontal_alignment(style.horizontal_alignment(global)))
                         .push(Space::with_height(Length::Fill))
-                        .push(e_next)
                 } else {
                     Column::new()
-                        .width(Length::Fill)
-                        .align_items(Align::Center)
-                        .push(Space::with_height(Length::Fill))
-                        .push(Text::new(prompt.clone())
-                            .size(global.text_size("XLARGE"))
-                            .horizontal_alignment(global.horizontal_alignment()))
-                        .push(Space::with_height(Length::Fill))
                 }
             }
-            Action::Selection { prompt, options, handles, .. } => {
-                let mut rows = Column::new()
-                    .spacing(40)
-                    .align_items(Align::Center);
-                let mut controls = Row::new()
-                    .spacing(60);
-                for (i, handle) in handles.iter_mut().enumerate() {
-                    if i > 0 && i % 3 == 0 {
-                        rows = rows.push(controls);
-                        controls = Row::new()
-                            .spacing(60);
-                    }
-                    controls = controls.push(button(
-                        handle,
-                        &options[i],
-                        global.text_size("XLARGE"))
-                        .on_press(Message::UIEvent(0x01, Value::Integer(1+i as i32)))
-                        .width(Length::Units(200)));
+            Action::Image { handle, fit, size, max_width, max_height, .. } => {
+                let image = handle.as_ref().unwrap().clone();
+                let image = match (fit, *size) {
+                    (Fit::Native, _) => Image::new(image),
+                    (Fit::Contain, _) | (Fit::Cover, _) => Image::new(image)
+                        .width(Length::Fill)
+                        .height(Length::Fill),
+                    (Fit::Width(px), Some((w, h))) => Image::new(image)
+                        .width(Length::Units(*px))
+                        .height(Length::Units((*px as u32 * h / w.max(1)) as u16)),
+                    (Fit::Width(px), None) => Image::new(image)
+                        .width(Length::Units(*px)),
+                };
+                let mut image = Container::new(image);
+                if let Some(max_width) = max_width {
+                    image = image.max_width(*max_width);
+                }
+                if let Some(max_height) = max_height {
+                    image = image.max_height(*max_height);
                 }
-                rows = rows.push(controls);
 
                 Column::new()
-                    // .width(Length::Fill)
-                    .spacing(40)
-                    .align_items(Align::Center)
-                    .push(Text::new(prompt.as_str())
-                        .size(global.text_size("XLARGE")))
-                    .push(rows)
-                    .into()
+                    .push(Container::new(image)
+                        .width(Length::Fill)
+                        .height(Length::Fill)
+                        .center_x()
+                        .center_y())
+                    .width(Length::Fill)
+                    .height(Length::Fill)
             }
-            Action::Audio { .. } => {
+            Action::Fixation { size, handle, .. } => {
+                let cross = Svg::new(handle.as_ref().unwrap().clone())
+                    .width(Length::Units(*size))
+                    .height(Length::Units(*size));
+
                 Column::new()
+                    .push(Container::new(cross)
+                        .width(Length::Fill)
+                        .height(Length::Fill)
+                        .center_x()
+                        .center_y())
+                    .width(Length::Fill)
+                    .height(Length::Fill)
             }
-            Action::Image { handle, .. } => {
-                let image = handle.as_ref().unwrap().clone();
-                let image = Image::new(image);
+            Action::Rsvp { handles, frame, .. } |
+            Action::ImageSequence { handles, frame, .. } => {
+                // A looping sequence keeps counting frames past the end of its sources
+                let image = Image::new(handles[*frame % handles.len()].clone());
 
                 Column::new()
                     .push(Container::new(image)
@@ -608,31 +2596,72 @@ impl Action {
                     .width(Length::Fill)
                     .height(Length::Fill)
             }
-            Action::Question { list: questions, handle, .. } => {
+            Action::Question {
+                list: questions,
+                per_page,
+                handle,
+                back_handle,
+                page,
+                incomplete,
+                ..
+            } => {
+                let pages = view::pages(questions.len(), *per_page);
+                let shown = shown_questions(questions);
+                let is_last = view::next_page(&pages, &shown, *page).is_none();
                 let mut content = Column::new()
                     // .width(Length::Fill)
                     .spacing(40)
                     .align_items(Align::Start);
                 for (i, quest) in questions.iter_mut().enumerate() {
-                    content = content.push(view::question(quest, i, global));
+                    if pages[*page].contains(&i) && shown[i] {
+                        content = content.push(view::question(quest, i, *incomplete, &style, global));
+                    }
                 }
 
                 let e_submit = button(
                     handle,
-                    "Submit",
+                    if is_last { "Submit" } else { "Next" },
                     global.text_size("XLARGE"))
-                    .on_press(Message::ActionComplete(id))
+                    .on_press(Message::UIEvent(
+                        if is_last { view::SUBMIT } else { view::NEXT_PAGE },
+                        Value::Null))
                     .width(Length::Units(400));
+                let mut controls = Row::new()
+                    .spacing(60);
+                if *page > 0 {
+                    controls = controls.push(button(
+                        back_handle,
+                        "Back",
+                        global.text_size("XLARGE"))
+                        .on_press(Message::UIEvent(view::PREV_PAGE, Value::Null))
+                        .width(Length::Units(400)));
+                }
+                controls = controls.push(e_submit);
 
-                Column::new()
+                let mut column = Column::new()
                     // .width(Length::Fill)
                     .align_items(Align::Center)
                     .push(content)
-                    .push(Space::with_height(Length::Fill))
-                    .push(e_submit)
+                    .push(Space::with_height(Length::Fill));
+                if *incomplete {
+                    column = column
+                        .push(Text::new("Please answer all required questions.")
+                            .size(global.text_size("LARGE")))
+                        .push(Space::with_height(Length::Units(20)));
+                }
+                if pages.len() > 1 {
+                    column = column
+                        .push(Text::new(format!("Page {} of {}", *page + 1, pages.len()))
+                            .size(global.text_size("NORMAL")))
+                        .push(Space::with_height(Length::Units(20)));
+                }
+                column
+                    .push(controls)
                     .into()
             }
-            Action::Template { .. } => {
+            Action::QuestionSequence { .. } |
+            Action::Template { .. } |
+            Action::FromCsv { .. } => {
                 Column::new()
                     .push(Text::new("This shouldn't have happened!")
                         .size(global.text_size("XLARGE")))
@@ -641,11 +2670,67 @@ impl Action {
     }
 
     pub fn update(&mut self, message: Message, _global: &Global) -> Command<Message> {
-        if let Message::KeyPress(key_code) = message {
-            self.info_mut().keystrokes.push(format!("{}  {:?}", timestamp(), key_code));
+        if let Message::KeyRelease(key_code) = message {
+            let info = self.info_mut();
+            if !accepts_key(&info.keys, key_code) {
+                return Command::none();
+            }
+            let release_ms = info.onset.map_or(0.0, |onset| onset.elapsed().as_secs_f64() * 1000.0);
+            let press_ms = info.held_keys.iter()
+                .position(|&(held, _)| held == key_code)
+                .map(|i| info.held_keys.remove(i).1);
+            info.key_holds.push(KeyHold {
+                key: format!("{:?}", key_code),
+                press_ms,
+                release_ms: Some(release_ms),
+                duration_ms: press_ms.map(|press_ms| release_ms - press_ms),
+            });
             return Command::none();
         }
+        if let Message::KeyPress(key_code) = message {
+            let info = self.info_mut();
+            let rt = info.onset.map(|onset| onset.elapsed()).unwrap_or_default();
+            let time = info.block_onset.map(event_time).unwrap_or_else(timestamp);
+            let mut keystroke = format!("{}  {:?}", time, key_code);
+            if !accepts_key(&info.keys, key_code) {
+                if info.log_invalid_keys {
+                    keystroke.push_str("  INVALID");
+                    info.keystrokes.push(keystroke);
+                }
+                return Command::none();
+            }
+            if info.is_anticipatory(rt) {
+                keystroke.push_str("  ANTICIPATORY");
+            }
+            info.keystrokes.push(keystroke);
+            // Repeated presses of a key held down (by auto-repeat) are part of the same hold
+            if !info.held_keys.iter().any(|&(held, _)| held == key_code) {
+                info.held_keys.push((key_code, rt.as_secs_f64() * 1000.0));
+            }
+            if info.monitor_kb.unwrap_or(false) {
+                info.responses.push(rt);
+            }
+            return match self {
+                Action::Instruction { handle: Some(_), advance_on_key: true, accept_keys, .. }
+                if accepts_key(accept_keys, key_code) => {
+                    let id = self.id();
+                    Command::perform(async move { id }, Message::ActionComplete)
+                }
+                Action::WaitTrigger { key: Some(key), count, info, pulses, .. }
+                if *key == format!("{:?}", key_code) => {
+                    let time = info.block_onset.map(event_time).unwrap_or_else(timestamp);
+                    if record_pulse(pulses, time, "key", *count) {
+                        let id = self.id();
+                        Command::perform(async move { id }, Message::ActionComplete)
+                    } else {
+                        Command::none()
+                    }
+                }
+                _ => Command::none(),
+            };
+        }
 
+        let action = self.id();
         match self {
             Action::Audio { .. } => {
                 match message {
@@ -654,44 +2739,158 @@ impl Action {
                     //     Command::none()
                     // }
                     _ => {
-                        panic!("{:?}", message);
+                        unexpected(&action, &message)
+                    }
+                }
+            }
+            Action::Selection { choice, .. } if choice.is_some() => Command::none(),
+            Action::Selection { options, feedback_timer, choice, confirm, pending, changes, info, .. } => {
+                let i = match message {
+                    // With `confirm`, a choice is highlighted (and can be changed) until confirmed
+                    Message::UIEvent(0x01, Value::Integer(i)) if *confirm => {
+                        *pending = Some(i as usize);
+                        changes.push(SelectionChange {
+                            option: options[i as usize - 1].label().clone(),
+                            time_ms: info.onset.map_or(0.0, |onset| onset.elapsed().as_secs_f64() * 1000.0),
+                        });
+                        return Command::none();
+                    }
+                    Message::UIEvent(0x01, Value::Integer(i)) => i as usize,
+                    Message::UIEvent(view::SUBMIT, _) => match *pending {
+                        Some(i) => i,
+                        None => return Command::none(),
+                    },
+                    _ => {
+                        return unexpected(&action, &message);
                     }
+                };
+                *choice = Some(i);
+                let feedback = options[i - 1].feedback().is_some();
+                let deadline = Instant::now() + *feedback_timer;
+                // The response time is that of the click, not of the end of the feedback
+                self.respond();
+                let id = self.id();
+                if feedback {
+                    let rx = self.new_comm_link();
+                    Command::perform(run::last_frame(id, rx, deadline), |msg| msg)
+                } else {
+                    Command::perform(
+                        async move { id },
+                        |id| Message::ActionComplete(id))
                 }
             }
-            Action::Selection { choice, .. } => {
+            Action::ImageSequence { info, sources, frame_duration, durations, looping, frame, frame_onsets, .. } => {
                 match message {
-                    Message::UIEvent(0x01, Value::Integer(i)) => {
-                        *choice = Some(i as usize);
-                        let id = self.id();
-                        Command::perform(
-                            async move { id },
-                            |id| Message::ActionComplete(id))
+                    Message::Value(_, _, 0x01, Value::Integer(i)) => {
+                        let onset = info.onset.unwrap();
+                        *frame = i as usize;
+                        frame_onsets.push(onset.elapsed());
+                        let next = *frame + 1;
+                        let deadline = onset + sequence_offset(sources.len(), *frame_duration, durations, next);
+                        let is_last = next == sources.len() && !*looping;
+                        let rx = self.new_comm_link();
+                        if is_last {
+                            Command::perform(run::last_frame(self.id(), rx, deadline), |msg| msg)
+                        } else {
+                            Command::perform(run::frame(self.id(), rx, deadline, next), |msg| msg)
+                        }
                     }
                     _ => {
-                        panic!("{:?}", message);
+                        unexpected(&action, &message)
                     }
                 }
             }
-            Action::Question { list, .. } => {
+            Action::Break { info, min, max, elapsed, continued, .. } => {
                 match message {
-                    Message::UIEvent(code, value) => {
-                        list[(code - 0x01) as usize].update(value);
+                    Message::Value(_, _, 0x01, Value::Integer(ms)) => {
+                        *elapsed = Duration::from_millis(ms as u64);
+                        let (onset, min, max, elapsed) = (info.onset.unwrap(), *min, *max, *elapsed);
+                        let rx = self.new_comm_link();
+                        run::rest(self.id(), rx, onset, elapsed, min, max)
+                    }
+                    Message::UIEvent(view::SUBMIT, _) if *elapsed >= *min => {
+                        *continued = true;
+                        Command::perform(async move { action }, Message::ActionComplete)
+                    }
+                    Message::UIEvent(..) => Command::none(),
+                    _ => {
+                        unexpected(&action, &message)
+                    }
+                }
+            }
+            Action::Rsvp { info, rate_hz, sources, frame, frame_onsets, .. } => {
+                match message {
+                    Message::Value(_, _, 0x01, Value::Integer(i)) => {
+                        let onset = info.onset.unwrap();
+                        *frame = i as usize;
+                        frame_onsets.push(onset.elapsed());
+                        let next = *frame + 1;
+                        let deadline = run::frame_deadline(onset, *rate_hz, next);
+                        let is_last = next == sources.len();
+                        let rx = self.new_comm_link();
+                        if is_last {
+                            Command::perform(run::last_frame(self.id(), rx, deadline), |msg| msg)
+                        } else {
+                            Command::perform(run::frame(self.id(), rx, deadline, next), |msg| msg)
+                        }
+                    }
+                    _ => {
+                        unexpected(&action, &message)
+                    }
+                }
+            }
+            Action::Question { list, per_page, page, incomplete, .. } => {
+                let pages = view::pages(list.len(), *per_page);
+                match message {
+                    Message::UIEvent(view::PREV_PAGE, _) => {
+                        let shown = shown_questions(list);
+                        // The first page always has a question shown, as it cannot depend on another
+                        *page = (0..*page).rev()
+                            .find(|&p| pages[p].clone().any(|i| shown[i]))
+                            .unwrap_or(0);
+                        *incomplete = false;
                         Command::none()
                     }
+                    Message::UIEvent(code @ (view::NEXT_PAGE | view::SUBMIT), _) => {
+                        let shown = shown_questions(list);
+                        *incomplete = !pages[*page].clone().all(|i| !shown[i] || list[i].is_complete());
+                        let next = view::next_page(&pages, &shown, *page);
+                        if *incomplete {
+                            Command::none()
+                        } else if let (view::NEXT_PAGE, Some(next)) = (code, next) {
+                            *page = next;
+                            Command::none()
+                        } else {
+                            let id = self.id();
+                            Command::perform(async move { id }, Message::ActionComplete)
+                        }
+                    }
+                    Message::UIEvent(code, value) => {
+                        match list[(code - 0x01) as usize].update(value) {
+                            Ok(()) => {
+                                clear_hidden(list);
+                                Command::none()
+                            }
+                            Err(e) => report(e),
+                        }
+                    }
                     _ => {
-                        panic!("{:?}", message);
+                        unexpected(&action, &message)
                     }
                 }
             }
             _ => {
-                panic!("{:?}", message)
+                unexpected(&action, &message)
             }
         }
     }
 
     pub fn background(&mut self) -> Column<Message> {
-        let image = self.info_mut().background_image.as_ref().unwrap().clone();
-        let image = Image::new(image);
+        let image = match &self.info().background_image {
+            Some(image) => Image::new(image.clone()),
+            // Only a background color, which fills the window behind the content
+            None => return Column::new(),
+        };
 
         Column::new()
             .push(Container::new(image)
@@ -705,22 +2904,126 @@ impl Action {
 
     pub fn wrap(&self) {
         let info = self.info();
-        if info.monitor_kb {
+        if info.monitor_kb.unwrap_or(false) {
             async_write_to_file(
                 format!("{}.keypress", info.log_prefix),
                 info.keystrokes.clone(),
                 "Failed to write key presses to output file");
+            // Keys still held when the action ends are listed without a release
+            let mut key_holds = info.key_holds.clone();
+            key_holds.extend(info.held_keys.iter().map(|&(key_code, press_ms)| KeyHold {
+                key: format!("{:?}", key_code),
+                press_ms: Some(press_ms),
+                release_ms: None,
+                duration_ms: None,
+            }));
+            if !key_holds.is_empty() {
+                async_write_to_file(
+                    format!("{}.keyhold", info.log_prefix),
+                    key_holds,
+                    "Failed to write key holds to output file");
+            }
+        }
+        if info.track_mouse {
+            async_write_to_file(
+                format!("{}.mousetrack", info.log_prefix),
+                info.cursor.clone(),
+                "Failed to write mouse track to output file");
         }
         for comm in &info.comm {
             comm.send(Message::Wrap).ok();
         }
 
+        let anticipatory = info.response_rt
+            .map(|rt| info.is_anticipatory(rt))
+            .unwrap_or(false);
+
         match self {
-            Action::Selection { info, choice, .. } => {
+            Action::Selection { info, options, choice, changes, .. } => {
+                let file = format!("{}.choice", info.log_prefix);
+                let err = "Failed to write selection choice to output file";
+                let choice = *choice;
+                match (choice.and_then(|i| options[i-1].value()), info.min_rt) {
+                    (Some(value), Some(_)) => {
+                        async_write_to_file(file, Tagged { response: Outcome { choice, value }, anticipatory }, err)
+                    }
+                    (Some(value), None) => async_write_to_file(file, Outcome { choice, value }, err),
+                    (None, Some(_)) => async_write_to_file(file, Tagged { response: choice, anticipatory }, err),
+                    (None, None) => async_write_to_file(file, choice, err),
+                }
+                if !changes.is_empty() {
+                    async_write_to_file(
+                        format!("{}.selections", info.log_prefix),
+                        changes.clone(),
+                        "Failed to write selection changes to output file");
+                }
+            }
+            Action::Break { info, min, max, continued, .. } => {
+                // Only continuing records a response; otherwise the break lasted until it timed
+                // out at `max`, or was interrupted
+                let duration = info.response_rt
+                    .or_else(|| info.onset.map(|onset| onset.elapsed()))
+                    .unwrap_or_default();
+                async_write_to_file(
+                    format!("{}.rest", info.log_prefix),
+                    RestRecord {
+                        duration_ms: duration.as_secs_f64() * 1000.0,
+                        min_ms: min.as_millis() as u32,
+                        max_ms: max.map(|max| max.as_millis() as u32),
+                        continued: *continued,
+                    },
+                    "Failed to write break duration to output file");
+            }
+            Action::WaitTrigger { info, pulses, .. } => {
                 async_write_to_file(
-                    format!("{}.choice", info.log_prefix),
-                    choice.clone(),
-                    "Failed to write selection choice to output file");
+                    format!("{}.pulses", info.log_prefix),
+                    pulses.lock().unwrap().clone(),
+                    "Failed to write trigger pulses to output file");
+            }
+            Action::AudioSequence { info, sources, clip_durations, intervals, playback, .. } => {
+                // Clip onsets follow from the clip durations and intervals, as the clips are
+                // queued back-to-back in a single sink; clips that never started are left out
+                let elapsed = info.onset.map(|onset| onset.elapsed()).unwrap_or_default();
+                let completed = *playback == Some(Playback::Completed);
+                let mut onset = Duration::ZERO;
+                let mut clips = vec![];
+                for (i, source) in sources.iter().enumerate() {
+                    if onset > elapsed && !completed {
+                        break;
+                    }
+                    clips.push(ClipOnset {
+                        source: source.clone(),
+                        onset_ms: onset.as_secs_f64() * 1000.0,
+                        isi_ms: intervals.get(i).map(|interval| interval.as_millis() as u32),
+                    });
+                    onset += clip_durations[i] + intervals.get(i).copied().unwrap_or_default();
+                }
+                async_write_to_file(
+                    format!("{}.onsets", info.log_prefix),
+                    clips,
+                    "Failed to write audio sequence onsets to output file");
+            }
+            Action::Rsvp { info, sources, frame_onsets, .. } |
+            Action::ImageSequence { info, sources, frame_onsets, .. } => {
+                let timing: Vec<_> = frame_onsets.iter()
+                    .enumerate()
+                    .map(|(i, onset)| FrameTiming {
+                        source: sources[i % sources.len()].clone(),
+                        onset_ms: onset.as_secs_f64() * 1000.0,
+                        interval_ms: frame_onsets.get(i + 1)
+                            .map(|next| (*next - *onset).as_secs_f64() * 1000.0),
+                    })
+                    .collect();
+                async_write_to_file(
+                    format!("{}.frames", info.log_prefix),
+                    timing,
+                    "Failed to write frame timing to output file");
+            }
+            Action::Question { info, list, .. } if info.min_rt.is_some() => {
+                async_write_to_file(
+                    format!("{}.response", info.log_prefix),
+                    Tagged { response: list.clone(), anticipatory },
+                    "Failed to write question responses to output file");
             }
             Action::Question { info, list, .. } => {
                 async_write_to_file(
@@ -732,6 +3035,19 @@ impl Action {
         }
     }
 
+    /// Suspends the timers and playback of a running action.
+    pub fn pause(&self) {
+        for comm in &self.info().comm {
+            comm.send(Message::Pause).ok();
+        }
+    }
+
+    pub fn resume(&self) {
+        for comm in &self.info().comm {
+            comm.send(Message::Resume).ok();
+        }
+    }
+
     pub fn new_comm_link(&mut self) -> Receiver {
         let (tx, rx) = mpsc::channel();
         self.info_mut().comm.push(tx);
@@ -740,20 +3056,174 @@ impl Action {
 }
 
 pub mod view {
-    use iced::{Radio, Row};
+    use std::ops::Range;
+    use iced::{Color, Element, HorizontalAlignment, PickList, Radio, Row};
     use super::*;
 
-    pub fn question<'a>(quest: &'a mut Question, index: usize, global: &Global) -> Column<'a, Message> {
+    /// Decodes an image, and crops it around its center to the shape of the content area.
+    pub fn cover_image(path: &Path, global: &Global) -> Result<image::Handle, Error> {
+        use ::image::GenericImageView;
+
+        let decoded = ::image::open(path)
+            .map_err(|e| Error::MissingResource(format!("Failed to decode image {:?}: {}", path, e)))?;
+        let (width, height) = (decoded.width(), decoded.height());
+        let (area_width, area_height) = global.content_pixels();
+        let aspect = area_width / area_height;
+        let (crop_width, crop_height) = if width as f32 / height as f32 > aspect {
+            ((height as f32 * aspect).round() as u32, height)
+        } else {
+            (width, (width as f32 / aspect).round() as u32)
+        };
+        let cropped = decoded
+            .crop_imm((width - crop_width) / 2, (height - crop_height) / 2, crop_width, crop_height)
+            .into_bgra8();
+        Ok(image::Handle::from_pixels(crop_width, crop_height, cropped.into_raw()))
+    }
+
+    /// A duration in minutes and seconds, e.g. "2:05", with partial seconds rounded up.
+    pub fn clock(duration: Duration) -> String {
+        let secs = duration.as_millis().div_ceil(1000);
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+
+    /// Text of an instruction prompt, rendered as rich text if the task enables it.
+    pub fn instruction<'a>(prompt: &str, style: &TextStyle, global: &Global) -> Element<'a, Message> {
+        let size = style.text_size(global, "XLARGE");
+        if global.rich_text().is_some() {
+            markup::view(prompt, size, style.color(), style.horizontal_alignment(global), global)
+                .into()
+        } else {
+            Text::new(prompt)
+                .size(size)
+                .color(style.color())
+                .horizontal_alignment(style.horizontal_alignment(global))
+                .into()
+        }
+    }
+
+    pub const PREV_PAGE: u16 = 0xFF01;
+    pub const NEXT_PAGE: u16 = 0xFF02;
+    pub const SUBMIT: u16 = 0xFF03;
+
+    /// Question prompt, marked with an asterisk if an answer is required.
+    pub fn prompt(prompt: &str, required: bool) -> String {
+        if required {
+            format!("{} *", prompt)
+        } else {
+            prompt.to_string()
+        }
+    }
+
+    /// A fixation cross drawn as vector graphics, so that it stays sharp at any resolution.
+    pub fn fixation_cross(size: u16, line_width: u16, color: Color) -> svg::Handle {
+        let fill = format!("#{:02x}{:02x}{:02x}",
+                           (color.r * 255.0).round() as u8,
+                           (color.g * 255.0).round() as u8,
+                           (color.b * 255.0).round() as u8);
+        let offset = (size - line_width) as f32 / 2.0;
+        let data = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{s}" height="{s}" viewBox="0 0 {s} {s}">
+<rect x="0" y="{o}" width="{s}" height="{w}" fill="{c}"/>
+<rect x="{o}" y="0" width="{w}" height="{s}" fill="{c}"/>
+</svg>"#,
+            s = size, w = line_width, o = offset, c = fill);
+        svg::Handle::from_memory(data.into_bytes())
+    }
+
+    /// Splits a list of questions into pages of (at most) `per_page` questions each.
+    pub fn pages(len: usize, per_page: Option<usize>) -> Vec<Range<usize>> {
+        let per_page = per_page.unwrap_or(len).max(1);
+        (0..len.max(1))
+            .step_by(per_page)
+            .map(|start| start..(start + per_page).min(len))
+            .collect()
+    }
+
+    const MATRIX_ROW_WIDTH: u16 = 360;
+    const MATRIX_COLUMN_WIDTH: u16 = 110;
+
+    const SLIDER_WIDTH: u16 = 500;
+    const SLIDER_LABEL_WIDTH: u16 = 160;
+
+    /// A row of texts (tick marks or anchor labels) of the given width, each centered under its
+    /// position on a slider that is indented by half a label width.
+    fn slider_marks<'a>(
+        marks: &[(f32, &str)],
+        range: &RangeInclusive<f32>,
+        size: u16,
+        width: u16
+    ) -> Row<'a, Message> {
+        let mut row = Row::new();
+        let mut end = 0;
+        for (x, text) in marks {
+            let fraction = (x - range.start()) / (range.end() - range.start());
+            let center = SLIDER_LABEL_WIDTH / 2 + (fraction * SLIDER_WIDTH as f32).round() as u16;
+            let start = (center - width / 2).max(end);
+            row = row
+                .push(Space::with_width(Length::Units(start - end)))
+                .push(Text::new(*text)
+                    .size(size)
+                    .width(Length::Units(width))
+                    .horizontal_alignment(HorizontalAlignment::Center));
+            end = start + width;
+        }
+        row
+    }
+
+    /// Option indices in the order they are displayed; options are in their given order unless shuffled.
+    pub fn display_order(option_order: &[usize], len: usize) -> Vec<usize> {
+        if option_order.is_empty() {
+            (0..len).collect()
+        } else {
+            option_order.to_vec()
+        }
+    }
+
+    /// The next page after `page` with any question shown, skipping pages of hidden questions.
+    pub fn next_page(pages: &[Range<usize>], shown: &[bool], page: usize) -> Option<usize> {
+        (page + 1..pages.len()).find(|&p| pages[p].clone().any(|i| shown[i]))
+    }
+
+    /// A question with its answer controls. After a failed submission (`check`), a required
+    /// question that is still unanswered is marked below its controls.
+    pub fn question<'a>(
+        quest: &'a mut Question,
+        index: usize,
+        check: bool,
+        style: &TextStyle,
+        global: &Global
+    ) -> Column<'a, Message> {
+        let missing = check && !quest.is_complete() && quest.hint().is_none();
+        let column = question_controls(quest, index, style, global);
+        if missing {
+            column.push(Text::new("This question requires an answer")
+                .size(global.text_size("NORMAL"))
+                .color(Color::from_rgb(0.8, 0.2, 0.2)))
+        } else {
+            column
+        }
+    }
+
+    fn question_controls<'a>(
+        quest: &'a mut Question,
+        index: usize,
+        style: &TextStyle,
+        global: &Global
+    ) -> Column<'a, Message> {
+        let hint = quest.hint();
         match quest {
             Question::SingleChoice {
                 prompt,
                 options,
-                answer
+                answer,
+                required,
+                option_order,
+                ..
             } => {
                 let mut row = Row::new()
                     // .width(Length::Fill)
                     .spacing(40);
-                for i in 0..options.len() {
+                for i in display_order(option_order, options.len()) {
                     let ind = index.clone();
                     row = row.push(Radio::new(
                         i,
@@ -771,24 +3241,30 @@ pub mod view {
                     // .width(Length::Fill)
                     .align_items(Align::Start)
                     .spacing(20)
-                    .push(Text::new(prompt.as_str())
-                        .size(global.text_size("XLARGE")))
+                    .push(Text::new(view::prompt(prompt, *required))
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color()))
                     .push(row)
             }
 
             Question::MultiChoice {
                 prompt,
                 options,
-                answer
+                none_option,
+                answer,
+                required,
+                option_order,
+                ..
             } => {
                 let mut row = Row::new()
                     // .width(Length::Fill)
                     .spacing(40);
-                for i in 0..options.len() {
+                let labels: Vec<_> = options.iter().chain(none_option.iter()).collect();
+                for i in display_order(option_order, labels.len()) {
                     let ind = index.clone();
                     row = row.push(Checkbox::new(
                         answer[i],
-                        options[i].clone(),
+                        labels[i].clone(),
                         move |_value| Message::UIEvent(
                             (0x01 + ind) as u16,
                             Value::Integer(i as i32)))
@@ -801,15 +3277,145 @@ pub mod view {
                     // .width(Length::Fill)
                     .align_items(Align::Start)
                     .spacing(20)
-                    .push(Text::new(prompt.as_str())
-                        .size(global.text_size("XLARGE")))
+                    .push(Text::new(view::prompt(prompt, *required))
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color()))
                     .push(row)
+                    .push(Text::new(hint.unwrap_or_default())
+                        .size(global.text_size("NORMAL")))
+            }
+
+            Question::Dropdown {
+                prompt,
+                options,
+                answer,
+                required,
+                handle,
+                ..
+            } => {
+                let ind = index;
+                let e_pick_list = PickList::new(
+                    handle,
+                    options.as_slice(),
+                    answer.clone(),
+                    move |value| Message::UIEvent(
+                        (0x01 + ind) as u16,
+                        Value::String(value)))
+                    .text_size(global.text_size("XLARGE"))
+                    .width(Length::Units(600));
+
+                Column::new()
+                    // .width(Length::Fill)
+                    .align_items(Align::Start)
+                    .spacing(20)
+                    .push(Text::new(view::prompt(prompt, *required))
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color()))
+                    .push(e_pick_list)
+            }
+
+            Question::Matrix {
+                prompt,
+                rows,
+                columns,
+                answer,
+                required,
+                ..
+            } => {
+                let mut header = Row::new()
+                    .push(Space::with_width(Length::Units(MATRIX_ROW_WIDTH)));
+                for column in columns.iter() {
+                    header = header.push(Text::new(column.as_str())
+                        .size(global.text_size("NORMAL"))
+                        .width(Length::Units(MATRIX_COLUMN_WIDTH))
+                        .horizontal_alignment(HorizontalAlignment::Center));
+                }
+
+                let mut grid = Column::new()
+                    .spacing(10)
+                    .push(header);
+                for (i, row) in rows.iter().enumerate() {
+                    let mut e_row = Row::new()
+                        .align_items(Align::Center)
+                        .push(Text::new(row.as_str())
+                            .size(global.text_size("LARGE"))
+                            .width(Length::Units(MATRIX_ROW_WIDTH)));
+                    for j in 0..columns.len() {
+                        // Each radio button answers one cell, encoded as `row * columns + column`
+                        let (ind, cell) = (index, (i * columns.len() + j) as i32);
+                        e_row = e_row.push(Container::new(Radio::new(
+                            j,
+                            "",
+                            answer[i],
+                            move |_value| Message::UIEvent(
+                                (0x01 + ind) as u16,
+                                Value::Integer(cell)))
+                            .size(global.text_size("LARGE")))
+                            .width(Length::Units(MATRIX_COLUMN_WIDTH))
+                            .center_x());
+                    }
+                    grid = grid.push(e_row);
+                }
+
+                Column::new()
+                    // .width(Length::Fill)
+                    .align_items(Align::Start)
+                    .spacing(20)
+                    .push(Text::new(view::prompt(prompt, *required))
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color()))
+                    .push(grid)
+            }
+
+            Question::Number {
+                prompt,
+                text,
+                required,
+                handle,
+                down_handle,
+                up_handle,
+                ..
+            } => {
+                let ind = index;
+                let e_text_input = TextInput::new(
+                    handle,
+                    "",
+                    text.as_str(),
+                    move |value| Message::UIEvent(
+                        (0x01 + ind) as u16,
+                        Value::String(value)))
+                    .size(global.text_size("XLARGE"))
+                    .width(Length::Units(200));
+                let e_down = button(down_handle, "-", global.text_size("XLARGE"))
+                    .on_press(Message::UIEvent((0x01 + ind) as u16, Value::Integer(-1)))
+                    .width(Length::Units(60));
+                let e_up = button(up_handle, "+", global.text_size("XLARGE"))
+                    .on_press(Message::UIEvent((0x01 + ind) as u16, Value::Integer(1)))
+                    .width(Length::Units(60));
+
+                Column::new()
+                    // .width(Length::Fill)
+                    .align_items(Align::Start)
+                    .spacing(20)
+                    .push(Text::new(view::prompt(prompt, *required))
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color()))
+                    .push(Row::new()
+                        .spacing(10)
+                        .align_items(Align::Center)
+                        .push(e_down)
+                        .push(e_text_input)
+                        .push(e_up))
+                    .push(Text::new(hint.unwrap_or_default())
+                        .size(global.text_size("NORMAL")))
             }
 
             Question::ShortAnswer {
                 prompt,
                 answer,
-                handle
+                handle,
+                required,
+                ..
             } => {
                 let ind = index.clone();
                 let e_text_input = TextInput::new(
@@ -826,16 +3432,23 @@ pub mod view {
                     // .width(Length::Fill)
                     .align_items(Align::Start)
                     .spacing(20)
-                    .push(Text::new(prompt.as_str())
-                        .size(global.text_size("XLARGE")))
+                    .push(Text::new(view::prompt(prompt, *required))
+                        .size(style.text_size(global, "XLARGE"))
+                        .color(style.color()))
                     .push(e_text_input)
+                    .push(Text::new(hint.unwrap_or_default())
+                        .size(global.text_size("NORMAL")))
             }
 
             Question::Slider {
-                prompt,
+                prompt: prompt_text,
                 answer,
                 range,
                 step,
+                required,
+                show_value,
+                ticks,
+                labels,
                 handle,
                 ..
             } => {
@@ -848,22 +3461,60 @@ pub mod view {
                         (0x01 + ind) as u16,
                         Value::Float(value)))
                     .step(*step)
-                    .width(Length::Units(500));
+                    .width(Length::Units(SLIDER_WIDTH));
+
+                let e_value = Text::new(if *show_value { answer.to_string() } else { String::new() })
+                    .size(global.text_size("LARGE"));
+                let e_prompt = Text::new(prompt(prompt_text, *required))
+                    .size(style.text_size(global, "XLARGE"))
+                    .color(style.color());
+
+                if ticks.is_none() && labels.is_empty() {
+                    return Column::new()
+                        // .width(Length::Fill)
+                        .align_items(Align::Start)
+                        .spacing(20)
+                        .push(e_prompt)
+                        .push(Row::new()
+                            .spacing(20)
+                            .push(Text::new(range.start().to_string())
+                                .size(global.text_size("LARGE")))
+                            .push(e_slider)
+                            .push(Text::new(range.end().to_string())
+                                .size(global.text_size("LARGE")))
+                            .push(e_value)
+                        );
+                }
+
+                // Ticks and labels are centered under their positions on the slider, and the
+                // slider is indented by half a label so that the end labels fit
+                let mut scale = Column::new()
+                    .spacing(5)
+                    .push(Row::new()
+                        .spacing(20)
+                        .push(Space::with_width(Length::Units(SLIDER_LABEL_WIDTH / 2)))
+                        .push(e_slider)
+                        .push(e_value));
+                if let Some(interval) = ticks {
+                    let count = ((range.end() - range.start()) / *interval + 1e-3).floor() as usize;
+                    let marks = (0..=count)
+                        .map(|i| (range.start() + *interval * i as f32, "|"))
+                        .collect::<Vec<_>>();
+                    scale = scale.push(slider_marks(&marks, range, global.text_size("NORMAL"), 10));
+                }
+                if !labels.is_empty() {
+                    let marks = labels.iter()
+                        .map(|(x, label)| (*x, label.as_str()))
+                        .collect::<Vec<_>>();
+                    scale = scale.push(slider_marks(&marks, range, global.text_size("LARGE"), SLIDER_LABEL_WIDTH));
+                }
 
                 Column::new()
                     // .width(Length::Fill)
                     .align_items(Align::Start)
                     .spacing(20)
-                    .push(Text::new(prompt.as_str())
-                        .size(global.text_size("XLARGE")))
-                    .push(Row::new()
-                        .spacing(20)
-                        .push(Text::new(range.start().to_string())
-                            .size(global.text_size("LARGE")))
-                        .push(e_slider)
-                        .push(Text::new(range.end().to_string())
-                            .size(global.text_size("LARGE")))
-                    )
+                    .push(e_prompt)
+                    .push(scale)
             }
         }
     }
@@ -875,40 +3526,294 @@ pub mod run {
     use rodio::OutputStreamHandle;
     use super::*;
 
-    pub async fn interruptible_timer(id: ID, comm: Comm, mut timer: u32) -> Message {
-        while timer > 0 {
-            let t = if timer >= 1000 { 1000 } else { timer };
-            std::thread::sleep(Duration::from_millis(t as u64));
+    pub async fn interruptible_timer(id: ID, comm: Comm, mut timer: Duration) -> Message {
+        while !timer.is_zero() {
+            let t = timer.min(Duration::from_secs(1));
+            std::thread::sleep(t);
             match comm.1.try_recv() {
                 Ok(Message::Wrap) |
                 Ok(Message::Interrupt) |
                 Err(TryRecvError::Disconnected) => {
                     return Message::Null;
                 },
+                Ok(Message::Pause) => match wait_for_resume(&comm.1) {
+                    Ok(true) => (),
+                    Ok(false) => return Message::Null,
+                    Err(e) => return Message::Error(e),
+                },
                 Err(TryRecvError::Empty) => (),
-                Ok(msg) => panic!("Unexpected message received: {:?}", msg),
+                Ok(msg) => return Message::Error(format!("Unexpected message received by timer: {:?}", msg)),
             }
             timer -= t;
         }
         Message::ActionComplete(id)
     }
 
-    pub async fn audio(id: ID, comm: Comm, source: PathBuf, use_trigger: bool, stream_handle: OutputStreamHandle) -> Message {
-        let trigger = source.with_extension("trig.wav");
-        let trigger = if use_trigger { Some(trigger.as_path()) } else { None };
+    pub async fn timeout(id: ID, comm: Comm, timer: Duration) -> Message {
+        match interruptible_timer(id, comm, timer).await {
+            Message::ActionComplete(id) => Message::Timeout(id),
+            msg => msg,
+        }
+    }
+
+    pub async fn wait_serial(
+        id: ID,
+        rx: Receiver,
+        serial: SerialInput,
+        count: u32,
+        pulses: Arc<Mutex<Vec<String>>>,
+        block_onset: Option<Instant>
+    ) -> Message {
+        let result = hardware::wait_for_serial(&serial.port, serial.baud, serial.code, &rx, || {
+            let time = block_onset.map(event_time).unwrap_or_else(timestamp);
+            record_pulse(&pulses, time, "serial", count)
+        });
+        // A port that fails stops the block with the error (as `report` does), rather than
+        // leaving the block waiting for pulses that cannot arrive
+        match result {
+            Ok(true) => Message::ActionComplete(id),
+            Ok(false) => Message::Null,
+            Err(e) => Message::Error(e),
+        }
+    }
+
+    /// Sends a trigger code (opening the port first if needed, off the UI thread) and records
+    /// the outcome. A trigger that cannot be sent is only a warning, as the block can go on.
+    pub async fn serial_trigger(id: ID, port: String, baud: u32, code: u8, log_prefix: String, complete: bool) -> Message {
+        let error = hardware::send_trigger(&port, baud, code).err();
+        if let Some(e) = &error {
+            println!("Warning: {}", e);
+        }
+        async_write_to_file(
+            format!("{}.trigger", log_prefix),
+            TriggerRecord { port, code, error },
+            "Failed to write serial trigger record to output file");
+        if complete {
+            Message::ActionComplete(id)
+        } else {
+            Message::Null
+        }
+    }
+
+    /// Scheduled onset of a frame, relative to the onset of the first frame, so that timing
+    /// errors do not accumulate over the sequence.
+    pub fn frame_deadline(onset: Instant, rate_hz: f32, frame: usize) -> Instant {
+        onset + Duration::from_secs_f64(frame as f64 / rate_hz as f64)
+    }
+
+    fn wait_until(deadline: Instant, rx: &Receiver) -> Result<bool, String> {
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        match rx.try_recv() {
+            Err(TryRecvError::Empty) => Ok(true),
+            Ok(Message::Pause) => wait_for_resume(rx),
+            _ => Ok(false),
+        }
+    }
+
+    /// Schedules the next update of a break: every whole second (for its clock), when `min`
+    /// is reached (to enable continuing), or its end at `max`, where it times out. Updates carry
+    /// the elapsed time in milliseconds.
+    pub fn rest(
+        id: ID,
+        rx: Receiver,
+        onset: Instant,
+        elapsed: Duration,
+        min: Duration,
+        max: Option<Duration>
+    ) -> Command<Message> {
+        let mut next = Duration::from_secs(elapsed.as_secs() + 1);
+        if min > elapsed {
+            next = next.min(min);
+        }
+        match max {
+            Some(max) if next >= max => {
+                Command::perform(frame_timeout(id, rx, onset + max), |msg| msg)
+            }
+            _ => {
+                Command::perform(frame(id, rx, onset + next, next.as_millis() as usize), |msg| msg)
+            }
+        }
+    }
+
+    pub async fn frame(id: ID, rx: Receiver, deadline: Instant, frame: usize) -> Message {
+        match wait_until(deadline, &rx) {
+            Ok(true) => Message::Value(id.clone(), id, 0x01, Value::Integer(frame as i32)),
+            Ok(false) => Message::Null,
+            Err(e) => Message::Error(e),
+        }
+    }
+
+    pub async fn last_frame(id: ID, rx: Receiver, deadline: Instant) -> Message {
+        match wait_until(deadline, &rx) {
+            Ok(true) => Message::ActionComplete(id),
+            Ok(false) => Message::Null,
+            Err(e) => Message::Error(e),
+        }
+    }
+
+    /// Times out an action at a deadline that is counted in frames, like `timeout`.
+    pub async fn frame_timeout(id: ID, rx: Receiver, deadline: Instant) -> Message {
+        match last_frame(id, rx, deadline).await {
+            Message::ActionComplete(id) => Message::Timeout(id),
+            msg => msg,
+        }
+    }
+
+    pub async fn audio(
+        id: ID,
+        comm: Comm,
+        source: PathBuf,
+        trigger: Trigger,
+        repeats: Option<u32>,
+        volume: f32,
+        stream_handle: OutputStreamHandle
+    ) -> Message {
+        Message::Playback(id, play_audio(comm, source.as_path(), &trigger, repeats, volume, stream_handle))
+    }
+
+    pub async fn audio_sequence(
+        id: ID,
+        comm: Comm,
+        sources: Vec<PathBuf>,
+        intervals: Vec<Duration>,
+        volume: f32,
+        stream_handle: OutputStreamHandle
+    ) -> Message {
+        Message::Playback(id, play_sequence(comm, &sources, &intervals, volume, stream_handle))
+    }
+}
+
+pub(crate) mod deserialize {
+    use std::fmt;
+    use std::time::Duration;
+    use serde::{de, Deserialize};
+
+    /// Parses a duration: a plain number of milliseconds, or a number with a unit of `ms`, `s`,
+    /// `m` (or `min`), or `h`, e.g. "200ms", "1.5s", or "2m".
+    pub fn parse(v: &str) -> Result<Duration, String> {
+        let v = v.trim();
+        let split = v.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(v.len());
+        let (number, unit) = (&v[..split], v[split..].trim());
+        let scale = match unit {
+            "" | "ms" => 0.001,
+            "s" | "sec" => 1.0,
+            "m" | "min" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(format!("Invalid unit `{}` in duration \"{}\"; use ms, s, m, or h", unit, v)),
+        };
+        let secs = number.parse::<f64>()
+            .map_err(|_| format!("Invalid duration \"{}\"; use e.g. 500, 200ms, 1.5s, or 2m", v))?
+            * scale;
+        Duration::try_from_secs_f64(secs).map_err(|_| format!("Duration \"{}\" is too long", v))
+    }
+
+    struct DurationVisitor;
+
+    impl<'de> de::Visitor<'de> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a duration in milliseconds, or with a unit like 200ms, 1.5s, or 2m")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Duration::from_millis(v))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            u64::try_from(v)
+                .map_err(|_| E::custom(format!("Duration should not be negative: {}", v)))
+                .and_then(|v| self.visit_u64(v))
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            parse(v).map_err(E::custom)
+        }
+    }
+
+    struct OptDurationVisitor;
+
+    impl<'de> de::Visitor<'de> for OptDurationVisitor {
+        type Value = Option<Duration>;
 
-        match play_audio(comm, source.as_path(), trigger, stream_handle) {
-            Ok(()) => Message::ActionComplete(id),
-            Err(()) => Message::Null,
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a duration, or nothing")
         }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+            duration(deserializer).map(Some)
+        }
+    }
+
+    // A duration in a list of durations
+    #[derive(Deserialize)]
+    struct Item(#[serde(deserialize_with="duration")] Duration);
+
+    pub fn duration<'de, D>(deserializer: D) -> Result<Duration, D::Error> where
+        D: de::Deserializer<'de>
+    {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+
+    /// Like `duration`, but also takes `~` (null) for no duration.
+    pub fn opt_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error> where
+        D: de::Deserializer<'de>
+    {
+        deserializer.deserialize_option(OptDurationVisitor)
+    }
+
+    pub fn durations<'de, D>(deserializer: D) -> Result<Vec<Duration>, D::Error> where
+        D: de::Deserializer<'de>
+    {
+        Vec::<Item>::deserialize(deserializer).map(|items| items.into_iter().map(|Item(d)| d).collect())
     }
 }
 
 mod default {
     use super::*;
 
-    pub fn timer() -> u32 {
-        0
+    pub fn wait_prompt() -> String {
+        "Waiting for scanner...".to_string()
+    }
+
+    pub fn break_prompt() -> String {
+        "Take a break. Continue when you are ready.".to_string()
+    }
+
+    pub fn pulse_count() -> u32 {
+        1
+    }
+
+    pub fn baud() -> u32 {
+        9600
+    }
+
+    pub fn feedback_timer() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    pub fn fixation_size() -> u16 {
+        40
+    }
+
+    pub fn fixation_line_width() -> u16 {
+        4
+    }
+
+    pub fn fixation_color() -> String {
+        "black".to_string()
     }
 
     pub fn slider_range() -> RangeInclusive<f32> {
@@ -918,24 +3823,76 @@ mod default {
     pub fn slider_step() -> f32 {
         0.01
     }
+
+    pub fn number_step() -> f64 {
+        1.0
+    }
 }
 
-mod serialize {
+pub(crate) mod serialize {
+    use std::time::Duration;
     use serde::ser::SerializeMap;
-    use serde::Serializer;
+    use serde::{Serialize, Serializer};
+
+    // Durations are written back (e.g. to `task.log`) in milliseconds, as they were read
+    // before they could be given with units: as integers if they are whole
+    struct Millis<'a>(&'a Duration);
+
+    impl Serialize for Millis<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.0.subsec_nanos() % 1_000_000 {
+                0 => serializer.serialize_u64(self.0.as_millis() as u64),
+                _ => serializer.serialize_f64(self.0.as_secs_f64() * 1000.0),
+            }
+        }
+    }
+
+    pub fn duration<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        Millis(duration).serialize(serializer)
+    }
+
+    pub fn opt_duration<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_some(&Millis(duration)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn durations<S: Serializer>(durations: &[Duration], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(durations.iter().map(Millis))
+    }
+
+    /// A number answer, written as an integer if it is whole (e.g. an age of 25, not 25.0).
+    pub fn number<S: Serializer>(answer: &Option<f64>, s: S) -> Result<S::Ok, S::Error> {
+        match answer {
+            Some(x) if x.fract() == 0.0 && x.abs() < 1e15 => s.serialize_i64(*x as i64),
+            Some(x) => s.serialize_f64(*x),
+            None => s.serialize_none(),
+        }
+    }
 
     pub mod question {
         use super::*;
+        use crate::action::ShowIf;
 
+        // The signature is dictated by serde (one argument per variant field)
+        #[allow(clippy::too_many_arguments)]
         pub fn single_choice<S: Serializer>(
             prompt: &str,
             options: &Vec<String>,
+            _required: &bool,
+            _show_if: &Option<ShowIf>,
+            _shuffle_options: &bool,
             answer: &Option<usize>,
+            option_order: &[usize],
             s: S,
         ) -> Result<S::Ok, S::Error> {
             let mut map = s.serialize_map(Some(3))?;
             map.serialize_entry("prompt", prompt)?;
             map.serialize_entry("options", options)?;
+            if !option_order.is_empty() {
+                map.serialize_entry("displayed", &displayed(options, option_order))?;
+            }
             if let Some(ans) = answer {
                 map.serialize_entry("answer", options[*ans].as_str())?;
             } else {
@@ -944,20 +3901,61 @@ mod serialize {
             map.end()
         }
 
+        pub fn matrix<S: Serializer>(
+            prompt: &str,
+            rows: &[String],
+            columns: &[String],
+            _required: &bool,
+            _show_if: &Option<ShowIf>,
+            answer: &[Option<usize>],
+            s: S
+        ) -> Result<S::Ok, S::Error> {
+            let mut map = s.serialize_map(Some(4))?;
+            let answer: Vec<_> = answer.iter()
+                .map(|column| column.map_or("~", |j| columns[j].as_str()))
+                .collect();
+            map.serialize_entry("prompt", prompt)?;
+            map.serialize_entry("rows", rows)?;
+            map.serialize_entry("columns", columns)?;
+            map.serialize_entry("answer", &answer)?;
+            map.end()
+        }
+
+        /// Options in the (shuffled) order they were displayed.
+        fn displayed<'a>(options: &'a [String], option_order: &[usize]) -> Vec<&'a str> {
+            option_order.iter().map(|&i| options[i].as_str()).collect()
+        }
+
+        // The signature is dictated by serde (one argument per variant field)
+        #[allow(clippy::too_many_arguments)]
         pub fn multi_choice<S: Serializer>(
             prompt: &str,
             options: &Vec<String>,
+            _required: &bool,
+            _min_selections: &Option<usize>,
+            _max_selections: &Option<usize>,
+            none_option: &Option<String>,
+            _show_if: &Option<ShowIf>,
+            _shuffle_options: &bool,
             answer: &Vec<bool>,
+            option_order: &[usize],
             s: S
         ) -> Result<S::Ok, S::Error> {
-            let mut map = s.serialize_map(Some(3))?;
-            let answer: Vec<_> = options.iter()
-                .enumerate()
-                .filter(|(i, _)| answer[*i])
-                .map(|(_, o)| o.clone())
+            let mut map = s.serialize_map(None)?;
+            let choices: Vec<_> = options.iter().chain(none_option).cloned().collect();
+            let answer: Vec<_> = choices.iter()
+                .zip(answer)
+                .filter(|(_, &checked)| checked)
+                .map(|(o, _)| o.clone())
                 .collect();
             map.serialize_entry("prompt", prompt)?;
             map.serialize_entry("options", options)?;
+            if let Some(none) = none_option {
+                map.serialize_entry("none_option", none)?;
+            }
+            if !option_order.is_empty() {
+                map.serialize_entry("displayed", &displayed(&choices, option_order))?;
+            }
             map.serialize_entry("answer", &answer)?;
             map.end()
         }
@@ -965,27 +3963,254 @@ mod serialize {
 }
 
 pub mod flow {
+    use rand::seq::SliceRandom;
     use super::*;
 
+    /// Whether an action is one of the `entry`/`exit` gates added around a list of actions.
+    pub fn is_gate(id: &str) -> bool {
+        ["entry", "exit"].iter().any(|gate| id == *gate || id.ends_with(&format!("~{}", gate)))
+    }
+
+    /// Order in which a list of actions is chained by default: as written, or shuffled.
+    /// Positions (and so default IDs) are unaffected by shuffling.
+    pub fn order(len: usize, shuffle: bool, rng: &mut StdRng) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..len).collect();
+        if shuffle {
+            order.shuffle(rng);
+        }
+        order
+    }
+
+    pub fn read_template(task_dir: &Path, source: &str) -> Result<String, Error> {
+        let file = template(task_dir, source)?;
+        let mut file = File::open(file)
+            .map_err(|e| Error::io(format!("Failed to open template file: {:?}", source), e))?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)
+            .map_err(|e| Error::io(format!("Invalid UTF-8 text in template file: {:?}", source), e))?;
+        Ok(content)
+    }
+
+    pub const RESPONSE_PREFIX: &str = "response:";
+    pub const SAMPLE_PREFIX: &str = "sample:";
+    pub const CONFIG_PREFIX: &str = "config:";
+
+    fn is_runtime(param: &str) -> bool {
+        [RESPONSE_PREFIX, SAMPLE_PREFIX, CONFIG_PREFIX]
+            .iter()
+            .any(|prefix| param.starts_with(prefix))
+    }
+
+    fn braced(content: &str) -> Vec<String> {
+        let mut params = vec![];
+        let mut rest = content;
+        while let Some(start) = rest.find("{{") {
+            rest = &rest[start+2..];
+            if let Some(end) = rest.find("}}") {
+                params.push(rest[..end].to_string());
+                rest = &rest[end+2..];
+            } else {
+                break;
+            }
+        }
+        params
+    }
+
+    /// Template parameters, i.e. `{{name}}` placeholders that are substituted at load time.
+    pub fn placeholders(content: &str) -> HashSet<String> {
+        braced(content)
+            .into_iter()
+            .filter(|p| !is_runtime(p))
+            .collect()
+    }
+
+    /// Whether the text contains placeholders that can only be resolved at runtime.
+    pub fn has_runtime_refs(content: &str) -> bool {
+        braced(content)
+            .iter()
+            .any(|p| is_runtime(p))
+    }
+
+    /// Sample references, i.e. `{{sample:pool}}` placeholders that are substituted with an
+    /// item drawn without replacement from a session-wide stimulus pool right before running.
+    pub fn sample_refs(content: &str) -> Vec<String> {
+        braced(content)
+            .into_iter()
+            .filter_map(|p| p.strip_prefix(SAMPLE_PREFIX).map(String::from))
+            .collect()
+    }
+
+    pub fn sample_ref(pool: &str) -> String {
+        format!("{{{{{}{}}}}}", SAMPLE_PREFIX, pool)
+    }
+
+    /// Config references, i.e. `{{config:name}}` placeholders that are substituted with the
+    /// value of a task-defined configuration option chosen on the configuration screen.
+    pub fn config_refs(content: &str) -> Vec<String> {
+        braced(content)
+            .into_iter()
+            .filter_map(|p| p.strip_prefix(CONFIG_PREFIX).map(String::from))
+            .collect()
+    }
+
+    pub fn config_ref(name: &str) -> String {
+        format!("{{{{{}{}}}}}", CONFIG_PREFIX, name)
+    }
+
+    /// Response references, i.e. `{{response:ID}}` or `{{response:ID:N}}` placeholders that
+    /// are substituted with the answer of a previous action right before an action runs.
+    pub fn response_refs(content: &str) -> Vec<(ID, Option<usize>)> {
+        braced(content)
+            .into_iter()
+            .filter_map(|p| p.strip_prefix(RESPONSE_PREFIX).map(String::from))
+            .map(|r| match r.split_once(':') {
+                Some((id, n)) => (id.to_string(), n.parse().ok()),
+                None => (r, None),
+            })
+            .collect()
+    }
+
+    pub fn response_ref(id: &str, index: Option<usize>) -> String {
+        match index {
+            Some(n) => format!("{{{{{}{}:{}}}}}", RESPONSE_PREFIX, id, n),
+            None => format!("{{{{{}{}}}}}", RESPONSE_PREFIX, id),
+        }
+    }
+
+    pub type Params = HashMap<String, String>;
+
+    pub fn read_csv(task_dir: &Path, file: &str) -> Result<(Vec<String>, Vec<Params>), Error> {
+        let path = resource(task_dir, file)?;
+        let delimiter = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tsv") => b'\t',
+            _ => b',',
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .trim(csv::Trim::All)
+            .from_path(&path)
+            .or(Err(Error::Io(format!("Failed to open CSV file: {:?}", file), None)))?;
+
+        let columns: Vec<String> = reader.headers()
+            .map_err(|e| Error::Io(format!("Failed to read header of CSV file {:?}: {}", file, e), None))?
+            .iter()
+            .map(String::from)
+            .collect();
+
+        let mut rows = vec![];
+        for (i, record) in reader.records().enumerate() {
+            let record = record.map_err(|e| Error::Io(format!(
+                "Failed to read row {} of CSV file {:?}: {}", i+1, file, e), None))?;
+            rows.push(columns.iter().cloned().zip(record.iter().map(String::from)).collect());
+        }
+        Ok((columns, rows))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn expand(
+        actions: &mut Vec<Action>,
+        info: &mut Info,
+        depth: u16,
+        task_dir: &Path,
+        config: &Config,
+        source: &str,
+        shuffle: bool,
+        rng: &mut StdRng,
+    ) -> Result<(), Error> {
+        let mut last_action = None;
+        let mut ids = HashSet::new();
+        for i in order(actions.len(), shuffle, rng) {
+            let action = &mut actions[i];
+            action.init(i+1, &last_action, 1+depth, task_dir, config, rng)?;
+            last_action = Some(action.id());
+
+            let id = action.id();
+            if ids.contains(&id) {
+                return Err(Error::InvalidGraph(format!("Action ID `{}` used more than once in template: {}", id, source)));
+            } else {
+                ids.insert(id);
+            }
+        }
+
+        let mut i: usize = 0;
+        while i < actions.len() {
+            if let Some(inners) = actions[i].inner_actions().cloned() {
+                actions.remove(i);
+                for inner in inners.into_iter() {
+                    actions.insert(i, inner);
+                    i += 1;
+                }
+            } else {
+                i += 1;
+            }
+        }
+
+        for action in actions.iter_mut() {
+            let inner_info = action.info_mut();
+            inner_info.id = format!("{}~{}", info.id, inner_info.id);
+            if let Some(after) = &mut inner_info.after {
+                *after = after.iter().map(|x| format!("{}~{}", info.id, x)).collect();
+                if let Some(ids) = &info.after {
+                    after.extend(ids.clone());
+                }
+            } else {
+                info.after = info.after.clone();
+            }
+            if let Some(id) = &info.with {
+                info.with = Some(format!("{}~{}", info.id, id));
+            } else {
+                info.with = info.with.clone();
+            }
+        }
+
+        add_gates(actions, info.after.clone(), info.with.clone())?;
+
+        let len = actions.len();
+        actions[0].set_id(&format!("{}~entry", info.id));
+        actions[len-1].set_id(&format!("{}~exit", info.id));
+        Ok(())
+    }
+
     pub fn add_gates(
         actions: &mut Vec<Action>,
         after: Option<HashSet<ID>>,
         with: Option<ID>
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let entry = Action::Nothing {
             info: Info {
                 id: "entry".to_string(),
                 with: with.clone(),
                 after: after.clone(),
-                monitor_kb: false,
+                monitor_kb: None,
+                keys: None,
+                log_invalid_keys: false,
                 keystrokes: vec![],
+                held_keys: vec![],
+                key_holds: vec![],
+                branch_on: Default::default(),
+                on_timeout: None,
+                track_mouse: false,
+                cursor: vec![],
                 background: None,
                 background_image: None,
-                timeout: Some(0),
+                background_color: None,
+                backdrop: None,
+                style: None,
+                timeout: Some(Duration::ZERO),
+                frames: None,
+                min_rt: None,
                 dependents: Default::default(),
                 successors: Default::default(),
                 expired: Some(true),
+                missing: None,
+                onset: None,
+                block_onset: None,
+                responses: vec![],
+                response_rt: None,
+                timed_out: false,
                 log_prefix: "".to_string(),
+                partial: vec![],
                 comm: vec![]
             }
         };
@@ -1010,15 +4235,35 @@ pub mod flow {
                 id: "exit".to_string(),
                 with: with.clone(),
                 after: Some(finalists),
-                monitor_kb: false,
+                monitor_kb: None,
+                keys: None,
+                log_invalid_keys: false,
                 keystrokes: vec![],
+                held_keys: vec![],
+                key_holds: vec![],
+                branch_on: Default::default(),
+                on_timeout: None,
+                track_mouse: false,
+                cursor: vec![],
                 background: None,
                 background_image: None,
-                timeout: Some(0),
+                background_color: None,
+                backdrop: None,
+                style: None,
+                timeout: Some(Duration::ZERO),
+                frames: None,
+                min_rt: None,
                 dependents: Default::default(),
                 successors: Default::default(),
                 expired: Some(true),
+                missing: None,
+                onset: None,
+                block_onset: None,
+                responses: vec![],
+                response_rt: None,
+                timed_out: false,
                 log_prefix: "".to_string(),
+                partial: vec![],
                 comm: vec![]
             }
         };
@@ -1028,3 +4273,220 @@ pub mod flow {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[derive(Deserialize, Serialize)]
+    struct Timing {
+        #[serde(deserialize_with="deserialize::duration", serialize_with="serialize::duration")]
+        duration: Duration,
+        #[serde(default, deserialize_with="deserialize::opt_duration", serialize_with="serialize::opt_duration")]
+        timeout: Option<Duration>,
+        #[serde(default, deserialize_with="deserialize::durations", serialize_with="serialize::durations")]
+        intervals: Vec<Duration>,
+    }
+
+    fn question(yaml: &str) -> Question {
+        let mut question: Question = serde_yaml::from_str(yaml).unwrap();
+        question.init().unwrap();
+        question
+    }
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(deserialize::parse("500"), Ok(Duration::from_millis(500)));
+        assert_eq!(deserialize::parse("200ms"), Ok(Duration::from_millis(200)));
+        assert_eq!(deserialize::parse("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(deserialize::parse("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(deserialize::parse(" 1 h "), Ok(Duration::from_secs(3600)));
+        assert!(deserialize::parse("3 days").is_err());
+        assert!(deserialize::parse("ms").is_err());
+        assert!(deserialize::parse("-5s").is_err());
+    }
+
+    #[test]
+    fn reads_and_writes_durations_in_milliseconds() {
+        let timing: Timing = serde_yaml::from_str("{duration: 1.5s, timeout: ~, intervals: [500, 250ms, 0.25]}").unwrap();
+        assert_eq!(timing.duration, Duration::from_millis(1500));
+        assert_eq!(timing.timeout, None);
+        assert_eq!(timing.intervals, vec![
+            Duration::from_millis(500),
+            Duration::from_millis(250),
+            Duration::from_micros(250),
+        ]);
+        let json = serde_json::to_string(&timing).unwrap();
+        assert_eq!(json, r#"{"duration":1500,"timeout":null,"intervals":[500,250,0.25]}"#);
+
+        let timing: Timing = serde_yaml::from_str("{duration: 0, timeout: 2s}").unwrap();
+        assert_eq!(timing.timeout, Some(Duration::from_secs(2)));
+        assert!(serde_yaml::from_str::<Timing>("{duration: -1}").is_err());
+    }
+
+    fn validation(yaml: &str) -> Validation {
+        let mut validation: Validation = serde_yaml::from_str(yaml).unwrap();
+        validation.init().unwrap();
+        validation
+    }
+
+    #[test]
+    fn validates_short_answers() {
+        let age = validation("{integer: true, min: 18, max: 99}");
+        assert!(age.check("42").is_ok());
+        assert!(age.check(" ").is_ok());
+        assert_eq!(age.check("4.5"), Err("Enter a whole number".to_string()));
+        assert_eq!(age.check("17"), Err("Enter a number between 18 and 99".to_string()));
+
+        let id = validation("{pattern: 'S[0-9]{2}', max_length: 3, message: Enter an ID like S01}");
+        assert!(id.check("S01").is_ok());
+        assert_eq!(id.check("S1"), Err("Enter an ID like S01".to_string()));
+        assert_eq!(id.check("S001"), Err("Enter an ID like S01".to_string()));
+
+        let mut empty: Validation = serde_yaml::from_str("{min: 5, max: 1}").unwrap();
+        assert!(empty.init().is_err());
+        let mut fraction: Validation = serde_yaml::from_str("{integer: true, min: 0.5, max: 0.7}").unwrap();
+        assert!(fraction.init().is_err());
+    }
+
+    #[test]
+    fn draws_random_answers_within_the_bounds() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for yaml in ["{integer: true, min: 100.5}", "{integer: true, max: -3}", "{min: 0.5, max: 0.7}", "{max: 50}"] {
+            let validation = validation(yaml);
+            for _ in 0..20 {
+                let answer = validation.random_answer(&mut rng);
+                assert!(validation.check(&answer).is_ok(), "{} for {}", answer, yaml);
+            }
+        }
+        let number = question("{type: number, prompt: Depth, max: -10}");
+        for _ in 0..20 {
+            let answer: f64 = number.random_answer(&mut rng).parse().unwrap();
+            assert!(answer <= -10.0, "{}", answer);
+        }
+    }
+
+    #[test]
+    fn sets_valid_answers() {
+        let mut single = question("{type: single_choice, prompt: Hand, options: [Left, Right]}");
+        single.set_answer("Right").unwrap();
+        assert_eq!(single.answer().as_deref(), Some("Right"));
+        assert!(single.is_complete());
+
+        let mut multi = question("{type: multi_choice, prompt: Days, options: [Mon, Tue, Wed]}");
+        multi.set_answer("Mon, Wed").unwrap();
+        assert_eq!(multi.answer().as_deref(), Some("Mon, Wed"));
+
+        let mut slider = question("{type: slider, prompt: Mood, range: [0, 10]}");
+        slider.set_answer("7.5").unwrap();
+        assert_eq!(slider.answer().as_deref(), Some("7.5"));
+    }
+
+    #[test]
+    fn keeps_the_none_option_apart_from_the_options() {
+        let yaml = "{type: multi_choice, prompt: Days, options: [Mon, Tue], none_option: Neither}";
+        let mut multi = question(yaml);
+        multi.set_answer("Neither").unwrap();
+        assert_eq!(multi.answer().as_deref(), Some("Neither"));
+        let json = serde_json::to_string(&multi).unwrap();
+        assert_eq!(json, r#"{"type":"multi_choice","prompt":"Days","options":["Mon","Tue"],"none_option":"Neither","answer":["Neither"]}"#);
+
+        let mut multi: Question = serde_yaml::from_str(yaml).unwrap();
+        multi.init().unwrap();
+        multi.init().unwrap();
+        assert!(multi.set_answer("Mon, Tue").is_ok());
+        assert_eq!(multi.answer().as_deref(), Some("Mon, Tue"));
+    }
+
+    #[test]
+    fn rejects_more_selections_than_options() {
+        let mut multi: Question = serde_yaml::from_str(
+            "{type: multi_choice, prompt: Days, options: [Mon, Tue], max_selections: 3}").unwrap();
+        assert!(matches!(multi.init(), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn sets_selection_responses_by_label_or_number() {
+        let mut selection: Action = serde_yaml::from_str(
+            "{id: s, type: selection, prompt: Pick, options: [ Left, Right ]}").unwrap();
+        selection.set_response(&["Right".to_string()]).unwrap();
+        assert_eq!(selection.response(None).as_deref(), Some("Right"));
+        selection.set_response(&["1".to_string()]).unwrap();
+        assert_eq!(selection.response(None).as_deref(), Some("Left"));
+        for answer in ["0", "3", "Middle"] {
+            assert!(selection.set_response(&[answer.to_string()]).is_err(), "{}", answer);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_answers() {
+        let mut single = question("{type: single_choice, prompt: Hand, options: [Left, Right]}");
+        assert!(matches!(single.set_answer("Both"), Err(Error::Invalid(_))));
+        assert_eq!(single.answer(), None);
+
+        let mut multi = question("{type: multi_choice, prompt: Days, options: [Mon, Tue, Wed]}");
+        assert!(multi.set_answer("Mon, Sun").is_err());
+
+        let mut slider = question("{type: slider, prompt: Mood, range: [0, 10]}");
+        assert!(slider.set_answer("11").is_err());
+        assert!(slider.set_answer("high").is_err());
+    }
+
+    /// A task directory under the temporary directory, with the given files in it.
+    fn task_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join("task-runner-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        for (file, content) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    /// Prompts of the instructions that an action from a CSV file expands to.
+    fn expand_csv(dir: &Path, yaml: &str) -> Result<Vec<String>, Error> {
+        let mut action: Action = serde_yaml::from_str(yaml).unwrap();
+        action.init(1, &None, 0, dir, &Config::default(), &mut StdRng::seed_from_u64(1))?;
+        Ok(action.inner_actions().unwrap()
+            .iter()
+            .filter_map(|action| match action {
+                Action::Instruction { prompt, .. } => Some(prompt.clone()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    const TRIAL: &str = "- { id: show, type: instruction, prompt: \"{{word}} ({{condition}})\" }\n";
+
+    #[test]
+    fn expands_a_template_per_csv_row() {
+        let dir = task_dir("from-csv", &[
+            ("templates/trial.yml", TRIAL),
+            ("resources/trials.csv", "word, condition\ncat, animal\nhammer, tool\n"),
+            ("resources/extra.csv", "word,condition,extra\ncat,animal,1\n"),
+            ("resources/partial.csv", "word\ncat\n"),
+        ]);
+        let prompts = expand_csv(&dir, "{id: trials, type: from_csv, file: trials.csv, template: trial}").unwrap();
+        assert_eq!(prompts, ["cat (animal)", "hammer (tool)"]);
+
+        let error = expand_csv(&dir, "{id: trials, type: from_csv, file: extra.csv, template: trial}").unwrap_err();
+        assert!(error.to_string().contains("CSV column \"extra\""), "{}", error);
+        let error = expand_csv(&dir, "{id: trials, type: from_csv, file: partial.csv, template: trial}").unwrap_err();
+        assert!(error.to_string().contains("\"condition\" in \"trial\" has neither a column"), "{}", error);
+    }
+
+    #[test]
+    fn shares_fixed_parameters_across_tsv_rows() {
+        let dir = task_dir("from-tsv", &[
+            ("templates/trial.yml", TRIAL),
+            ("resources/words.tsv", "word\nred apple\ngreen pear\n"),
+        ]);
+        let prompts = expand_csv(&dir, "{id: trials, type: from_csv, file: words.tsv, template: trial, params: {condition: fruit}}").unwrap();
+        assert_eq!(prompts, ["red apple (fruit)", "green pear (fruit)"]);
+
+        let error = expand_csv(&dir, "{id: trials, type: from_csv, file: words.tsv, template: trial, params: {condition: fruit, word: kiwi}}").unwrap_err();
+        assert!(error.to_string().contains("both as a fixed parameter and as a column"), "{}", error);
+    }
+}
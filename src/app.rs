@@ -1,15 +1,18 @@
-use iced::{Application, Clipboard, Column, Command, Container, Element, Length, Row, Space, Subscription};
+use iced::{window, Application, Clipboard, Color, Column, Command, Container, Element, Length, Row, Space, Subscription};
 use iced_native::subscription;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use crate::task::Task;
 use crate::comm::{Message, CommLink};
+use crate::remote::RemoteLink;
+use crate::replay::ReplayLink;
 use crate::global::IntOrFloat;
+use crate::logger;
+use crate::style;
 
 pub struct App
 {
     task: Task,
-    last_esc: Instant,
 }
 
 impl Application for App {
@@ -19,45 +22,68 @@ impl Application for App {
 
     fn new(task: Task) -> (App, Command<Self::Message>) {
         println!(">> {}", task.title());
+        // The theme is checked along with the rest of the global settings before the app starts
+        style::set_palette(task.global().theme().palette().unwrap());
 
-        let app = App {
+        let mut app = App {
             task,
-            last_esc: Instant::now(),
         };
+        let command = app.task.launch();
 
-        (app, Command::none())
+        (app, command)
     }
 
     fn title(&self) -> String {
         self.task.title()
     }
 
-    fn update(&mut self, message: Self::Message, _: &mut Clipboard) -> Command<Self::Message> {
-        match message {
-            Message::Null => {
-                Command::none()
-            }
-            Message::Interrupt => {
-                let now = Instant::now();
-                if now.duration_since(self.last_esc) < Duration::from_millis(250) {
-                    self.task.update(message)
-                } else if !self.task.is_active() {
-                    self.task.update(message)
-                } else {
-                    self.last_esc = now;
-                    Command::none()
+    fn mode(&self) -> window::Mode {
+        if self.task.fullscreen() {
+            window::Mode::Fullscreen
+        } else {
+            window::Mode::Windowed
+        }
+    }
+
+    fn should_exit(&self) -> bool {
+        // The event loop ends the process with status 0, so any other status is set here
+        match self.task.exit_code() {
+            Some(code) => {
+                logger::wait_for_output();
+                match code {
+                    0 => true,
+                    code => std::process::exit(code),
                 }
             }
-            message => {
-                self.task.update(message)
-            }
+            None => false,
+        }
+    }
+
+    fn background_color(&self) -> Color {
+        style::palette().background
+    }
+
+    fn update(&mut self, message: Self::Message, _: &mut Clipboard) -> Command<Self::Message> {
+        let message = match message {
+            Message::Replay(message) => *message,
+            // Live input is ignored while a recorded session is replayed
+            message if self.task.replay().is_some() && self.task.is_input(&message) => return Command::none(),
+            message => message,
+        };
+        self.task.record(&message);
+        if self.task.global().remote_control().is_some() && !matches!(message, Message::Null) {
+            let command = self.relay(message);
+            self.task.publish_status();
+            command
+        } else {
+            self.relay(message)
         }
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        use iced::keyboard::Event::KeyPressed;
-        use iced::keyboard::KeyCode::Escape;
-        use iced_native::Event::Keyboard;
+        use iced::keyboard::Event::{KeyPressed, KeyReleased};
+        use iced::mouse::Event::CursorMoved;
+        use iced_native::Event::{Keyboard, Mouse};
 
         let mut subscriptions = vec![];
         if !self.task.has_dispatcher() {
@@ -65,21 +91,46 @@ impl Application for App {
         }
         subscriptions.push(
             subscription::events_with(|event, _| match event {
-                Keyboard(KeyPressed { key_code: Escape, .. }) => {
-                    Some(Message::Interrupt)
+                Keyboard(KeyPressed { key_code, modifiers }) => {
+                    Some(Message::KeyChord(key_code, modifiers))
                 },
-                Keyboard(KeyPressed { key_code, .. }) => {
-                    Some(Message::KeyPress(key_code))
+                Keyboard(KeyReleased { key_code, .. }) => {
+                    Some(Message::KeyRelease(key_code))
                 },
                 _ => None,
             })
         );
+        if let (Some(replay), Some(started)) = (self.task.replay(), self.task.started()) {
+            subscriptions.push(Subscription::from_recipe(ReplayLink::new(replay, started)));
+        }
+        if let Some(address) = self.task.global().remote_control() {
+            subscriptions.push(Subscription::from_recipe(RemoteLink::new(&address, self.task.global().remote_token())));
+        }
+        if self.task.tracks_mouse() {
+            subscriptions.push(
+                subscription::events_with(|event, _| match event {
+                    Mouse(CursorMoved { position }) => {
+                        Some(Message::CursorMoved(position.x, position.y))
+                    },
+                    _ => None,
+                })
+            );
+            subscriptions.push(
+                iced::time::every(self.task.global().mouse_sample_interval()).map(|_| Message::MouseSample)
+            );
+        }
+        if self.task.global().max_session().is_some() || self.task.global().disk_space().is_some() {
+            subscriptions.push(
+                iced::time::every(Duration::from_secs(1)).map(|_| Message::Tick)
+            );
+        }
         Subscription::batch(subscriptions)
     }
 
     fn view(&mut self) -> Element<Message> {
         let debug_ui = self.task.global().debug_ui();
         let (inner_x, inner_y) = self.task.global().content_size();
+        let backdrop = self.task.backdrop();
 
         let content = match inner_x {
             IntOrFloat::Integer(i) => Row::new()
@@ -116,6 +167,7 @@ impl Application for App {
             .height(Length::Fill)
             .center_x()
             .center_y()
+            .style(style::Container(backdrop))
             .into();
 
         if debug_ui {
@@ -125,3 +177,29 @@ impl Application for App {
         }
     }
 }
+
+impl App {
+    fn relay(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Null => {
+                Command::none()
+            }
+            Message::KeyChord(key_code, modifiers) => {
+                // The abort key leaves menus at once, but asks for confirmation during a block
+                if self.task.global().abort_key().matches(key_code, modifiers) {
+                    self.task.update(Message::Interrupt)
+                } else {
+                    let key_code = self.task.global().key(key_code);
+                    self.task.update(Message::KeyPress(key_code))
+                }
+            }
+            Message::KeyRelease(key_code) => {
+                let key_code = self.task.global().key(key_code);
+                self.task.update(Message::KeyRelease(key_code))
+            }
+            message => {
+                self.task.update(message)
+            }
+        }
+    }
+}
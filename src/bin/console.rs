@@ -0,0 +1,59 @@
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use task_runner::console::Status;
+
+/// Shows the state of a running task to the experimenter in a terminal, e.g. on a machine
+/// outside the booth, while the participant only sees the stimulus window:
+///
+///     console <ip>:<port>
+///
+/// The task should accept remote control on that address (`remote_control` in `global`), and
+/// the shared token of the task (`remote_token`) is read from TASK_RUNNER_REMOTE_TOKEN.
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        return Err("Usage example: ./console <ip>:<port>".to_string());
+    }
+    let token = env::var("TASK_RUNNER_REMOTE_TOKEN")
+        .map_err(|_| "Set TASK_RUNNER_REMOTE_TOKEN to the remote token of the task".to_string())?;
+    let mut stream = TcpStream::connect(&args[1])
+        .map_err(|e| format!("Failed to connect to task at {}: {}", args[1], e))?;
+    let mut reader = BufReader::new(stream.try_clone()
+        .map_err(|e| format!("Failed to read from task: {}", e))?);
+
+    let mut reply = String::new();
+    writeln!(stream, "auth {}", token)
+        .and_then(|()| reader.read_line(&mut reply))
+        .map_err(|e| format!("Lost connection to task: {}", e))?;
+    if reply.trim() != "OK" {
+        return Err("Task refused the remote token".to_string());
+    }
+
+    loop {
+        let mut reply = String::new();
+        writeln!(stream, "status")
+            .and_then(|()| reader.read_line(&mut reply))
+            .map_err(|e| format!("Lost connection to task: {}", e))?;
+        if reply.is_empty() {
+            return Err("Task closed the connection".to_string());
+        }
+
+        // Clear the terminal and redraw from the top
+        print!("\x1b[2J\x1b[H");
+        match reply.trim().strip_prefix("OK ") {
+            Some(json) => {
+                let status: Status = serde_json::from_str(json)
+                    .map_err(|e| format!("Invalid status received from task: {}", e))?;
+                for line in status.render() {
+                    println!("{}", line);
+                }
+            }
+            None => println!("{}", reply.trim()),
+        }
+        std::io::stdout().flush().ok();
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
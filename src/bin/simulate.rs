@@ -0,0 +1,36 @@
+use std::env;
+use std::fs::File;
+use std::path::PathBuf;
+
+use task_runner::simulate::Timeline;
+use task_runner::task::Task;
+
+/// Runs a block of a task headlessly with a scripted timeline of responses, e.g. in CI:
+///
+///     simulate <task_dir> <block> <timeline.yml>
+///
+/// The timeline maps action IDs to responses and their delay from the action onset:
+///
+///     choice1: { after: 800, response: [ Option B ] }
+///     survey: { after: 5000, response: [ "Red, Blue", Alpha, some text, "50" ] }
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        return Err("Usage example: ./simulate <task_dir> <block> <timeline.yml>".to_string());
+    }
+    let task_dir = PathBuf::from(&args[1]);
+    let block: usize = args[2].parse()
+        .map_err(|_| format!("Invalid block number: {}", args[2]))?;
+    let file = File::open(&args[3])
+        .map_err(|_| format!("Failed to open timeline file: {}", args[3]))?;
+    let timeline: Timeline = serde_yaml::from_reader(file)
+        .map_err(|e| format!("Failed to read timeline file: {}", e))?;
+
+    let mut task = Task::new(task_dir)?;
+    task.global().verify()?;
+    for line in task.simulate(block, &timeline, false)? {
+        println!("{}", line);
+    }
+    println!("Output written to: {}", task.log_dir());
+    Ok(())
+}
@@ -1,15 +1,22 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
-use iced::Column;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use iced::{Color, Column};
 use iced_futures::Command;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Serialize, Deserialize};
 
-use crate::action::{Action, flow, ID};
+use crate::action::{Action, Question, flow, ID, MARK_MISSED};
 use crate::comm::{Message, Sender};
+use crate::config::Config;
+use crate::error::Error;
 use crate::global::Global;
-use crate::util::{timestamp, async_write_to_file};
+use crate::sound::Playback;
+use crate::logger::{async_write_to_file, async_write_csv, EventLog};
+use crate::util::{timestamp, event_time, output};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -21,37 +28,139 @@ pub struct Block {
     #[serde(default, skip_serializing)]
     description: String,
     #[serde(default)]
+    monitor_kb: bool,
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    requires: Vec<usize>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    seed: Option<u64>,
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    shuffle: bool,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    probes: Option<Probes>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    progress: Option<ProgressDisplay>,
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    practice: bool,
+    #[serde(default)]
     actions: Vec<Action>,
     #[serde(skip)]
     id2action: HashMap<ID, usize>,
     #[serde(skip)]
     log_dir: String,
     #[serde(skip)]
-    events: Vec<String>,
+    events: EventLog,
+    #[serde(skip)]
+    probe_log: Vec<ProbeRecord>,
+    #[serde(skip)]
+    responses: Vec<ResponseRecord>,
+    #[serde(skip)]
+    trials: usize,
+    #[serde(skip)]
+    rng: Option<StdRng>,
+    #[serde(skip)]
+    refresh_rate: f32,
+    #[serde(skip)]
+    onset: Option<Instant>,
+}
+
+/// How the progress through a block is shown to the participant: as a count of completed
+/// actions (`counter`, e.g. "12 / 60"), or as a thin `bar` above the content.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressDisplay {
+    Counter,
+    Bar,
+}
+
+/// Thought probes that interrupt a block at random intervals, drawn uniformly from
+/// `interval` (in seconds). Each probe presents a question drawn from `questions`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Probes {
+    questions: Vec<Question>,
+    interval: (u32, u32),
+}
+
+impl Probes {
+    pub fn questions(&self) -> &Vec<Question> {
+        &self.questions
+    }
+
+    pub fn interval(&self) -> (u32, u32) {
+        self.interval
+    }
+}
+
+/// A row of the block's `responses.csv`, with one answered question per row.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseRecord {
+    pub block: usize,
+    pub action: ID,
+    pub prompt: String,
+    pub answer: String,
+    pub timestamp: String,
+    pub block_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeRecord {
+    pub onset: String,
+    pub interrupted: Vec<ID>,
+    pub duration_ms: f64,
+    pub question: Question,
 }
 
 impl Block {
-    pub fn init(&mut self, id: usize, task_dir: &Path) -> Result<(), String> {
+    pub fn init(
+        &mut self,
+        id: usize,
+        task_dir: &Path,
+        config: &Config,
+        global: &Global
+    ) -> Result<(), Error> {
         self.id = id;
+        // Without its own seed, a block derives one from the task seed, so that its
+        // randomization is independent of other blocks but still reproducible
+        let seed = self.seed.unwrap_or_else(|| {
+            global.seed() ^ (id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        });
+        self.seed = Some(seed);
+        self.rng = Some(StdRng::seed_from_u64(seed));
+
         if self.description.starts_with("<") {
             let file = task_dir.join(&self.description[1..].trim());
             let mut file = File::open(file)
-                .or(Err("Failed to open block description file".to_string()))?;
+                .map_err(|e| Error::io("Failed to open block description file".to_string(), e))?;
 
             self.description = String::new();
             file.read_to_string(&mut self.description)
-                .or(Err("Failed to read block description file".to_string()))?;
+                .map_err(|e| Error::io("Failed to read block description file".to_string(), e))?;
         }
 
+        if let Some(probes) = &mut self.probes {
+            let (min, max) = probes.interval;
+            if min == 0 || min > max {
+                return Err(Error::Invalid(format!("Invalid probe interval for block {}: {} to {} seconds", id, min, max)));
+            }
+            if probes.questions.is_empty() {
+                return Err(Error::Invalid(format!("Probes of block {} should have at least one question", id)));
+            }
+            for quest in &mut probes.questions {
+                quest.init()?;
+            }
+        }
+
+        let rng = self.rng.as_mut().unwrap();
         let mut last_action = None;
         let mut ids = HashSet::new();
-        for (i, action) in self.actions.iter_mut().enumerate() {
-            action.init(i+1, &last_action, 0, task_dir)?;
+        for i in flow::order(self.actions.len(), self.shuffle, rng) {
+            let action = &mut self.actions[i];
+            action.init(i+1, &last_action, 0, task_dir, config, rng)?;
             last_action = Some(action.id());
 
             let id = action.id();
             if ids.contains(&id) {
-                return Err(format!("Action ID `{}` used more than once; IDs should be unique", id));
+                return Err(Error::InvalidGraph(format!("Action ID `{}` used more than once; IDs should be unique", id)));
             } else {
                 ids.insert(id);
             }
@@ -59,19 +168,21 @@ impl Block {
 
         let mut i: usize = 0;
         while i < self.actions.len() {
-            if matches!(self.actions[i], Action::Template { .. }) {
-                if let Action::Template { actions: inners, .. } = self.actions[i].clone() {
-                    self.actions.remove(i);
-                    for inner in inners.into_iter() {
-                        self.actions.insert(i, inner);
-                        i += 1;
-                    }
+            if let Some(inners) = self.actions[i].inner_actions().cloned() {
+                self.actions.remove(i);
+                for inner in inners.into_iter() {
+                    self.actions.insert(i, inner);
+                    i += 1;
                 }
             } else {
                 i += 1;
             }
         }
 
+        for action in &mut self.actions {
+            action.default_monitor_kb(self.monitor_kb);
+        }
+
         flow::add_gates(&mut self.actions, Some(HashSet::new()), None)?;
 
         // Make a lookup table for actions by ID
@@ -85,6 +196,125 @@ impl Block {
             action.verify(&id_list)?;
         }
 
+        // Verify that sample references point to defined stimulus pools
+        for action in &mut self.actions {
+            for pool in action.sample_refs() {
+                if !global.has_pool(&pool) {
+                    return Err(Error::Invalid(format!("Action `{}` samples from undefined stimulus pool `{}`", action.id(), pool)));
+                }
+            }
+        }
+
+        // Verify that frame rates are achievable on the display
+        let refresh_rate = global.refresh_rate();
+        self.refresh_rate = refresh_rate;
+        for action in &self.actions {
+            match action.frames() {
+                Some(0) => return Err(Error::Invalid(format!("Frame duration of action `{}` should be positive", action.id()))),
+                Some(_) if !action.has_view() => {
+                    return Err(Error::Invalid(format!("Frame duration can only be used with viewable actions, but `{}` has no view", action.id())));
+                }
+                Some(_) if !global.has_refresh_rate() => {
+                    println!("Warning: Display refresh rate is not specified; frame duration of action `{}` falls back to a timer assuming {} Hz",
+                             action.id(), refresh_rate);
+                }
+                _ => (),
+            }
+            if let Some(rate) = action.frame_rate() {
+                let cycles = refresh_rate / rate;
+                if rate > refresh_rate {
+                    return Err(Error::Invalid(format!("RSVP frame rate of action `{}` ({} Hz) exceeds the display refresh rate ({} Hz)", action.id(), rate, refresh_rate)));
+                } else if (cycles - cycles.round()).abs() > 0.01 {
+                    println!("Warning: RSVP frame rate of action `{}` ({} Hz) is not a divisor of the display refresh rate ({} Hz); frame durations will vary by one refresh cycle",
+                             action.id(), rate, refresh_rate);
+                }
+            }
+        }
+
+        // Crop images that cover the content area, which depends on the window size
+        for action in &mut self.actions {
+            action.crop_cover(task_dir, global)?;
+        }
+
+        // Verify that config references point to defined configuration options
+        for action in &mut self.actions {
+            for name in action.config_refs() {
+                if !config.has_option(&name) {
+                    return Err(Error::Invalid(format!("Action `{}` refers to undefined configuration option `{}`", action.id(), name)));
+                }
+            }
+        }
+
+        // Verify that response references only point to preceding actions
+        for id in &id_list {
+            let refs = self.action_mut(id)?.response_refs();
+            if refs.is_empty() {
+                continue;
+            }
+            let preceding = self.predecessors(id);
+            for (target, index) in refs {
+                let resolved = self.resolve_ref(id, &target)
+                    .ok_or(Error::InvalidGraph(format!("Action `{}` references response of unknown action `{}`", id, target)))?;
+                if !preceding.contains(&resolved) {
+                    return Err(Error::InvalidGraph(format!(
+                        "Action `{}` references response of `{}`, which does not finish before it starts",
+                        id, target)));
+                }
+                if !self.action(&resolved)?.collects_response() {
+                    return Err(Error::InvalidGraph(format!("Action `{}` references `{}`, which has no response", id, target)));
+                }
+                let is_question = matches!(self.action(&resolved)?, Action::Question { .. });
+                match index {
+                    Some(0) => return Err(Error::InvalidGraph(format!(
+                        "Question numbers in response references start from 1: `{}`", target))),
+                    Some(_) if !is_question => return Err(Error::InvalidGraph(format!(
+                        "Only question actions can be referenced by question number: `{}`", target))),
+                    _ => (),
+                }
+            }
+        }
+
+        // Verify that branches lead to actions that start after the branching action
+        for id in &id_list {
+            let branches = self.action(id)?.branches().clone();
+            if branches.is_empty() {
+                continue;
+            }
+            if !self.action(id)?.collects_response() {
+                return Err(Error::InvalidGraph(format!("Action `{}` branches on its response, but has none", id)));
+            }
+            let mut resolved = HashMap::new();
+            for (value, target) in branches {
+                let target_id = self.resolve_ref(id, &target)
+                    .ok_or(Error::InvalidGraph(format!("Action `{}` branches to unknown action `{}`", id, target)))?;
+                if !self.predecessors(&target_id).contains(id) {
+                    return Err(Error::InvalidGraph(format!("Action `{}` branches to `{}`, which does not start after it", id, target)));
+                }
+                resolved.insert(value, target_id);
+            }
+            *self.action_mut(id)?.branches_mut() = resolved;
+        }
+
+        // Verify that timeout fallbacks follow an action with a timeout
+        for id in &id_list {
+            let target = match self.action(id)?.on_timeout() {
+                Some(target) => target.clone(),
+                None => continue,
+            };
+            if self.action(id)?.timeout().is_none() {
+                return Err(Error::InvalidGraph(format!("Action `{}` has `on_timeout`, but no `timeout`", id)));
+            }
+            if target == MARK_MISSED {
+                continue;
+            }
+            let target_id = self.resolve_ref(id, &target)
+                .ok_or(Error::InvalidGraph(format!("Action `{}` falls back on timeout to unknown action `{}`", id, target)))?;
+            if !self.predecessors(&target_id).contains(id) {
+                return Err(Error::InvalidGraph(format!("Action `{}` falls back on timeout to `{}`, which does not start after it", id, target)));
+            }
+            *self.action_mut(id)?.on_timeout_mut() = Some(target_id);
+        }
+
         // Make reverse dependency links
         for id in id_list {
             let action = self.action(&id)?;
@@ -98,7 +328,78 @@ impl Block {
             }
         }
 
-        Ok(())
+        self.check_reachable()
+    }
+
+    /// Verifies that every action can start, by starting from the entry point and following
+    /// the `after` (all of which must have started, and eventually finish) and `with` (which
+    /// must have started) links, the same way as the dispatcher does. Reports a cycle of
+    /// actions waiting on each other if there is one, and the unreachable actions otherwise.
+    fn check_reachable(&self) -> Result<(), Error> {
+        let prerequisites: HashMap<ID, Option<Vec<ID>>> = self.actions.iter()
+            .map(|action| {
+                let links = match (action.is_ready(), action.with()) {
+                    (None, None) => None,
+                    (_, with) => Some(action.after().into_iter().chain(with).collect()),
+                };
+                (action.id(), links)
+            })
+            .collect();
+
+        let mut started = HashSet::from(["entry".to_string()]);
+        loop {
+            let ready: Vec<_> = prerequisites.iter()
+                .filter(|(id, _)| !started.contains(*id))
+                .filter(|(_, links)| links.as_ref().is_some_and(|links| links.iter().all(|l| started.contains(l))))
+                .map(|(id, _)| id.clone())
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            started.extend(ready);
+        }
+
+        let mut stuck: Vec<_> = prerequisites.keys()
+            .filter(|id| !started.contains(*id))
+            .cloned()
+            .collect();
+        if stuck.is_empty() {
+            return Ok(());
+        }
+        stuck.sort();
+
+        // Follow the unmet links from a stuck action; if they lead back to an action already on
+        // the path, that part of the path is a cycle
+        for first in &stuck {
+            let mut path = vec![first.clone()];
+            let mut visited = HashSet::from([first.clone()]);
+            loop {
+                let unmet = prerequisites[path.last().unwrap()].as_ref()
+                    .and_then(|links| {
+                        let mut unmet: Vec<_> = links.iter().filter(|l| !started.contains(*l)).collect();
+                        unmet.sort();
+                        unmet.first().cloned().cloned()
+                    });
+                match unmet {
+                    Some(next) if visited.contains(&next) => {
+                        let start = path.iter().position(|id| *id == next).unwrap();
+                        let mut cycle = path[start..].to_vec();
+                        cycle.reverse();
+                        cycle.push(cycle[0].clone());
+                        return Err(Error::InvalidGraph(format!(
+                            "Actions of block {} wait on each other in a cycle: {}", self.id, cycle.join(" -> "))));
+                    }
+                    Some(next) => {
+                        visited.insert(next.clone());
+                        path.push(next);
+                    }
+                    None => break,
+                }
+            }
+        }
+        Err(Error::InvalidGraph(format!(
+            "Actions of block {} can never start (they are missing `after` or `with` links to reachable actions): {}",
+            self.id, stuck.join(", "))))
     }
 
     pub fn id(&self) -> usize {
@@ -109,6 +410,113 @@ impl Block {
         self.title.clone()
     }
 
+    /// The dependency graph of the (initialized) block in Graphviz DOT format. Solid edges
+    /// point from an action to the ones that start after it, dashed edges to the ones that
+    /// end with it, and dotted edges to the branches taken on a response (or a timeout). Actions expanded
+    /// from a template are grouped in a box named after it.
+    pub fn to_dot(&self) -> String {
+        let quote = |id: &str| format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"));
+        let mut groups: BTreeMap<String, Vec<&Action>> = BTreeMap::new();
+        for action in &self.actions {
+            let id = action.id();
+            let group = id.rfind('~').map_or("", |i| &id[..i]);
+            groups.entry(group.to_string()).or_default().push(action);
+        }
+
+        let mut lines = vec![
+            format!("digraph {} {{", quote(&format!("block-{}", self.id))),
+            format!("  label={};", quote(&format!("Block {}: {}", self.id, self.title))),
+            "  node [shape=box];".to_string(),
+        ];
+        for (group, actions) in groups {
+            let indent = if group.is_empty() { "  " } else { "    " };
+            if !group.is_empty() {
+                lines.push(format!("  subgraph {} {{", quote(&format!("cluster_{}", group))));
+                lines.push(format!("    label={};", quote(&group)));
+            }
+            for action in actions {
+                let id = action.id();
+                let name = id.rsplit('~').next().unwrap_or(&id);
+                let shape = if matches!(name, "entry" | "exit") { ", shape=ellipse" } else { "" };
+                lines.push(format!("{}{} [label={}{}];", indent, quote(&id), quote(&format!("{}\n{}", name, action.kind())), shape));
+            }
+            if !group.is_empty() {
+                lines.push("  }".to_string());
+            }
+        }
+        for action in &self.actions {
+            let id = action.id();
+            let mut after: Vec<_> = action.after().into_iter().collect();
+            after.sort();
+            for before in after {
+                lines.push(format!("  {} -> {};", quote(&before), quote(&id)));
+            }
+            if let Some(with) = action.with() {
+                lines.push(format!("  {} -> {} [style=dashed, label=\"with\"];", quote(&with), quote(&id)));
+            }
+            let mut branches: Vec<_> = action.branches().iter().collect();
+            branches.sort();
+            for (value, target) in branches {
+                lines.push(format!("  {} -> {} [style=dotted, label={}];", quote(&id), quote(target), quote(value)));
+            }
+            if let Some(target) = action.on_timeout().filter(|&target| target != MARK_MISSED) {
+                lines.push(format!("  {} -> {} [style=dotted, label=\"timeout\"];", quote(&id), quote(target)));
+            }
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    pub fn progress_display(&self) -> Option<ProgressDisplay> {
+        self.progress
+    }
+
+    pub fn onset(&self) -> Option<Instant> {
+        self.onset
+    }
+
+    /// Draws a uniformly distributed integer from an inclusive range, using the block seed.
+    pub fn random_range(&mut self, low: u32, high: u32) -> u32 {
+        self.rng.as_mut().unwrap().gen_range(low..=high)
+    }
+
+    pub fn probes(&self) -> Option<&Probes> {
+        self.probes.as_ref()
+    }
+
+    pub fn pause(&self, id: &ID) {
+        self.action(id).unwrap().pause();
+    }
+
+    pub fn resume(&self, id: &ID) {
+        self.action(id).unwrap().resume();
+    }
+
+    pub fn record_probe(&mut self, record: ProbeRecord) {
+        self.events.push(format!("{}  PROBE  {}", record.onset, record.duration_ms.round()));
+        self.probe_log.push(record);
+    }
+
+    pub fn requires(&self) -> &Vec<usize> {
+        &self.requires
+    }
+
+    /// Whether the block is for practice: its output is kept apart (in a `practice` directory),
+    /// and running it does not count as completing it.
+    pub fn is_practice(&self) -> bool {
+        self.practice
+    }
+
+    /// Audio files played by the block, including trigger files if triggers are in use.
+    pub fn audio_sources(&self, global: &Global) -> Vec<PathBuf> {
+        let task_dir = Path::new(global.dir());
+        let trigger_files = global.config().uses_trigger_files();
+        self.actions
+            .iter()
+            .flat_map(|action| action.audio_sources(task_dir, trigger_files))
+            .collect()
+    }
+
     pub fn actions(&self) -> Vec<ID> {
         self.actions
             .iter()
@@ -116,18 +524,51 @@ impl Block {
             .collect()
     }
 
-    pub fn action(&self, id: &ID) -> Result<&Action, String> {
+    pub fn action(&self, id: &ID) -> Result<&Action, Error> {
         let index = self.id2action.get(id)
-            .ok_or(format!("Invalid reference to action: {}", id))?;
+            .ok_or(Error::InvalidGraph(format!("Invalid reference to action: {}", id)))?;
         Ok(&self.actions[*index])
     }
 
-    pub fn action_mut(&mut self, id: &ID) -> Result<&mut Action, String> {
+    pub fn action_mut(&mut self, id: &ID) -> Result<&mut Action, Error> {
         let index = self.id2action.get(id)
-            .ok_or(format!("Invalid mutable reference to action: {}", id))?;
+            .ok_or(Error::InvalidGraph(format!("Invalid mutable reference to action: {}", id)))?;
         Ok(&mut self.actions[*index])
     }
 
+    /// All actions that have to finish before the given action starts.
+    fn predecessors(&self, id: &ID) -> HashSet<ID> {
+        let mut preceding = HashSet::new();
+        let mut frontier = vec![id.clone()];
+        while let Some(id) = frontier.pop() {
+            if let Ok(action) = self.action(&id) {
+                for before in action.after() {
+                    if preceding.insert(before.clone()) {
+                        frontier.push(before);
+                    }
+                }
+            }
+        }
+        preceding
+    }
+
+    /// Resolves a referenced action ID, first relative to the referring action's template.
+    fn resolve_ref(&self, from: &ID, target: &ID) -> Option<ID> {
+        let mut scope = from.as_str();
+        while let Some((outer, _)) = scope.rsplit_once('~') {
+            let id = format!("{}~{}", outer, target);
+            if self.id2action.contains_key(&id) {
+                return Some(id);
+            }
+            scope = outer;
+        }
+        if self.id2action.contains_key(target) {
+            Some(target.clone())
+        } else {
+            None
+        }
+    }
+
     pub fn dependents(&self, id: &ID) -> &HashSet<ID> {
         &self.action(id).unwrap().dependents()
     }
@@ -152,27 +593,128 @@ impl Block {
         self.action(id).unwrap().has_background()
     }
 
+    pub fn backdrop(&self, id: &ID) -> Option<Color> {
+        self.action(id).unwrap().backdrop()
+    }
+
     pub fn captures_keystrokes(&self, id: &ID) -> bool {
         self.action(id).unwrap().captures_keystrokes()
     }
 
+    pub fn tracks_mouse(&self, id: &ID) -> bool {
+        self.action(id).unwrap().tracks_mouse()
+    }
+
+    pub fn record_cursor(&mut self, id: &ID, x: f32, y: f32) {
+        self.action_mut(id).unwrap().record_cursor(x, y);
+    }
+
     pub fn with_log_dir(mut self, log_dir: &str) -> Self {
-        self.log_dir = Path::new(log_dir)
+        let log_dir = if self.practice {
+            Path::new(log_dir).join("practice")
+        } else {
+            Path::new(log_dir).to_path_buf()
+        };
+        self.log_dir = log_dir
             .join(format!("block-{}-{}", self.id, timestamp()))
             .to_str().unwrap().to_string();
         std::fs::create_dir_all(&self.log_dir)
             .expect("Failed to create output directory for block");
+        self.events.set_dir(&self.log_dir);
+        self.onset = Some(Instant::now());
+        self.events.push(format!("{}  SEED  {}", self.now(), self.seed.unwrap()));
+        if self.practice {
+            self.events.push(format!("{}  PRACTICE", self.now()));
+        }
         self
     }
 
-    pub fn execute(&mut self, id: &ID, writer: Sender, global: &Global) -> Command<Message> {
-        let log_dir = self.log_dir.to_owned();
-        self.events.push(format!("{}  START  {}", timestamp(), id));
-        self.action_mut(id).unwrap().run(writer, &log_dir, global)
+    /// Timestamp of a block event, with the time since the start of the block.
+    fn now(&self) -> String {
+        event_time(self.onset.unwrap())
+    }
+
+    /// Runs an action, or skips it (returning None) if a file it names only once its runtime
+    /// references are resolved is missing, and missing resources are to be skipped.
+    pub fn execute(&mut self, id: &ID, writer: Sender, global: &Global) -> Result<Option<Command<Message>>, Error> {
+        self.resolve_runtime_refs(id, global);
+        if let Err(e) = self.action(id).unwrap().check_resources(Path::new(global.dir())) {
+            if !global.config().skip_missing_resources() {
+                return Err(e);
+            }
+            self.action_mut(id).unwrap().mark_missing(e.to_string());
+            return Ok(None);
+        }
+        self.draw_intervals(id);
+        let log_prefix = self.log_start(id, global);
+        self.shuffle_options(id);
+        Ok(Some(self.action_mut(id).unwrap().run(writer, log_prefix, global)))
+    }
+
+    fn resolve_runtime_refs(&mut self, id: &ID, global: &Global) {
+        let mut values = HashMap::new();
+        for (target, index) in self.action_mut(id).unwrap().response_refs() {
+            let answer = self.resolve_ref(id, &target)
+                .and_then(|target| self.action(&target).unwrap().response(index))
+                .unwrap_or_else(|| "~".to_string());
+            values.insert(flow::response_ref(&target, index), answer);
+        }
+        for pool in self.action_mut(id).unwrap().sample_refs() {
+            values.entry(flow::sample_ref(&pool))
+                .or_insert_with(|| global.sample(&pool));
+        }
+        for name in self.action_mut(id).unwrap().config_refs() {
+            let value = global.config().value(&name).unwrap_or_else(|| "~".to_string());
+            values.insert(flow::config_ref(&name), value);
+        }
+        self.action_mut(id).unwrap().resolve_runtime_refs(&values);
+    }
+
+    /// Draws the jittered intervals of an action from the block's random generator.
+    fn draw_intervals(&mut self, id: &ID) {
+        let index = self.id2action[id];
+        let rng = self.rng.as_mut().unwrap();
+        self.actions[index].draw_intervals(|low, high| rng.gen_range(low..=high));
+    }
+
+    /// Draws the display order of shuffled options of an action, and logs it (as option numbers).
+    fn shuffle_options(&mut self, id: &ID) {
+        let index = self.id2action[id];
+        let rng = self.rng.as_mut().unwrap();
+        for (question, order) in self.actions[index].shuffle_options(rng) {
+            let order = order.iter().map(|i| (i + 1).to_string()).collect::<Vec<_>>().join(" ");
+            match question {
+                Some(n) => self.events.push(format!("{}  OPTION_ORDER  {}  {}  {}", self.now(), id, n, order)),
+                None => self.events.push(format!("{}  OPTION_ORDER  {}  {}", self.now(), id, order)),
+            }
+        }
+    }
+
+    /// Starts an action without running its timers or playback, for simulated runs.
+    pub fn start(&mut self, id: &ID, global: &Global) {
+        self.resolve_runtime_refs(id, global);
+        self.draw_intervals(id);
+        let log_prefix = self.log_start(id, global);
+        self.shuffle_options(id);
+        self.action_mut(id).unwrap().start(log_prefix);
+    }
+
+    fn log_start(&mut self, id: &ID, global: &Global) -> String {
+        self.trials += 1;
+        self.events.push(format!("{}  START  {}", self.now(), id));
+        let onset = self.onset;
+        self.action_mut(id).unwrap().set_block_onset(onset);
+        output(&self.log_dir, global.output_pattern(), id, self.id, self.trials)
     }
 
     pub fn update(&mut self, id: &ID, message: Message, global: &Global) -> Command<Message> {
-        self.action_mut(id).unwrap().update(message, global)
+        let is_input = matches!(message, Message::UIEvent(..));
+        let action = self.action_mut(id).unwrap();
+        let command = action.update(message, global);
+        if is_input {
+            action.save_partial();
+        }
+        command
     }
 
     pub fn view(&mut self, id: &ID, global: &Global) -> Column<Message> {
@@ -198,22 +740,323 @@ impl Block {
         (ready, expired)
     }
 
+    pub fn playback(&mut self, id: &ID, result: Playback) {
+        if self.action(id).unwrap().playback().is_none() {
+            self.events.push(format!("{}  PLAYBACK  {}  {}", self.now(), id, result));
+            self.action_mut(id).unwrap().set_playback(result);
+        }
+    }
+
     pub fn wrap(&mut self, id: &ID) -> (HashSet<ID>, HashSet<ID>) {
-        self.events.push(format!("{}  WRAP  {}", timestamp(), id));
+        if self.action(id).unwrap().has_started() && self.action(id).unwrap().plays_audio() {
+            self.playback(id, Playback::Interrupted);
+        }
+        self.events.push(format!("{}  WRAP  {}", self.now(), id));
+        self.branch(id);
+        self.route_timeout(id);
+        if let Some(frames) = self.action(id).unwrap().frames() {
+            // Frames are not synced to the display, so the elapsed time is logged for verification
+            let elapsed = self.action(id).unwrap().elapsed().unwrap_or_default().as_secs_f64();
+            self.events.push(format!("{}  FRAMES  {}  {}  {:.2}  {:.2}",
+                                     self.now(), id, frames, elapsed * self.refresh_rate as f64, elapsed * 1000.0));
+        }
         self.action_mut(id).unwrap().wrap();
+        let wrapped = timestamp();
+        let block_ms = self.onset.unwrap().elapsed().as_secs_f64() * 1000.0;
+        for (prompt, answer) in self.action(id).unwrap().answers() {
+            self.responses.push(ResponseRecord {
+                block: self.id,
+                action: id.clone(),
+                prompt,
+                answer,
+                timestamp: wrapped.clone(),
+                block_ms,
+            });
+        }
         self.satisfy(id)
     }
 
+    /// Skips the follow-up actions of a branching action that its response did not choose.
+    fn branch(&mut self, id: &ID) {
+        let action = self.action(id).unwrap();
+        if action.branches().is_empty() {
+            return;
+        }
+        let value = action.response(None);
+        let chosen = value.as_ref().and_then(|value| action.branches().get(value)).cloned();
+        let skipped: HashSet<_> = action.branches().values()
+            .filter(|&target| chosen.as_ref() != Some(target))
+            .cloned()
+            .collect();
+
+        self.events.push(format!("{}  BRANCH  {}  {}  {}", self.now(), id,
+                                 value.as_deref().unwrap_or("~"), chosen.as_deref().unwrap_or("~")));
+        for target in skipped {
+            self.action_mut(&target).unwrap().expire();
+        }
+    }
+
+    /// Runs the timeout fallback of an action if it timed out, and skips it otherwise. With
+    /// `mark_missed`, a timeout is recorded as a missed response instead.
+    fn route_timeout(&mut self, id: &ID) {
+        let action = self.action(id).unwrap();
+        let target = match action.on_timeout() {
+            Some(target) => target.clone(),
+            None => return,
+        };
+        let timed_out = action.is_timed_out();
+        if target == MARK_MISSED {
+            if timed_out {
+                self.events.push(format!("{}  MISSED  {}", self.now(), id));
+                self.responses.push(ResponseRecord {
+                    block: self.id,
+                    action: id.clone(),
+                    prompt: String::new(),
+                    answer: "MISSED".to_string(),
+                    timestamp: timestamp(),
+                    block_ms: self.onset.unwrap().elapsed().as_secs_f64() * 1000.0,
+                });
+            }
+        } else if timed_out {
+            self.events.push(format!("{}  ON_TIMEOUT  {}  {}", self.now(), id, target));
+        } else {
+            self.action_mut(&target).unwrap().expire();
+        }
+    }
+
+    /// Whether reaching the action ends the block early, in which case the reason is logged.
+    pub fn ends_block(&mut self, id: &ID) -> bool {
+        match self.action(id).unwrap().ends_block() {
+            Some("") => self.events.push(format!("{}  END_BLOCK  {}", self.now(), id)),
+            Some(reason) => self.events.push(format!("{}  END_BLOCK  {}  {}", self.now(), id, reason)),
+            None => return false,
+        }
+        true
+    }
+
     pub fn skip(&mut self, id: &ID) -> (HashSet<ID>, HashSet<ID>) {
-        self.events.push(format!("{}  SKIP  {}", timestamp(), id));
+        match self.action(id).unwrap().missing_resource() {
+            Some(reason) => self.events.push(format!("{}  SKIP  {}  {}", self.now(), id, reason)),
+            None => self.events.push(format!("{}  SKIP  {}", self.now(), id)),
+        }
         self.satisfy(id)
     }
 
+    pub fn respond(&mut self, id: &ID) {
+        let action = self.action_mut(id).unwrap();
+        if action.collects_response() {
+            action.respond();
+        }
+    }
+
+    pub fn respond_after(&mut self, id: &ID, rt: Duration) {
+        let action = self.action_mut(id).unwrap();
+        if action.collects_response() {
+            action.respond_after(rt);
+        }
+    }
+
+    pub fn time_out(&mut self, id: &ID) {
+        self.action_mut(id).unwrap().time_out();
+    }
+
+    pub fn rt_summary(&self) -> Vec<ReactionTimes> {
+        self.actions
+            .iter()
+            .filter(|action| action.has_started())
+            .filter(|action| action.collects_response() || action.captures_keystrokes())
+            .map(ReactionTimes::from)
+            .collect()
+    }
+
     pub fn finish(&mut self) {
         async_write_to_file(
             Path::new(&self.log_dir).join("events.log").to_str().unwrap().to_string(),
-            self.events.clone(),
+            self.events.to_vec(),
             "Failed to write block event log to output file");
+        async_write_to_file(
+            Path::new(&self.log_dir).join("rt_summary.yml").to_str().unwrap().to_string(),
+            self.rt_summary(),
+            "Failed to write block reaction time summary to output file");
+        if !self.probe_log.is_empty() {
+            async_write_to_file(
+                Path::new(&self.log_dir).join("probes.log").to_str().unwrap().to_string(),
+                self.probe_log.clone(),
+                "Failed to write block probe log to output file");
+        }
+        if !self.responses.is_empty() {
+            async_write_csv(
+                Path::new(&self.log_dir).join("responses.csv").to_str().unwrap().to_string(),
+                self.responses.clone(),
+                "Failed to write block response table to output file");
+        }
         self.events.clear();
     }
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReactionTimes {
+    action: ID,
+    responses: usize,
+    anticipatory: usize,
+    mean_rt: Option<f64>,
+    median_rt: Option<f64>,
+    timed_out: bool,
+}
+
+impl From<&Action> for ReactionTimes {
+    fn from(action: &Action) -> Self {
+        let mut rts: Vec<f64> = action.response_times()
+            .iter()
+            .map(|rt| rt.as_secs_f64() * 1000.0)
+            .collect();
+        rts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let n = rts.len();
+        let anticipatory = match action.min_rt() {
+            Some(min_rt) => action.response_times().iter().filter(|&&rt| rt < min_rt).count(),
+            None => 0,
+        };
+        let mean_rt = if n > 0 {
+            Some(rts.iter().sum::<f64>() / n as f64)
+        } else {
+            None
+        };
+        let median_rt = match n {
+            0 => None,
+            n if n % 2 == 1 => Some(rts[n / 2]),
+            n => Some((rts[n / 2 - 1] + rts[n / 2]) / 2.0),
+        };
+
+        ReactionTimes {
+            action: action.id(),
+            responses: n,
+            anticipatory,
+            mean_rt,
+            median_rt,
+            timed_out: action.is_timed_out(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Settings of a task with a fixed seed.
+    pub(crate) fn global(seed: u64) -> Global {
+        let mut global = Global::default();
+        global.set_seed(seed);
+        global.init_sampler();
+        global
+    }
+
+    /// A block read from YAML and initialized as the first block of a task.
+    pub(crate) fn block(yaml: &str) -> Result<Block, Error> {
+        let mut block: Block = serde_yaml::from_str(yaml).unwrap();
+        block.init(1, Path::new("."), &Config::default(), &global(1))?;
+        Ok(block)
+    }
+
+    #[test]
+    fn accepts_reachable_actions() {
+        let block = block(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100 }
+              - { id: b, type: nothing, timeout: 100, after: [ a ] }
+              - { id: c, type: nothing, with: b }
+        "#);
+        assert!(block.is_ok(), "{:?}", block.err());
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let error = block(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100 }
+              - { id: b, type: nothing, timeout: 100, after: [ a, c ] }
+              - { id: c, type: nothing, timeout: 100, after: [ b ] }
+        "#).unwrap_err();
+        assert!(matches!(error, Error::InvalidGraph(_)), "{:?}", error);
+        assert!(error.message().contains("b -> c -> b") || error.message().contains("c -> b -> c"), "{}", error);
+    }
+
+    #[test]
+    fn reports_unknown_links() {
+        let error = block(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100, after: [ missing ] }
+        "#).unwrap_err();
+        assert!(matches!(error, Error::InvalidGraph(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn records_a_missed_response_on_timeout() {
+        let log_dir = std::env::temp_dir().join("task-runner-tests");
+        let mut block = block(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100, on_timeout: mark_missed }
+              - { id: b, type: nothing, timeout: 100, on_timeout: mark_missed }
+        "#).unwrap().with_log_dir(log_dir.to_str().unwrap());
+        let (a, b) = ("a".to_string(), "b".to_string());
+        block.time_out(&a);
+        block.wrap(&a);
+        block.wrap(&b);
+        let missed: Vec<_> = block.responses.iter().map(|record| (record.action.as_str(), record.answer.as_str())).collect();
+        assert_eq!(missed, [("a", "MISSED")]);
+    }
+
+    /// Settings of a task in the current directory, with its configuration given as YAML.
+    fn configured(config: &str) -> Global {
+        let mut global = global(1);
+        global.set_dir(".");
+        global.set_config(&serde_yaml::from_str(config).unwrap());
+        global
+    }
+
+    #[test]
+    fn checks_sources_that_refer_to_an_unanswered_question() {
+        let yaml = r#"
+            title: B
+            actions:
+              - { id: q, type: question, list: [ { type: short_answer, prompt: Name } ] }
+              - { id: img, type: image, source: "{{response:q}}.png", after: [ q ] }
+        "#;
+        let (writer, _) = std::sync::mpsc::channel();
+        let id = "img".to_string();
+
+        let mut first = block(yaml).unwrap();
+        let global = configured("{audio: [stereo, false]}");
+        let error = first.execute(&id, writer.clone(), &global).err();
+        assert!(matches!(error, Some(Error::MissingResource(_))), "{:?}", error);
+
+        let mut second = block(yaml).unwrap();
+        let global = configured("{audio: [stereo, false], on_missing_resource: skip}");
+        assert!(second.execute(&id, writer, &global).unwrap().is_none());
+        let reason = second.action(&id).unwrap().missing_resource().cloned();
+        assert!(reason.as_deref().is_some_and(|reason| reason.contains("not found")), "{:?}", reason);
+        assert!(!second.action(&id).unwrap().has_started());
+    }
+
+    #[test]
+    fn skips_a_sampled_file_that_is_missing() {
+        let mut global: Global = serde_yaml::from_str("pools: { sounds: [ missing.wav ] }").unwrap();
+        global.set_seed(1);
+        global.init_sampler();
+        global.set_dir(".");
+        global.set_config(&serde_yaml::from_str("{audio: [stereo, false], on_missing_resource: skip}").unwrap());
+        let mut block: Block = serde_yaml::from_str(r#"
+            title: B
+            actions:
+              - { id: a, type: audio, source: "{{sample:sounds}}" }
+        "#).unwrap();
+        block.init(1, Path::new("."), global.config(), &global).unwrap();
+        let (writer, _) = std::sync::mpsc::channel();
+        assert!(block.execute(&"a".to_string(), writer, &global).unwrap().is_none());
+        assert!(global.draws().iter().any(|line| line.ends_with("DRAW  sounds  missing.wav")));
+    }
+}
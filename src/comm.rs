@@ -3,13 +3,16 @@ use std::hash::{Hash, Hasher};
 use std::sync::mpsc;
 use std::sync::mpsc::TryRecvError;
 use std::time::Duration;
-use iced::keyboard::KeyCode;
+use iced::keyboard::{KeyCode, Modifiers};
+use iced_futures::Command;
 use iced_native::subscription::Recipe;
 use iced_futures::futures;
+use serde::{Deserialize, Serialize};
 
 use crate::action::ID;
+use crate::sound::Playback;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Value {
     Null,
     Bool(bool),
@@ -33,14 +36,31 @@ pub enum Message {
     Value(ID, ID, Code, Value),
     UIEvent(Code, Value),
     KeyPress(KeyCode),
+    KeyRelease(KeyCode),
+    KeyChord(KeyCode, Modifiers),
+    CursorMoved(f32, f32),
+    MouseSample,
     Log(LogMode, String),
     SetComms(Sender),
     Interrupt,
+    ConfirmAbort(bool),
+    Error(String),
     Query(ID, String),
     QueryResponse(ID, String),
     ActionComplete(ID),
+    Timeout(ID),
+    Playback(ID, Playback),
+    Probe(u32),
+    ProbeComplete,
+    Preloaded(u32, Result<(), String>),
+    Uploaded(Result<usize, String>),
+    Remote(Box<Message>),
+    Replay(Box<Message>),
+    Pause,
+    Resume,
     BlockComplete,
     Wrap,
+    Tick,
     Null,
 }
 
@@ -48,16 +68,37 @@ pub type Sender = mpsc::Sender<Message>;
 pub type Receiver = mpsc::Receiver<Message>;
 pub type Comm = (Sender, Receiver);
 
+/// Reports an unexpected error to the task, which stops the running block and shows it.
+pub fn report(error: String) -> Command<Message> {
+    Command::perform(async move { error }, Message::Error)
+}
+
+/// Blocks a paused activity until it is resumed. Returns false if it was wrapped or
+/// interrupted in the meantime instead, or an error if it received a message it cannot handle.
+pub fn wait_for_resume(inbox: &Receiver) -> Result<bool, String> {
+    loop {
+        match inbox.recv() {
+            Ok(Message::Resume) => return Ok(true),
+            Ok(Message::Pause) => (),
+            Ok(Message::Wrap) |
+            Ok(Message::Interrupt) |
+            Err(_) => return Ok(false),
+            Ok(msg) => return Err(format!("Unexpected message received while paused: {:?}", msg)),
+        }
+    }
+}
+
 pub struct CommLink {
     writer: Sender,
     inbox: Receiver,
     is_ready: bool,
+    is_closed: bool,
 }
 
 impl CommLink {
     pub fn new() -> Self {
         let (writer, inbox) = mpsc::channel();
-        CommLink { writer, inbox, is_ready: false }
+        CommLink { writer, inbox, is_ready: false, is_closed: false }
     }
 
     pub fn new_writer(&self) -> Sender {
@@ -85,7 +126,9 @@ impl<H, I> Recipe<H, I> for CommLink
         Box::pin(futures::stream::unfold(
             self,
             |mut comm_link| async {
-                if !comm_link.is_ready {
+                if comm_link.is_closed {
+                    None
+                } else if !comm_link.is_ready {
                     comm_link.is_ready = true;
                     Some((Message::SetComms(comm_link.new_writer()), comm_link))
                 } else {
@@ -98,7 +141,9 @@ impl<H, I> Recipe<H, I> for CommLink
                             Some((Message::Null, comm_link))
                         },
                         Err(TryRecvError::Disconnected) => {
-                            panic!("Dispatcher has died!!")
+                            // The link ends once the error is reported
+                            comm_link.is_closed = true;
+                            Some((Message::Error("Lost the link to the dispatcher".to_string()), comm_link))
                         },
                     }
                 }
@@ -1,24 +1,66 @@
-use iced::{Column, Length, Row, Text, button, Radio};
+use std::collections::HashSet;
+use std::path::Path;
+use iced::{Column, Length, Row, Text, button, Radio, Checkbox, TextInput, text_input};
 use iced_native::Space;
 use serde::{Serialize, Deserialize};
 
 use crate::comm::{Code, Message, Value};
 use crate::global::Global;
+use crate::sound::{self, Trigger};
 use crate::style::{self, button};
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct Config {
     audio: (AudioConfig, bool),
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio_device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trigger_pulse: Option<TriggerPulse>,
+    #[serde(default)]
+    on_missing_resource: MissingResource,
+    #[serde(default)]
+    options: Vec<ConfigItem>,
+    #[serde(skip)]
+    devices: Vec<String>,
     #[serde(skip)]
     handles: [button::State; 3],
 }
 
 impl Config {
+    pub fn init(&mut self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for item in &mut self.options {
+            if !names.insert(item.name.clone()) {
+                return Err(format!("Duplicate configuration option: `{}`", item.name));
+            }
+            item.init()?;
+        }
+        if let Some(pulse) = &self.trigger_pulse {
+            if pulse.width == 0 {
+                return Err("Trigger pulse width should be positive".to_string());
+            }
+            if pulse.amplitude <= 0.0 || pulse.amplitude > 1.0 {
+                return Err(format!("Trigger pulse amplitude should be in (0, 1]: {}", pulse.amplitude));
+            }
+        }
+        if !self.audio.1 {
+            self.devices = sound::output_devices();
+        }
+        if let Some(device) = &self.audio_device {
+            if !self.devices.is_empty() && !self.devices.contains(device) {
+                println!("Warning: Audio output device `{}` is not available; the default device will be used", device);
+            }
+        }
+        Ok(())
+    }
+
     pub fn is_static(&self) -> bool {
-        self.audio.1
+        self.audio.1 && self.options.iter().all(|item| item.fixed)
     }
 
     pub fn view(&mut self, global: &Global) -> Column<Message> {
+        let devices = (!self.audio.1 && !self.devices.is_empty())
+            .then(|| self.device_view(global));
         let mut content = Column::new()
             .width(Length::Fill)
             .spacing(60)
@@ -30,6 +72,14 @@ impl Config {
         if !self.audio.1 {
             content = content.push(self.audio.0.view(global));
         }
+        if let Some(devices) = devices {
+            content = content.push(devices);
+        }
+        for (i, item) in self.options.iter_mut().enumerate() {
+            if !item.fixed {
+                content = content.push(item.view(i, global));
+            }
+        }
         content = content.push(Space::with_height(Length::Fill));
 
         let [h_cancel, h_revert, h_start] = &mut self.handles;
@@ -66,23 +116,308 @@ impl Config {
             .push(e_start))
     }
 
-    pub fn update(&mut self, code: Code, value: Value) {
+    pub fn update(&mut self, code: Code, value: Value) -> Result<(), String> {
         match (code, value) {
             (0x04, Value::Integer(i)) => {
                 self.audio.0 = match i {
                     1 => AudioConfig::MonoAndTrigger,
                     2 => AudioConfig::Stereo,
-                    _ => panic!("Invalid value for audio config")
+                    _ => return Err(format!("Invalid value for audio config: {}", i)),
+                };
+            }
+
+            (0x05, Value::Integer(i)) => {
+                self.audio_device = match i {
+                    0 => None,
+                    i => Some(self.devices[i as usize - 1].clone()),
                 };
             }
 
-            _ => panic!("Invalid configuration code or value type")
+            (code, value) if code >= 0x10 && ((code - 0x10) as usize) < self.options.len() => {
+                self.options[(code - 0x10) as usize].update(value)?;
+            }
+
+            (code, value) => return Err(format!("Invalid configuration code or value type: {} {:?}", code, value)),
+        }
+        Ok(())
+    }
+
+    /// Output device picker, with the system default as the first option.
+    fn device_view(&self, global: &Global) -> Column<'static, Message> {
+        let selected = match &self.audio_device {
+            Some(device) => self.devices.iter().position(|d| d == device).map(|i| i + 1),
+            None => Some(0),
+        };
+        let mut options = Column::new()
+            .spacing(15)
+            .push(Radio::new(
+                0,
+                "System default",
+                selected,
+                |_| Message::UIEvent(0x05, Value::Integer(0)))
+                .text_size(global.text_size("LARGE")));
+        for (i, device) in self.devices.iter().enumerate() {
+            options = options.push(Radio::new(
+                i + 1,
+                device.clone(),
+                selected,
+                move |_| Message::UIEvent(0x05, Value::Integer(i as i32 + 1)))
+                .text_size(global.text_size("LARGE")));
         }
+
+        Column::new()
+            .align_items(global.alignment())
+            .spacing(25)
+            .push(Text::new("Output audio device")
+                .size(global.text_size("LARGE")))
+            .push(options)
+    }
+
+    /// Name of the selected audio output device, or `None` for the system default.
+    pub fn audio_device(&self) -> Option<&str> {
+        self.audio_device.as_deref()
+    }
+
+    /// Overrides a setting of the task file, e.g. for a particular machine: `audio`
+    /// (`mono_and_trigger` or `stereo`), `audio_device` (empty for the system default), or a
+    /// task-defined option by name (ignoring case).
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "audio" => {
+                self.audio.0 = serde_yaml::from_str(value)
+                    .map_err(|_| format!("Invalid audio configuration: {} (use mono_and_trigger or stereo)", value))?;
+            }
+            "audio_device" if value.is_empty() => self.audio_device = None,
+            "audio_device" => self.audio_device = Some(value.to_string()),
+            _ => {
+                self.options.iter_mut()
+                    .find(|item| item.name.eq_ignore_ascii_case(key))
+                    .ok_or_else(|| format!("Unknown setting: `{}`", key))?
+                    .set(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a key names a setting that `set` accepts.
+    pub fn is_setting(&self, key: &str) -> bool {
+        matches!(key, "audio" | "audio_device") || self.options.iter().any(|item| item.name.eq_ignore_ascii_case(key))
+    }
+
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|item| item.name == name)
+    }
+
+    /// Current value of a task-defined option, in the form used for parameter substitution.
+    pub fn value(&self, name: &str) -> Option<String> {
+        self.options
+            .iter()
+            .find(|item| item.name == name)
+            .map(ConfigItem::value)
     }
 
     pub fn use_trigger(&self) -> bool {
         matches!(self.audio.0, AudioConfig::MonoAndTrigger)
     }
+
+    /// Trigger channel to play alongside an audio file, if triggers are in use.
+    pub fn trigger(&self, source: &Path) -> Trigger {
+        match &self.trigger_pulse {
+            _ if !self.use_trigger() => Trigger::None,
+            Some(pulse) => Trigger::Pulses {
+                width_ms: pulse.width,
+                amplitude: pulse.amplitude,
+            },
+            None => Trigger::File(source.with_extension("trig.wav")),
+        }
+    }
+
+    /// Whether triggers are read from `.trig.wav` files paired with the audio files.
+    pub fn uses_trigger_files(&self) -> bool {
+        self.use_trigger() && self.trigger_pulse.is_none()
+    }
+
+    pub fn skip_missing_resources(&self) -> bool {
+        matches!(self.on_missing_resource, MissingResource::Skip)
+    }
+}
+
+/// A trigger channel synthesized as square pulses, instead of read from `.trig.wav` files.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerPulse {
+    /// Pulse width in milliseconds
+    #[serde(default = "TriggerPulse::default_width")]
+    width: u32,
+    /// Pulse amplitude as a fraction of full scale
+    #[serde(default = "TriggerPulse::default_amplitude")]
+    amplitude: f32,
+}
+
+impl TriggerPulse {
+    fn default_width() -> u32 { 10 }
+
+    fn default_amplitude() -> f32 { 1.0 }
+}
+
+/// A task-defined option that can be changed on the configuration screen, and whose value
+/// is available to actions as a `{{config:name}}` reference.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigItem {
+    name: String,
+    #[serde(default)]
+    prompt: String,
+    #[serde(flatten)]
+    kind: ConfigKind,
+    #[serde(default)]
+    fixed: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConfigKind {
+    Choice {
+        options: Vec<String>,
+        value: String,
+    },
+    Toggle {
+        value: bool,
+    },
+    Number {
+        value: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range: Option<(f32, f32)>,
+        #[serde(skip)]
+        input: String,
+        #[serde(skip)]
+        handle: text_input::State,
+    },
+}
+
+impl ConfigItem {
+    fn init(&mut self) -> Result<(), String> {
+        if self.prompt.is_empty() {
+            self.prompt = self.name.clone();
+        }
+        match &mut self.kind {
+            ConfigKind::Choice { options, value } => {
+                if !options.contains(value) {
+                    return Err(format!("Invalid value for configuration option `{}`: {}", self.name, value));
+                }
+            }
+            ConfigKind::Toggle { .. } => (),
+            ConfigKind::Number { value, range, input, .. } => {
+                if let Some((min, max)) = range {
+                    if *value < *min || *value > *max {
+                        return Err(format!("Value for configuration option `{}` out of range: {}", self.name, value));
+                    }
+                }
+                *input = value.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the value from its textual form; choices and ranges are checked by `init`.
+    fn set(&mut self, new_value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid value for configuration option `{}`: {}", self.name, new_value);
+        match &mut self.kind {
+            ConfigKind::Choice { value, .. } => *value = new_value.to_string(),
+            ConfigKind::Toggle { value } => *value = new_value.trim().parse().map_err(|_| invalid())?,
+            ConfigKind::Number { value, .. } => *value = new_value.trim().parse().map_err(|_| invalid())?,
+        }
+        Ok(())
+    }
+
+    fn value(&self) -> String {
+        match &self.kind {
+            ConfigKind::Choice { value, .. } => value.clone(),
+            ConfigKind::Toggle { value } => value.to_string(),
+            ConfigKind::Number { value, .. } => value.to_string(),
+        }
+    }
+
+    fn update(&mut self, new_value: Value) -> Result<(), String> {
+        match (&mut self.kind, new_value) {
+            (ConfigKind::Choice { options, value }, Value::Integer(i)) => {
+                *value = options[i as usize].clone();
+            }
+            (ConfigKind::Toggle { value }, Value::Bool(b)) => {
+                *value = b;
+            }
+            (ConfigKind::Number { value, range, input, .. }, Value::String(s)) => {
+                if let Ok(x) = s.trim().parse::<f32>() {
+                    if range.is_none_or(|(min, max)| x >= min && x <= max) {
+                        *value = x;
+                    }
+                }
+                *input = s;
+            }
+            (_, value) => return Err(format!("Invalid value type for configuration option `{}`: {:?}", self.name, value)),
+        }
+        Ok(())
+    }
+
+    fn view(&mut self, index: usize, global: &Global) -> Column<'_, Message> {
+        let code = (0x10 + index) as u16;
+        let element: iced::Element<Message> = match &mut self.kind {
+            ConfigKind::Choice { options, value } => {
+                let selected = options.iter().position(|o| o == value);
+                let mut row = Row::new().spacing(40);
+                for (i, option) in options.iter().enumerate() {
+                    row = row.push(Radio::new(
+                        i,
+                        option.clone(),
+                        selected,
+                        move |_| Message::UIEvent(code, Value::Integer(i as i32)))
+                        .text_size(global.text_size("LARGE")));
+                }
+                row.into()
+            }
+            ConfigKind::Toggle { value } => {
+                Checkbox::new(
+                    *value,
+                    "",
+                    move |b| Message::UIEvent(code, Value::Bool(b)))
+                    .size(global.text_size("LARGE"))
+                    .into()
+            }
+            ConfigKind::Number { value, range, input, handle } => {
+                let mut row = Row::new()
+                    .spacing(20)
+                    .push(TextInput::new(
+                        handle,
+                        &value.to_string(),
+                        input,
+                        move |s| Message::UIEvent(code, Value::String(s)))
+                        .size(global.text_size("LARGE"))
+                        .width(Length::Units(150)));
+                if let Some((min, max)) = range {
+                    row = row.push(Text::new(format!("({} - {})", min, max))
+                        .size(global.text_size("NORMAL")));
+                }
+                row.into()
+            }
+        };
+
+        Column::new()
+            .align_items(global.alignment())
+            .spacing(25)
+            .push(Text::new(self.prompt.as_str())
+                .size(global.text_size("LARGE")))
+            .push(element)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingResource {
+    Error,
+    Skip,
+}
+
+impl Default for MissingResource {
+    fn default() -> Self { MissingResource::Error }
 }
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Copy, Clone)]
@@ -124,12 +459,14 @@ impl AudioConfig {
     }
 }
 
-impl From<String> for AudioConfig {
-    fn from(value: String) -> Self {
+impl TryFrom<String> for AudioConfig {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
         match value.as_str() {
-            "MonoAndTrigger" => AudioConfig::MonoAndTrigger,
-            "Stereo" => AudioConfig::Stereo,
-            _ => panic!("Unexpected value"),
+            "MonoAndTrigger" => Ok(AudioConfig::MonoAndTrigger),
+            "Stereo" => Ok(AudioConfig::Stereo),
+            _ => Err(format!("Invalid audio configuration: {}", value)),
         }
     }
 }
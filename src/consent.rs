@@ -0,0 +1,128 @@
+use std::path::Path;
+use iced::{Checkbox, Column, Element, Length, Row, Scrollable, Text, button, scrollable};
+use iced_native::Space;
+use serde::{Serialize, Deserialize};
+
+use crate::comm::{Code, Message, Value};
+use crate::error::Error;
+use crate::global::Global;
+use crate::markup;
+use crate::style::{self, button};
+use crate::util::{resource, timestamp};
+
+/// Consent form shown before the start screen. The subject has to tick the agreement box to
+/// continue, or can decline, which ends the task. The decision is written to the session log.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Consent {
+    /// Resource file with the text of the form
+    file: String,
+    #[serde(default="default::agree")]
+    agree: String,
+    #[serde(skip_deserializing, skip_serializing_if="Option::is_none")]
+    decision: Option<Decision>,
+    #[serde(skip)]
+    text: String,
+    #[serde(skip)]
+    agreed: bool,
+    #[serde(skip)]
+    scroll: scrollable::State,
+    #[serde(skip)]
+    handles: [button::State; 2],
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Decision {
+    agreed: bool,
+    time: String,
+}
+
+impl Consent {
+    pub fn init(&mut self, task_dir: &Path) -> Result<(), Error> {
+        let file = resource(task_dir, &self.file)?;
+        self.text = std::fs::read_to_string(&file)
+            .map_err(|e| Error::io(format!("Failed to read consent form {:?}: {}", file, e), e))?;
+        Ok(())
+    }
+
+    /// Records the decision of the subject, with the time it was made.
+    pub fn decide(&mut self, agreed: bool) {
+        self.decision = Some(Decision {
+            agreed,
+            time: timestamp(),
+        });
+    }
+
+    pub fn is_agreed(&self) -> bool {
+        self.agreed
+    }
+
+    pub fn view(&mut self, global: &Global) -> Column<'_, Message> {
+        let size = global.text_size("LARGE");
+        let text: Element<Message> = if global.rich_text().is_some() {
+            markup::view(&self.text, size, style::palette().text, global.horizontal_alignment(), global)
+                .into()
+        } else {
+            Text::new(&self.text)
+                .size(size)
+                .horizontal_alignment(global.horizontal_alignment())
+                .into()
+        };
+
+        let [h_decline, h_continue] = &mut self.handles;
+        let e_decline = button(
+            h_decline,
+            "Decline",
+            global.text_size("LARGE"))
+            .on_press(Message::UIEvent(0x01, Value::Null))
+            .style(style::Button::Secondary)
+            .width(Length::Units(200))
+            .padding(15);
+        let mut e_continue = button(
+            h_continue,
+            "Continue",
+            global.text_size("LARGE"))
+            .style(style::Button::Primary)
+            .width(Length::Units(200))
+            .padding(15);
+        if self.agreed {
+            e_continue = e_continue.on_press(Message::UIEvent(0x02, Value::Null));
+        }
+
+        Column::new()
+            .width(Length::Fill)
+            .spacing(40)
+            .align_items(global.alignment())
+            .push(Text::new("Consent")
+                .size(global.text_size("XLARGE"))
+                .horizontal_alignment(global.horizontal_alignment()))
+            .push(Scrollable::new(&mut self.scroll)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .push(text))
+            .push(Checkbox::new(
+                self.agreed,
+                self.agree.clone(),
+                |b| Message::UIEvent(0x10, Value::Bool(b)))
+                .size(size)
+                .text_size(size))
+            .push(Row::new()
+                .push(e_decline)
+                .push(Space::with_width(Length::Fill))
+                .push(e_continue))
+    }
+
+    pub fn update(&mut self, code: Code, value: Value) -> Result<(), String> {
+        match (code, value) {
+            (0x10, Value::Bool(b)) => self.agreed = b,
+            (code, value) => return Err(format!("Invalid consent form code or value type: {} {:?}", code, value)),
+        }
+        Ok(())
+    }
+}
+
+mod default {
+    pub fn agree() -> String {
+        "I agree".to_string()
+    }
+}
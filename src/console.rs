@@ -0,0 +1,130 @@
+use std::sync::Mutex;
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+
+// Latest state of the running task, published for the operator console, and the number of
+// times it has changed
+static SNAPSHOT: Mutex<(usize, Option<Snapshot>)> = Mutex::new((0, None));
+
+/// State of the task as last published, with the onsets that elapsed times are measured from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub task: String,
+    pub state: String,
+    pub session_onset: Option<Instant>,
+    pub block: Option<String>,
+    pub block_onset: Option<Instant>,
+    pub running: Vec<String>,
+    pub completed: usize,
+    pub foreground: Option<String>,
+    pub last_key: Option<(String, Instant)>,
+    pub upcoming: Vec<String>,
+    pub remaining: usize,
+    pub progress: Option<(usize, usize)>,
+}
+
+/// What the operator console shows, as sent to it over the remote control link.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Status {
+    task: String,
+    state: String,
+    session_s: f64,
+    block: Option<String>,
+    block_s: Option<f64>,
+    running: Vec<String>,
+    completed: usize,
+    foreground: Option<String>,
+    last_key: Option<String>,
+    last_key_s: Option<f64>,
+    upcoming: Vec<String>,
+    remaining: usize,
+    progress: Option<(usize, usize)>,
+}
+
+pub fn publish(snapshot: Snapshot) {
+    let mut latest = SNAPSHOT.lock().unwrap();
+    if latest.1.as_ref() != Some(&snapshot) {
+        latest.0 += 1;
+        latest.1 = Some(snapshot);
+    }
+}
+
+/// Number of times the published state has changed, so that it is only sent on when it does.
+pub fn version() -> usize {
+    SNAPSHOT.lock().unwrap().0
+}
+
+/// The status of the task right now, if it has published any.
+pub fn status() -> Option<Status> {
+    let snapshot = SNAPSHOT.lock().unwrap().1.clone()?;
+    let seconds = |onset: Instant| onset.elapsed().as_secs_f64();
+    Some(Status {
+        task: snapshot.task,
+        state: snapshot.state,
+        session_s: snapshot.session_onset.map(seconds).unwrap_or_default(),
+        block: snapshot.block,
+        block_s: snapshot.block_onset.map(seconds),
+        running: snapshot.running,
+        completed: snapshot.completed,
+        foreground: snapshot.foreground,
+        last_key_s: snapshot.last_key.as_ref().map(|(_, time)| seconds(*time)),
+        last_key: snapshot.last_key.map(|(key, _)| key),
+        upcoming: snapshot.upcoming,
+        remaining: snapshot.remaining,
+        progress: snapshot.progress,
+    })
+}
+
+/// Formats a number of seconds as hh:mm:ss.
+fn clock(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+impl Status {
+    /// Lines of text shown by the operator console.
+    pub fn render(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("{}  [{}]", self.task, self.state),
+            format!("Session time:  {}", clock(self.session_s)),
+        ];
+        if let (Some(block), Some(block_s)) = (&self.block, self.block_s) {
+            lines.push(format!("Block:         {}  ({})", block, clock(block_s)));
+            if let Some((complete, total)) = self.progress {
+                lines.push(format!("Progress:      {} / {} actions", complete, total));
+            }
+            let running: Vec<_> = self.running
+                .iter()
+                .map(|id| match &self.foreground {
+                    Some(fg) if fg == id => format!("{} (on screen)", id),
+                    _ => id.clone(),
+                })
+                .collect();
+            lines.push(format!("Running:       {}", running.join(", ")));
+            let more = self.remaining.saturating_sub(self.upcoming.len());
+            lines.push(match more {
+                0 => format!("Upcoming:      {}", self.upcoming.join(", ")),
+                _ => format!("Upcoming:      {}, ... ({} more)", self.upcoming.join(", "), more),
+            });
+        }
+        if let (Some(key), Some(key_s)) = (&self.last_key, self.last_key_s) {
+            lines.push(format!("Last key:      {}  ({:.1} s ago)", key, key_s));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_a_status_with_fewer_remaining_than_upcoming() {
+        let status: Status = serde_json::from_str(r#"{
+            "task": "Demo", "state": "block", "session_s": 12.0, "block": "Main", "block_s": 3.0,
+            "running": ["a"], "completed": 1, "foreground": "a", "last_key": null, "last_key_s": null,
+            "upcoming": ["b", "c"], "remaining": 1, "progress": [1, 3]
+        }"#).unwrap();
+        assert!(status.render().contains(&"Upcoming:      b, c".to_string()));
+    }
+}
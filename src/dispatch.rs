@@ -1,11 +1,44 @@
 use std::collections::HashSet;
-use iced::{Command, Column};
+use std::time::{Duration, Instant};
+use iced::{Align, Color, Command, Column, Length, ProgressBar, Space, Text, button, HorizontalAlignment};
 
-use crate::action::ID;
-use crate::block::Block;
-use crate::comm::{Message, Sender};
+use crate::action::{ID, Question, flow, view};
+use crate::block::{Block, ProbeRecord, ProgressDisplay};
+use crate::comm::{report, Message, Sender};
 use crate::global::Global;
+use crate::sound::{self, Playback};
+use crate::style::{button, TextStyle};
+use crate::util::timestamp;
 
+/// The outcome of resolving the action graph after a block starts or an action finishes.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Step {
+    pub start: Vec<ID>,
+    pub status: Status,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Status {
+    #[default]
+    Running,
+    Complete,
+    Deadlock,
+}
+
+/// A thought probe that is currently interrupting the block.
+#[derive(Debug)]
+struct Probe {
+    question: Question,
+    onset: Instant,
+    timestamp: String,
+    interrupted: Vec<ID>,
+    handle: button::State,
+}
+
+/// Runs the blocks of a task. Resolving the action graph (`begin`, `finish`, and `next`, over
+/// `Block::satisfy`, `wrap`, and `skip`) works on plain data and returns `Step`s, which can be
+/// checked without a window; starting the actions of a step (`run`), and passing them the
+/// `writer` they report back through, is left to the iced side.
 #[derive(Debug)]
 pub struct Dispatcher {
     writer: Sender,
@@ -16,6 +49,12 @@ pub struct Dispatcher {
     foreground: Option<ID>,
     background: Option<ID>,
     monitor_kb: Option<ID>,
+    cursor: Option<(f32, f32)>,
+    probe: Option<Probe>,
+    probe_seq: u32,
+    loading: Option<Block>,
+    load_seq: u32,
+    last_key: Option<(String, Instant)>,
 }
 
 impl Dispatcher {
@@ -29,6 +68,12 @@ impl Dispatcher {
             foreground: None,
             background: None,
             monitor_kb: None,
+            cursor: None,
+            probe: None,
+            probe_seq: 0,
+            loading: None,
+            load_seq: 0,
+            last_key: None,
         }
     }
 
@@ -37,34 +82,110 @@ impl Dispatcher {
     }
 
     pub fn is_active(&self) -> bool {
-        self.block.is_some()
+        self.block.is_some() || self.loading.is_some()
     }
 
+    /// Decodes the audio of the block into memory, and starts it once done.
     pub fn init(&mut self, block: Block, global: &Global) -> Command<Message> {
-        self.queue = HashSet::from_iter(block.actions());
-        self.block = Some(block);
-        self.next(HashSet::from(["entry".to_string()]), global)
+        let sources = block.audio_sources(global);
+        self.loading = Some(block);
+        self.load_seq += 1;
+        let seq = self.load_seq;
+        Command::perform(async move {
+            sound::preload(&sources)
+        }, move |result| Message::Preloaded(seq, result))
+    }
+
+    fn start(&mut self, block: Block, global: &Global) -> Command<Message> {
+        let step = self.begin(block);
+        let command = self.run(step, global);
+        Command::batch([command, self.schedule_probe()])
     }
 
     pub fn update(&mut self, message: Message, global: &Global) -> Command<Message> {
+        if self.loading.is_some() {
+            return match message {
+                Message::Preloaded(seq, result) if seq == self.load_seq => {
+                    if let Err(e) = result {
+                        println!("Warning: Failed to preload some audio; it will be decoded during playback: {}", e);
+                    }
+                    let block = self.loading.take().unwrap();
+                    self.start(block, global)
+                }
+                Message::Interrupt => {
+                    self.loading = None;
+                    sound::clear_preloaded();
+                    Command::none()
+                }
+                _ => Command::none(),
+            };
+        }
         if self.block.is_none() {
             return Command::none()
         }
+        if let Message::KeyPress(key_code) = &message {
+            self.last_key = Some((format!("{:?}", key_code), Instant::now()));
+        }
 
         match &message {
+            Message::Probe(seq) => {
+                if *seq == self.probe_seq && self.probe.is_none() {
+                    self.start_probe();
+                }
+                Command::none()
+            }
+            Message::ProbeComplete => {
+                self.finish_probe()
+            }
+            Message::KeyPress(_) |
+            Message::UIEvent(..) if self.probe.is_some() => {
+                if let Message::UIEvent(_, value) = message {
+                    if let Err(e) = self.probe.as_mut().unwrap().question.update(value) {
+                        return report(e);
+                    }
+                }
+                Command::none()
+            }
+            Message::KeyRelease(_) if self.probe.is_some() => {
+                Command::none()
+            }
+            Message::Code(_, id, ..) |
+            Message::Value(_, id, ..) if !self.active.contains(id) => {
+                Command::none()
+            }
             Message::Code(_, id, ..) |
             Message::Value(_, id, ..) |
             Message::QueryResponse(id, ..) => {
                 self.block.as_mut().unwrap().update(id, message.clone(), global)
             }
-            Message::KeyPress(_) => {
+            Message::KeyPress(_) |
+            Message::KeyRelease(_) => {
+                // Key presses go to the action monitoring the keyboard, and also to the
+                // foreground action, e.g. for an instruction that advances on a key press
+                let mut commands = vec![];
                 if let Some(id) = &self.monitor_kb {
-                    self.block.as_mut().unwrap().update(id, message.clone(), global)
-                } else if let Some(id) = &self.foreground {
-                    self.block.as_mut().unwrap().update(id, message.clone(), global)
-                } else {
-                    Command::none()
+                    commands.push(self.block.as_mut().unwrap().update(id, message.clone(), global));
+                }
+                if let Some(id) = &self.foreground {
+                    if self.monitor_kb.as_ref() != Some(id) {
+                        commands.push(self.block.as_mut().unwrap().update(id, message.clone(), global));
+                    }
                 }
+                Command::batch(commands)
+            }
+            Message::CursorMoved(x, y) => {
+                self.cursor = Some((*x, *y));
+                Command::none()
+            }
+            Message::MouseSample => {
+                // The latest cursor position is sampled at a fixed rate, rather than on every move
+                if let (Some(id), Some((x, y))) = (&self.foreground, self.cursor) {
+                    let block = self.block.as_mut().unwrap();
+                    if block.tracks_mouse(id) {
+                        block.record_cursor(id, x, y);
+                    }
+                }
+                Command::none()
             }
             Message::UIEvent(..) => {
                 if let Some(id) = &self.foreground {
@@ -74,6 +195,24 @@ impl Dispatcher {
                 }
             }
             Message::ActionComplete(id) => {
+                if self.active.contains(id) {
+                    self.block.as_mut().unwrap().respond(id);
+                }
+                self.complete(id.clone(), global)
+            }
+            Message::Playback(id, result) => {
+                if self.active.contains(id) {
+                    self.block.as_mut().unwrap().playback(id, result.clone());
+                    if *result != Playback::Interrupted {
+                        return self.complete(id.clone(), global);
+                    }
+                }
+                Command::none()
+            }
+            Message::Timeout(id) => {
+                if self.active.contains(id) {
+                    self.block.as_mut().unwrap().time_out(id);
+                }
                 self.complete(id.clone(), global)
             }
             Message::Interrupt |
@@ -81,20 +220,98 @@ impl Dispatcher {
                 if self.block.is_some() {
                     self.wrap_unfinished();
                     self.block = None;
+                    sound::clear_preloaded();
                     self.queue.clear();
                     self.active.clear();
                     self.foreground = None;
                     self.complete.clear();
+                    self.probe = None;
+                    self.probe_seq += 1;
                 }
                 Command::none()
             }
-            _ => panic!("Invalid message type for relaying")
+            message => report(format!("Invalid message type for relaying: {:?}", message)),
         }
     }
 
     pub fn complete(&mut self, id: ID, global: &Global) -> Command<Message> {
+        let step = self.finish(id);
+        self.run(step, global)
+    }
+
+    /// Starts the actions of a step, and translates its status into an iced command. Actions
+    /// skipped as they start (for a missing resource) are finished at once, and the actions
+    /// that become ready as a result are started in turn.
+    fn run(&mut self, mut step: Step, global: &Global) -> Command<Message> {
+        let mut commands = vec![];
+        loop {
+            let block = match self.block.as_mut() {
+                Some(block) => block,
+                None => return Command::none(),
+            };
+            let mut skipped = vec![];
+            for id in &step.start {
+                match block.execute(id, self.writer.clone(), global) {
+                    Ok(Some(command)) => {
+                        if self.probe.is_some() {
+                            block.pause(id);
+                        }
+                        commands.push(command);
+                    }
+                    Ok(None) => skipped.push(id.clone()),
+                    Err(e) => commands.push(report(e.to_string())),
+                }
+            }
+            if skipped.is_empty() {
+                break;
+            }
+            step = self.skip(skipped);
+        }
+
+        match step.status {
+            _ if !commands.is_empty() => Command::batch(commands),
+            Status::Running => Command::none(),
+            Status::Complete => Command::perform(async {}, |()| Message::BlockComplete),
+            Status::Deadlock => report("Arrived at a deadlock; unable to reach some actions".to_string()),
+        }
+    }
+
+    /// Finishes actions that were skipped as they started, and resolves the actions that
+    /// become ready to start as a result.
+    fn skip(&mut self, ids: Vec<ID>) -> Step {
+        let block = self.block.as_mut().unwrap();
+        let mut ready = HashSet::new();
+        let mut expired = HashSet::new();
+        for id in ids {
+            self.active.remove(&id);
+            self.complete.insert(id.clone());
+            let (ready2, expired2) = block.skip(&id);
+            ready.extend(ready2);
+            expired.extend(expired2);
+        }
+        let mut step = self.next(ready);
+        if !expired.is_empty() {
+            // Actions that run with a skipped one end with it
+            for id in expired {
+                step.start.extend(self.finish(id).start);
+            }
+            step.status = self.next(HashSet::new()).status;
+        }
+        step
+    }
+
+    /// Loads a block and resolves the actions that are ready to start at its entry point.
+    pub fn begin(&mut self, block: Block) -> Step {
+        self.queue = HashSet::from_iter(block.actions());
+        self.block = Some(block);
+        self.next(HashSet::from(["entry".to_string()]))
+    }
+
+    /// Marks an action (and, transitively, its dependents) as finished, and resolves the
+    /// actions that become ready to start as a result.
+    pub fn finish(&mut self, id: ID) -> Step {
         if self.block.is_none() || self.complete.contains(&id) {
-            return Command::none();
+            return Step::default();
         }
         let block = self.block.as_mut().unwrap();
 
@@ -123,12 +340,15 @@ impl Dispatcher {
         if let Some(id) = &self.monitor_kb {
             if self.complete.contains(id) { self.monitor_kb = None; }
         }
-        self.next(ready, global)
+        self.next(ready)
     }
 
-    pub fn next(&mut self, mut ready: HashSet<ID>, global: &Global) -> Command<Message> {
+    /// Resolves which of the ready actions should start, skipping expired ones, and
+    /// marks them as active. Reaching an end_block action completes the block at once.
+    /// The returned step lists the actions in the order they should be started.
+    pub fn next(&mut self, mut ready: HashSet<ID>) -> Step {
         let block = self.block.as_mut().unwrap();
-        let mut commands = vec![];
+        let mut start = vec![];
         while !ready.is_empty() {
             let mut new_ready = HashSet::new();
             for id in ready {
@@ -143,6 +363,15 @@ impl Dispatcher {
                         new_ready.extend(ready2);
                         expired = expired2;
                     }
+                } else if block.ends_block(&id) {
+                    // Actions resolved in this step never started, so they are dropped
+                    // rather than wrapped along with the ones already running
+                    for id in start {
+                        self.active.remove(&id);
+                    }
+                    self.queue.clear();
+                    self.complete.insert(id);
+                    return Step { start: vec![], status: Status::Complete };
                 } else {
                     if block.has_view(&id) {
                         self.foreground = Some(id.clone());
@@ -159,25 +388,136 @@ impl Dispatcher {
                         }
                     }
                     self.queue.remove(&id);
-                    let command = block.execute(&id, self.writer.clone(), global);
-                    self.active.insert(id);
-                    commands.push(command);
+                    self.active.insert(id.clone());
+                    start.push(id);
                 }
             }
             ready = new_ready;
         }
 
-        if !commands.is_empty() {
-            Command::batch(commands)
-        } else if !self.active.is_empty() {
-            Command::none()
+        let status = if !start.is_empty() || !self.active.is_empty() {
+            Status::Running
         } else if self.queue.is_empty() {
-            Command::perform(async {}, |()| Message::BlockComplete)
+            Status::Complete
         } else {
-            panic!("Arrived at a deadlock; unable to reach some actions")
+            Status::Deadlock
+        };
+        Step { start, status }
+    }
+
+    /// Schedules the next thought probe of the block, if it has any.
+    fn schedule_probe(&mut self) -> Command<Message> {
+        let block = match self.block.as_mut() {
+            Some(block) => block,
+            None => return Command::none(),
+        };
+        let (min, max) = match block.probes() {
+            Some(probes) => probes.interval(),
+            None => return Command::none(),
+        };
+        let delay = block.random_range(min, max);
+        let seq = self.probe_seq;
+        Command::perform(async move {
+            std::thread::sleep(Duration::from_secs(delay as u64));
+        }, move |()| Message::Probe(seq))
+    }
+
+    /// Suspends the active actions and presents a randomly chosen probe question.
+    fn start_probe(&mut self) {
+        let block = self.block.as_mut().unwrap();
+        let count = block.probes().unwrap().questions().len();
+        let index = block.random_range(0, count as u32 - 1) as usize;
+        let question = block.probes().unwrap().questions()[index].clone();
+
+        let mut interrupted: Vec<_> = self.active.iter().cloned().collect();
+        interrupted.sort();
+        for id in &interrupted {
+            block.pause(id);
+        }
+        self.probe = Some(Probe {
+            question,
+            onset: Instant::now(),
+            timestamp: timestamp(),
+            interrupted,
+            handle: button::State::new(),
+        });
+    }
+
+    /// Records the probe response, resumes the suspended actions, and schedules the next probe.
+    fn finish_probe(&mut self) -> Command<Message> {
+        let probe = match self.probe.take() {
+            Some(probe) => probe,
+            None => return Command::none(),
+        };
+        let block = self.block.as_mut().unwrap();
+        for id in &self.active {
+            block.resume(id);
+        }
+        block.record_probe(ProbeRecord {
+            onset: probe.timestamp,
+            interrupted: probe.interrupted,
+            duration_ms: probe.onset.elapsed().as_secs_f64() * 1000.0,
+            question: probe.question,
+        });
+        self.schedule_probe()
+    }
+
+    pub fn block_mut(&mut self) -> Option<&mut Block> {
+        self.block.as_mut()
+    }
+
+    /// Whether the foreground action is tracking the mouse cursor.
+    pub fn tracks_mouse(&self) -> bool {
+        match (&self.block, &self.foreground) {
+            (Some(block), Some(id)) => block.tracks_mouse(id),
+            _ => false,
+        }
+    }
+
+    pub fn active(&self) -> &HashSet<ID> {
+        &self.active
+    }
+
+    pub fn foreground(&self) -> Option<&ID> {
+        self.foreground.as_ref()
+    }
+
+    /// Number of completed (or skipped) actions of the running block, and its total number of
+    /// actions, leaving out the gates that mark the start and end of action lists.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        let block = self.block.as_ref()?;
+        let total = block.actions().iter().filter(|id| !flow::is_gate(id)).count();
+        let complete = self.complete.iter().filter(|id| !flow::is_gate(id)).count();
+        Some((complete, total))
+    }
+
+    pub fn last_key(&self) -> Option<&(String, Instant)> {
+        self.last_key.as_ref()
+    }
+
+    /// The running actions, and the actions that have yet to start, in block order.
+    pub fn schedule(&self) -> (Vec<ID>, Vec<ID>) {
+        match &self.block {
+            Some(block) => {
+                let actions = block.actions();
+                (
+                    actions.iter().filter(|id| self.active.contains(*id)).cloned().collect(),
+                    actions.into_iter().filter(|id| self.queue.contains(id)).collect(),
+                )
+            }
+            None => (vec![], vec![]),
         }
     }
 
+    /// Number of actions of the running block that are complete (or skipped).
+    pub fn completed(&self) -> usize {
+        self.complete.len()
+    }
+
+    pub fn block(&self) -> Option<&Block> {
+        self.block.as_ref()
+    }
+
     pub fn wrap_unfinished(&mut self) {
         let block = self.block.as_mut().unwrap();
         for action in &self.active {
@@ -187,13 +527,74 @@ impl Dispatcher {
     }
 
     pub fn view(&mut self, global: &Global) -> Column<Message> {
-        if let Some(id) = &self.foreground {
-            self.block.as_mut().unwrap().view(id, global)
-        } else if let Some(id) = &self.background {
-            self.block.as_mut().unwrap().background(id)
-        } else {
+        let progress = self.progress_view(global);
+        if self.loading.is_some() {
             Column::new()
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .align_items(Align::Center)
+                .push(Space::with_height(Length::Fill))
+                .push(Text::new("Loading...")
+                    .size(global.text_size("XLARGE")))
+                .push(Space::with_height(Length::Fill))
+        } else if let Some(probe) = &mut self.probe {
+            let e_submit = button(
+                &mut probe.handle,
+                "Submit",
+                global.text_size("XLARGE"))
+                .on_press(Message::ProbeComplete)
+                .width(Length::Units(400));
+
+            Column::new()
+                .align_items(Align::Center)
+                .push(Space::with_height(Length::Fill))
+                .push(view::question(&mut probe.question, 0, false, &TextStyle::default(), global))
+                .push(Space::with_height(Length::Fill))
+                .push(e_submit)
+        } else {
+            let content = if let Some(id) = &self.foreground {
+                self.block.as_mut().unwrap().view(id, global)
+            } else if let Some(id) = &self.background {
+                self.block.as_mut().unwrap().background(id)
+            } else {
+                Column::new()
+            };
+            match progress {
+                Some(progress) => Column::new()
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .push(progress)
+                    .push(content.height(Length::Fill)),
+                None => content,
+            }
+        }
+    }
+
+    /// Background color of the window, taken from the action on screen, or else from the
+    /// action that shows the background.
+    pub fn backdrop(&self) -> Option<Color> {
+        if self.loading.is_some() || self.probe.is_some() {
+            return None;
         }
+        let block = self.block.as_ref()?;
+        self.foreground.as_ref().and_then(|id| block.backdrop(id))
+            .or_else(|| self.background.as_ref().and_then(|id| block.backdrop(id)))
+    }
+
+    fn progress_view(&self, global: &Global) -> Option<Column<'static, Message>> {
+        let display = self.block.as_ref()?.progress_display()?;
+        let (complete, total) = self.progress()?;
+        let element = match display {
+            ProgressDisplay::Counter => Column::new()
+                .push(Text::new(format!("{} / {}", complete, total))
+                    .size(global.text_size("SMALL"))
+                    .width(Length::Fill)
+                    .horizontal_alignment(HorizontalAlignment::Right)),
+            ProgressDisplay::Bar => Column::new()
+                .push(ProgressBar::new(0.0..=total.max(1) as f32, complete as f32)
+                    .height(Length::Units(6))),
+        };
+        Some(element)
     }
 
     pub fn active_title(&self) -> String {
@@ -204,3 +605,127 @@ impl Dispatcher {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use super::*;
+    use crate::block::tests::block;
+
+    fn begin(yaml: &str) -> (Dispatcher, Step) {
+        let (writer, _) = mpsc::channel();
+        let mut dispatcher = Dispatcher::new(writer);
+        let log_dir = std::env::temp_dir().join("task-runner-tests");
+        let step = dispatcher.begin(block(yaml).unwrap().with_log_dir(log_dir.to_str().unwrap()));
+        (dispatcher, step)
+    }
+
+    fn starts(ids: &[&str]) -> Step {
+        Step { start: ids.iter().map(|id| id.to_string()).collect(), status: Status::Running }
+    }
+
+    #[test]
+    fn runs_actions_in_order() {
+        let (mut dispatcher, step) = begin(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100 }
+              - { id: b, type: nothing, timeout: 100, after: [ a ] }
+        "#);
+        assert_eq!(step, starts(&["a"]));
+        assert_eq!(dispatcher.finish("a".to_string()), starts(&["b"]));
+        assert_eq!(dispatcher.finish("b".to_string()).status, Status::Complete);
+    }
+
+    #[test]
+    fn ends_actions_with_the_one_they_run_with() {
+        let (mut dispatcher, step) = begin(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100 }
+              - { id: b, type: nothing, with: a }
+        "#);
+        assert_eq!(step, starts(&["a", "b"]));
+        assert_eq!(dispatcher.finish("a".to_string()).status, Status::Complete);
+        assert!(dispatcher.active().is_empty());
+    }
+
+    #[test]
+    fn ignores_actions_that_already_finished() {
+        let (mut dispatcher, _) = begin(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100 }
+              - { id: b, type: nothing, timeout: 100, after: [ a ] }
+        "#);
+        dispatcher.finish("a".to_string());
+        assert_eq!(dispatcher.finish("a".to_string()), Step::default());
+    }
+
+    #[test]
+    fn completes_the_block_at_an_end_block_action() {
+        let (mut dispatcher, _) = begin(r#"
+            title: B
+            actions:
+              - { id: a, type: nothing, timeout: 100 }
+              - { id: stop, type: end_block, after: [ a ] }
+              - { id: b, type: nothing, timeout: 100, after: [ stop ] }
+        "#);
+        assert_eq!(dispatcher.finish("a".to_string()).status, Status::Complete);
+    }
+
+    #[test]
+    fn follows_the_branch_chosen_by_the_response() {
+        let yaml = r#"
+            title: B
+            actions:
+              - { id: pick, type: selection, prompt: Which side?, options: [ Left, Right ], branch_on: { Left: left, Right: right } }
+              - { id: left, type: nothing, timeout: 100, after: [ pick ] }
+              - { id: right, type: nothing, timeout: 100, after: [ pick ] }
+              - { id: done, type: nothing, timeout: 100, after: [ left, right ] }
+        "#;
+        for (answer, chosen) in [("Left", "left"), ("Right", "right")] {
+            let (mut dispatcher, step) = begin(yaml);
+            assert_eq!(step, starts(&["pick"]));
+            let block = dispatcher.block.as_mut().unwrap();
+            block.action_mut(&"pick".to_string()).unwrap().set_response(&[answer.to_string()]).unwrap();
+            assert_eq!(dispatcher.finish("pick".to_string()), starts(&[chosen]));
+            assert_eq!(dispatcher.finish(chosen.to_string()), starts(&["done"]));
+            assert_eq!(dispatcher.finish("done".to_string()).status, Status::Complete);
+        }
+    }
+
+    #[test]
+    fn skips_every_branch_without_a_matching_response() {
+        let (mut dispatcher, _) = begin(r#"
+            title: B
+            actions:
+              - { id: pick, type: selection, prompt: Which side?, options: [ Left, Right, Neither ], branch_on: { Left: left, Right: right } }
+              - { id: left, type: nothing, timeout: 100, after: [ pick ] }
+              - { id: right, type: nothing, timeout: 100, after: [ pick ] }
+              - { id: done, type: nothing, timeout: 100, after: [ pick ] }
+        "#);
+        let block = dispatcher.block.as_mut().unwrap();
+        block.action_mut(&"pick".to_string()).unwrap().set_response(&["Neither".to_string()]).unwrap();
+        assert_eq!(dispatcher.finish("pick".to_string()), starts(&["done"]));
+    }
+
+    #[test]
+    fn runs_the_timeout_fallback_only_after_a_timeout() {
+        let yaml = r#"
+            title: B
+            actions:
+              - { id: respond, type: nothing, timeout: 100, on_timeout: reminder }
+              - { id: reminder, type: nothing, timeout: 100, after: [ respond ] }
+              - { id: next, type: nothing, timeout: 100, after: [ respond ] }
+        "#;
+        let (mut dispatcher, _) = begin(yaml);
+        dispatcher.block.as_mut().unwrap().time_out(&"respond".to_string());
+        let mut step = dispatcher.finish("respond".to_string());
+        step.start.sort();
+        assert_eq!(step, starts(&["next", "reminder"]));
+
+        let (mut dispatcher, _) = begin(yaml);
+        assert_eq!(dispatcher.finish("respond".to_string()), starts(&["next"]));
+    }
+}
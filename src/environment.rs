@@ -0,0 +1,185 @@
+use std::path::Path;
+use rodio::DeviceTrait;
+use rodio::cpal::traits::HostTrait;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::global::Global;
+use crate::logger::write_json;
+use crate::util::timestamp;
+
+/// The machine and setup that a session ran on, for tracing timing anomalies back to the
+/// display, audio device, or version of the task that produced them.
+#[derive(Debug, Serialize)]
+pub struct Environment {
+    time: String,
+    os: String,
+    os_release: Option<String>,
+    arch: String,
+    hostname: Option<String>,
+    version: String,
+    task_sha256: Option<String>,
+    window_size: (u32, u32),
+    fullscreen: bool,
+    /// Refresh rate that frame timing assumes, which is 60 Hz unless set in the task
+    refresh_rate: f32,
+    displays: Vec<Display>,
+    audio_device: Option<String>,
+}
+
+/// A connected display, as reported by the system (currently only through `xrandr`).
+#[derive(Debug, Serialize)]
+pub struct Display {
+    name: String,
+    primary: bool,
+    resolution: Option<String>,
+    refresh_rate: Option<f32>,
+}
+
+impl Environment {
+    pub fn capture(task_dir: &Path, global: &Global, config: &Config, fullscreen: bool) -> Self {
+        let task_sha256 = std::fs::read(task_dir.join("task.yml"))
+            .ok()
+            .map(|content| format!("{:x}", Sha256::digest(&content)));
+        // The configured device, or else the one the system plays to by default
+        let audio_device = config.audio_device()
+            .map(str::to_string)
+            .or_else(|| rodio::cpal::default_host().default_output_device()?.name().ok());
+
+        Environment {
+            time: timestamp(),
+            os: std::env::consts::OS.to_string(),
+            os_release: os_release(),
+            arch: std::env::consts::ARCH.to_string(),
+            hostname: hostname(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            task_sha256,
+            window_size: global.window_size(),
+            fullscreen,
+            refresh_rate: global.refresh_rate(),
+            displays: displays(),
+            audio_device,
+        }
+    }
+
+    /// Writes the snapshot to `session.json` in the session output directory.
+    pub fn write(&self, log_dir: &str) {
+        let path = Path::new(log_dir).join("session.json");
+        if let Err(e) = write_json(path.to_str().unwrap(), self) {
+            println!("Warning: Failed to write session environment to {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return None;
+    }
+    let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    Some(String::from_utf8_lossy(&buffer[..end]).to_string())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// Kernel release, e.g. "6.1.0-13-amd64".
+#[cfg(unix)]
+fn os_release() -> Option<String> {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return None;
+    }
+    let release = unsafe { std::ffi::CStr::from_ptr(name.release.as_ptr()) };
+    Some(release.to_string_lossy().to_string())
+}
+
+#[cfg(not(unix))]
+fn os_release() -> Option<String> {
+    None
+}
+
+/// Connected displays with their current mode, from `xrandr`; empty if it is not available
+/// (e.g. on Wayland without XWayland, or on other platforms).
+fn displays() -> Vec<Display> {
+    let output = match std::process::Command::new("xrandr").arg("--query").output() {
+        Ok(output) if output.status.success() => output.stdout,
+        _ => return vec![],
+    };
+    parse_xrandr(&String::from_utf8_lossy(&output))
+}
+
+/// Parses the output of `xrandr --query`, in which each connected output (e.g. "HDMI-1
+/// connected primary 1920x1080+0+0 ...") is followed by its modes, with the current refresh
+/// rate marked by `*` (e.g. "   1920x1080     60.00*+  50.00").
+fn parse_xrandr(output: &str) -> Vec<Display> {
+    let mut displays: Vec<Display> = vec![];
+    let mut in_connected = false;
+    for line in output.lines() {
+        if !line.starts_with(' ') {
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default().to_string();
+            in_connected = words.next() == Some("connected");
+            if in_connected {
+                let words: Vec<_> = words.collect();
+                displays.push(Display {
+                    name,
+                    primary: words.contains(&"primary"),
+                    resolution: words.iter()
+                        .find(|word| word.contains('x') && word.contains('+'))
+                        .and_then(|geometry| geometry.split('+').next())
+                        .map(str::to_string),
+                    refresh_rate: None,
+                });
+            }
+        } else if in_connected {
+            let mut words = line.split_whitespace();
+            let mode = words.next().unwrap_or_default();
+            if let Some(rate) = words.find(|word| word.contains('*')) {
+                let display = displays.last_mut().unwrap();
+                display.refresh_rate = rate.trim_end_matches(['*', '+']).parse().ok();
+                if display.resolution.is_none() {
+                    display.resolution = Some(mode.to_string());
+                }
+            }
+        }
+    }
+    displays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_connected_displays_from_xrandr() {
+        let output = "\
+Screen 0: minimum 320 x 200, current 3840 x 1080, maximum 16384 x 16384
+eDP-1 connected primary 1920x1080+0+0 (normal left inverted right x axis y axis) 344mm x 194mm
+   1920x1080     60.02*+  59.93
+   1680x1050     59.88
+HDMI-1 disconnected (normal left inverted right x axis y axis)
+   1280x720      60.00
+DP-1 connected 1920x1080+1920+0 (normal left inverted right x axis y axis) 527mm x 296mm
+   1920x1080    143.98*+ 119.98    60.00
+DP-2 connected (normal left inverted right x axis y axis)
+   2560x1440     59.95 +
+";
+        let displays = parse_xrandr(output);
+        assert_eq!(displays.len(), 3);
+        assert_eq!(displays[0].name, "eDP-1");
+        assert!(displays[0].primary);
+        assert_eq!(displays[0].resolution.as_deref(), Some("1920x1080"));
+        assert_eq!(displays[0].refresh_rate, Some(60.02));
+        assert_eq!(displays[1].name, "DP-1");
+        assert!(!displays[1].primary);
+        assert_eq!(displays[1].refresh_rate, Some(143.98));
+        assert_eq!(displays[2].name, "DP-2");
+        assert_eq!(displays[2].resolution, None);
+        assert_eq!(displays[2].refresh_rate, None);
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Errors in loading a task (and in setting up its session), by kind, so that callers can
+/// tell them apart. Each kind carries the message shown to the experimenter. Errors that come
+/// up while a block runs (and answers that fail validation) are plain messages instead, e.g.
+/// in `Message::Error`. Settings of the task configuration, style and audio are also still
+/// checked with plain messages, which the loader sorts into kinds.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The task file or a template is not valid YAML, or does not describe a valid task
+    Yaml(String),
+    /// A resource or template file is missing or could not be downloaded
+    MissingResource(String),
+    /// The actions of a block do not form a valid graph, e.g. they refer to unknown actions
+    InvalidGraph(String),
+    /// An audio file or device cannot be used
+    Audio(String),
+    /// A file or directory cannot be read or written, with the error of the system if known
+    Io(String, Option<Arc<std::io::Error>>),
+    /// Any other invalid setting
+    Invalid(String),
+}
+
+impl Error {
+    pub fn io(message: String, source: std::io::Error) -> Self {
+        Error::Io(message, Some(Arc::new(source)))
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Yaml(message) |
+            Error::MissingResource(message) |
+            Error::InvalidGraph(message) |
+            Error::Audio(message) |
+            Error::Io(message, _) |
+            Error::Invalid(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, Some(source)) => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string(), Some(Arc::new(error)))
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(error: serde_yaml::Error) -> Self {
+        Error::Yaml(error.to_string())
+    }
+}
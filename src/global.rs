@@ -1,10 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use iced::{Align, HorizontalAlignment};
+use iced::keyboard::{KeyCode, Modifiers};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use rodio::{OutputStream, OutputStreamHandle};
 use serde::{Serialize, Deserialize, de};
 use crate::config::Config;
+use crate::markup::RichText;
+use crate::sound;
+use crate::style::{self, Grid, Theme};
+use crate::util::{event_time, verify_output_pattern};
+
+pub const TEXT_SIZES: [&str; 6] = ["TINY", "SMALL", "NORMAL", "LARGE", "XLARGE", "XXLARGE"];
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -23,12 +36,238 @@ pub struct Global {
     text_alignment: String,
     #[serde(default)]
     debug_ui: bool,
+    #[serde(default)]
+    max_session: Option<u32>,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    pools: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    barrier: Option<Barrier>,
+    #[serde(default)]
+    auto_advance: bool,
+    #[serde(default)]
+    block_break: Option<BlockBreak>,
+    #[serde(default)]
+    disk_space: Option<DiskSpace>,
+    #[serde(default)]
+    refresh_rate: Option<f32>,
+    #[serde(default)]
+    output_pattern: Option<String>,
+    #[serde(default="default::audio_warmup")]
+    audio_warmup: u32,
+    #[serde(default="default::mouse_sample_rate")]
+    mouse_sample_rate: u32,
+    #[serde(default)]
+    key_mode: KeyMode,
+    #[serde(default)]
+    keyboard_layout: KeyboardLayout,
+    #[serde(default)]
+    remote_control: Option<String>,
+    #[serde(default, skip_serializing)]
+    remote_token: Option<String>,
+    #[serde(default="default::abort_key")]
+    abort_key: String,
+    #[serde(default)]
+    theme: Theme,
+    #[serde(default)]
+    rich_text: Option<RichText>,
+    #[serde(default)]
+    block_grid: Grid,
     #[serde(skip)]
     root_dir: String,
     #[serde(skip)]
     config: Option<Config>,
     #[serde(skip)]
     io: IO,
+    #[serde(skip)]
+    sampler: Sampler,
+}
+
+/// A key pressed along with modifiers, written as e.g. `Escape` or `Ctrl+Shift+Q`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyChord {
+    modifiers: Modifiers,
+    key: String,
+}
+
+// Every key code, to look keys up by name
+const KEY_CODES: [KeyCode; 163] = {
+    use KeyCode::*;
+    [
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0, A, B, C, D, E, F, G, H, I, J, K, L,
+        M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11,
+        F12, F13, F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24, Snapshot, Scroll, Pause,
+        Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down, Backspace, Enter, Space,
+        Compose, Caret, Numlock, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7,
+        Numpad8, Numpad9, NumpadAdd, NumpadDivide, NumpadDecimal, NumpadComma, NumpadEnter,
+        NumpadEquals, NumpadMultiply, NumpadSubtract, AbntC1, AbntC2, Apostrophe, Apps, Asterisk, At,
+        Ax, Backslash, Calculator, Capital, Colon, Comma, Convert, Equals, Grave, Kana, Kanji, LAlt,
+        LBracket, LControl, LShift, LWin, Mail, MediaSelect, MediaStop, Minus, Mute, MyComputer,
+        NavigateForward, NavigateBackward, NextTrack, NoConvert, OEM102, Period, PlayPause, Plus, Power,
+        PrevTrack, RAlt, RBracket, RControl, RShift, RWin, Semicolon, Slash, Sleep, Stop, Sysrq, Tab,
+        Underline, Unlabeled, VolumeDown, VolumeUp, Wake, WebBack, WebFavorites, WebForward, WebHome,
+        WebRefresh, WebSearch, WebStop, Yen, Copy, Paste, Cut,
+    ]
+};
+
+impl KeyChord {
+    pub fn parse(chord: &str) -> Result<Self, String> {
+        let mut parts: Vec<_> = chord.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        if key.is_empty() {
+            return Err(format!("Key chord is missing a key: {}", chord));
+        }
+        let mut modifiers = Modifiers::default();
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.control = true,
+                "alt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                "logo" | "super" | "cmd" => modifiers.logo = true,
+                _ => return Err(format!("Invalid modifier `{}` in key chord: {}", part, chord)),
+            }
+        }
+        Ok(KeyChord { modifiers, key: key.to_string() })
+    }
+
+    pub fn new(key_code: KeyCode, modifiers: Modifiers) -> Self {
+        KeyChord { modifiers, key: format!("{:?}", key_code) }
+    }
+
+    /// Whether a key press is this chord, with exactly its modifiers held.
+    pub fn matches(&self, key_code: KeyCode, modifiers: Modifiers) -> bool {
+        modifiers == self.modifiers && self.key.eq_ignore_ascii_case(&format!("{:?}", key_code))
+    }
+
+    /// The key of the chord, if it names one (ignoring case).
+    pub fn key_code(&self) -> Option<KeyCode> {
+        KEY_CODES
+            .into_iter()
+            .find(|&key_code| self.key.eq_ignore_ascii_case(&format!("{:?}", key_code)))
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Modifiers { shift, control, alt, logo } = self.modifiers;
+        for (held, name) in [(control, "Ctrl"), (alt, "Alt"), (shift, "Shift"), (logo, "Logo")] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Barrier {
+    key: String,
+    #[serde(default="default::barrier_message")]
+    message: String,
+}
+
+impl Barrier {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Break between blocks run with `auto_advance`, with a message and a countdown to the next block.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockBreak {
+    #[serde(default="default::break_message")]
+    message: String,
+    #[serde(deserialize_with="crate::action::deserialize::duration", serialize_with="crate::action::serialize::duration")]
+    duration: Duration,
+}
+
+impl BlockBreak {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+/// How key presses are interpreted: by the label of the key in the active keyboard layout
+/// (`logical`), or by its physical position on a QWERTY keyboard (`physical`).
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyMode {
+    #[default]
+    Logical,
+    Physical,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+}
+
+impl KeyboardLayout {
+    /// Translates a key label in this layout to the label of the key at the same
+    /// physical position on a QWERTY keyboard. Keys are labelled as they are pressed without
+    /// Shift, e.g. `'` for the AZERTY key that has 4 above it. The translation is a one-to-one
+    /// map, so that no two keys are taken for the same position; labels that do not appear
+    /// unshifted in the layout (e.g. digits on AZERTY) take the positions left over. Keys with no
+    /// key code (e.g. `!`, `é`, or `ù` on AZERTY) are not reported, and cannot be told apart.
+    pub fn to_qwerty(&self, key: KeyCode) -> KeyCode {
+        use KeyCode::*;
+        match (self, key) {
+            (KeyboardLayout::Azerty, A) => Q,
+            (KeyboardLayout::Azerty, Q) => A,
+            (KeyboardLayout::Azerty, Z) => W,
+            (KeyboardLayout::Azerty, W) => Z,
+            (KeyboardLayout::Azerty, M) => Semicolon,
+            (KeyboardLayout::Azerty, Semicolon) => Comma,
+            (KeyboardLayout::Azerty, Comma) => M,
+            (KeyboardLayout::Azerty, Colon) => Period,
+            (KeyboardLayout::Azerty, Period) => Colon,
+            (KeyboardLayout::Azerty, Apostrophe) => Key4,
+            (KeyboardLayout::Azerty, Key4) => Apostrophe,
+            (KeyboardLayout::Azerty, Minus) => Key6,
+            (KeyboardLayout::Azerty, Key6) => Minus,
+            (KeyboardLayout::Qwertz, Z) => Y,
+            (KeyboardLayout::Qwertz, Y) => Z,
+            (_, key) => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiskSpace {
+    warn_below: u64,
+    #[serde(default)]
+    refuse_start: bool,
+}
+
+impl DiskSpace {
+    /// Threshold of free space in the output directory, in megabytes.
+    pub fn warn_below(&self) -> u64 {
+        self.warn_below
+    }
+
+    pub fn refuse_start(&self) -> bool {
+        self.refuse_start
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize)]
@@ -135,6 +374,26 @@ mod default {
     pub fn text_alignment() -> String {
         "Center".to_string()
     }
+
+    pub fn audio_warmup() -> u32 {
+        100
+    }
+
+    pub fn mouse_sample_rate() -> u32 {
+        60
+    }
+
+    pub fn abort_key() -> String {
+        "Escape".to_string()
+    }
+
+    pub fn barrier_message() -> String {
+        "Waiting for the experimenter...".to_string()
+    }
+
+    pub fn break_message() -> String {
+        "Take a short break.".to_string()
+    }
 }
 
 impl Global {
@@ -150,6 +409,15 @@ impl Global {
         self.content_size
     }
 
+    /// Size (in pixels) of the area that content is laid out in, at the initial window size.
+    pub fn content_pixels(&self) -> (f32, f32) {
+        let pixels = |size: IntOrFloat, window: u32| match size {
+            IntOrFloat::Integer(i) => i as f32,
+            IntOrFloat::Float(f) => window as f32 * f,
+        };
+        (pixels(self.content_size.0, self.window_size.0), pixels(self.content_size.1, self.window_size.1))
+    }
+
     pub fn resizable(&self) -> bool {
         self.resizable
     }
@@ -159,21 +427,13 @@ impl Global {
     }
 
     pub fn alignment(&self) -> Align {
-        match self.text_alignment.to_uppercase().as_str() {
-            "START" | "LEFT" => Align::Start,
-            "CENTER" => Align::Center,
-            "END" | "RIGHT" => Align::End,
-            _ => panic!("Invalid text alignment value")
-        }
+        style::alignment(&self.text_alignment)
+            .expect("Invalid text alignment value")
     }
 
     pub fn horizontal_alignment(&self) -> HorizontalAlignment {
-        match self.text_alignment.to_uppercase().as_str() {
-            "START" | "LEFT" => HorizontalAlignment::Left,
-            "CENTER" => HorizontalAlignment::Center,
-            "END" | "RIGHT" => HorizontalAlignment::Right,
-            _ => panic!("Invalid text alignment value")
-        }
+        style::horizontal_alignment(&self.text_alignment)
+            .expect("Invalid text alignment value")
     }
 
     pub fn text_size(&self, scale: &str) -> u16 {
@@ -189,35 +449,95 @@ impl Global {
         (self.font_scale * size as f32).round() as u16
     }
 
-    pub fn verify(&self) {
+    /// Checks all settings, reporting every invalid one at once.
+    pub fn verify(&self) -> Result<(), String> {
+        let mut errors = vec![];
+
         match self.content_size.0 {
             IntOrFloat::Integer(i) if (i == 0 || i > self.window_size.0) => {
-                panic!("Content width should be positive and less than or equal to window width");
+                errors.push("Content width should be positive and less than or equal to window width".to_string());
             }
             IntOrFloat::Float(f) if (f <= 0.01 || f > 0.99) => {
-                panic!("Fractional content width should be between 0.01 and 0.99 inclusive");
+                errors.push("Fractional content width should be between 0.01 and 0.99 inclusive".to_string());
             }
             _ => (),
         }
         match self.content_size.1 {
             IntOrFloat::Integer(i) if (i == 0 || i > self.window_size.1) => {
-                panic!("Content height should be positive and less than or equal to window height");
+                errors.push("Content height should be positive and less than or equal to window height".to_string());
             }
             IntOrFloat::Float(f) if (f <= 0.01 || f > 0.99) => {
-                panic!("Fractional content height should be between 0.01 and 0.99 inclusive");
+                errors.push("Fractional content height should be between 0.01 and 0.99 inclusive".to_string());
             }
             _ => (),
         }
 
+        if let Some(0) = self.max_session {
+            errors.push("Maximum session duration should be a positive number of minutes".to_string());
+        }
+
+        if let Some(rate) = self.refresh_rate {
+            if rate <= 0.0 {
+                errors.push("Display refresh rate should be a positive number of Hz".to_string());
+            }
+        }
+
+        if self.mouse_sample_rate == 0 || self.mouse_sample_rate > 1000 {
+            errors.push("Mouse sample rate should be between 1 and 1000 Hz".to_string());
+        }
+
+        if let Err(e) = verify_output_pattern(self.output_pattern()) {
+            errors.push(e.to_string());
+        }
+
+        if let Some(address) = self.remote_control() {
+            if address.parse::<SocketAddr>().is_err() {
+                errors.push(format!("Remote control address should be of form <port> or <ip>:<port>: {}", address));
+            }
+            if self.remote_token.is_none() {
+                errors.push("Remote control needs a shared token (`remote_token`, or `--set remote_token=...`)".to_string());
+            }
+        }
+
+        if let Err(e) = KeyChord::parse(&self.abort_key) {
+            errors.push(format!("Invalid abort key: {}", e));
+        }
+
+        if let Some(barrier) = &self.barrier {
+            if !KEY_CODES.iter().any(|key_code| barrier.key.eq_ignore_ascii_case(&format!("{:?}", key_code))) {
+                errors.push(format!("Barrier key should be the name of a single key: {}", barrier.key));
+            }
+        }
+
+        if let Err(e) = self.block_grid.verify() {
+            errors.push(format!("Invalid block grid: {}", e));
+        }
+
+        if let Err(e) = self.theme.palette() {
+            errors.push(format!("Invalid theme: {}", e));
+        }
+
         if self.font_scale < 0.5 || self.font_scale > 3.0 {
-            panic!("Font scale should be between 0.5 and 3.0");
+            errors.push("Font scale should be between 0.5 and 3.0".to_string());
+        }
+
+        for (name, items) in &self.pools {
+            if items.is_empty() {
+                errors.push(format!("Stimulus pool `{}` should contain at least one item", name));
+            }
         }
 
         let possible_alignments = HashSet::from([
             "START", "LEFT", "CENTER", "END", "RIGHT"
         ]);
         if !possible_alignments.contains(self.text_alignment.to_uppercase().as_str()) {
-            panic!("Text alignment should be one of: {:?}", possible_alignments);
+            errors.push(format!("Text alignment should be one of: {:?}", possible_alignments));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid global settings: {}", errors.join("; ")))
         }
     }
 
@@ -233,6 +553,91 @@ impl Global {
         self.debug_ui
     }
 
+    /// Naming pattern of action output files, `action-{id}-{ts}` if not specified.
+    pub fn output_pattern(&self) -> &str {
+        self.output_pattern.as_deref().unwrap_or("action-{id}-{ts}")
+    }
+
+    pub fn has_refresh_rate(&self) -> bool {
+        self.refresh_rate.is_some()
+    }
+
+    /// Refresh rate of the display in Hz, assumed to be 60 Hz if not specified.
+    pub fn refresh_rate(&self) -> f32 {
+        self.refresh_rate.unwrap_or(60.0)
+    }
+
+    /// Interval between samples of the cursor position for actions that track the mouse.
+    pub fn mouse_sample_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.mouse_sample_rate as f64)
+    }
+
+    pub fn disk_space(&self) -> Option<&DiskSpace> {
+        self.disk_space.as_ref()
+    }
+
+    /// Address on which remote monitors are served the status of the task, and remote control
+    /// commands are accepted, if enabled. A port alone listens on the loopback interface only.
+    pub fn remote_control(&self) -> Option<String> {
+        self.remote_control.as_ref().map(|address| match address.parse::<u16>() {
+            Ok(port) => format!("127.0.0.1:{}", port),
+            Err(_) => address.clone(),
+        })
+    }
+
+    /// Token that remote clients have to present before they are served.
+    pub fn remote_token(&self) -> &str {
+        self.remote_token.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_remote_token(&mut self, token: &str) {
+        self.remote_token = Some(token.to_string()).filter(|token| !token.is_empty());
+    }
+
+    /// Key chord that interrupts the running block (after confirmation) or leaves a menu.
+    pub fn abort_key(&self) -> KeyChord {
+        // Checked along with the rest of the global settings
+        KeyChord::parse(&self.abort_key).unwrap()
+    }
+
+    /// Rich text settings, if prompts and descriptions should be rendered as rich text.
+    pub fn rich_text(&self) -> Option<&RichText> {
+        self.rich_text.as_ref()
+    }
+
+    /// Layout of the block selection buttons.
+    pub fn block_grid(&self) -> &Grid {
+        &self.block_grid
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn barrier(&self) -> Option<&Barrier> {
+        self.barrier.as_ref()
+    }
+
+    pub fn auto_advance(&self) -> bool {
+        self.auto_advance
+    }
+
+    pub fn block_break(&self) -> Option<&BlockBreak> {
+        self.block_break.as_ref()
+    }
+
+    /// Maps a pressed key to the key code that responses are matched against and logged as.
+    pub fn key(&self, key_code: KeyCode) -> KeyCode {
+        match self.key_mode {
+            KeyMode::Logical => key_code,
+            KeyMode::Physical => self.keyboard_layout.to_qwerty(key_code),
+        }
+    }
+
+    pub fn max_session(&self) -> Option<Duration> {
+        self.max_session.map(|m| Duration::from_secs(60 * m as u64))
+    }
+
     pub fn set_config(&mut self, config: &Config) {
         self.config = Some(config.clone());
     }
@@ -241,12 +646,107 @@ impl Global {
         self.config.as_ref().unwrap()
     }
 
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    pub fn init_sampler(&mut self) {
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        self.seed = Some(seed);
+        self.sampler = Sampler::new(seed);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed.expect("Random seed has not been initialized")
+    }
+
+    pub fn has_pool(&self, pool: &str) -> bool {
+        self.pools.contains_key(pool)
+    }
+
+    /// Draws an item from a session-wide stimulus pool without replacement. The pool is
+    /// reshuffled once all of its items have been used.
+    pub fn sample(&self, pool: &str) -> String {
+        let items = self.pools.get(pool)
+            .unwrap_or_else(|| panic!("Unknown stimulus pool: {}", pool));
+        self.sampler.draw(pool, items)
+    }
+
+    pub fn draws(&self) -> Vec<String> {
+        self.sampler.draws()
+    }
+
     pub fn io(&self) -> &IO {
         &self.io
     }
 
-    pub fn reset_io(&mut self) {
-        self.io.reset();
+    /// Reopens the audio output stream on the configured device, warming it up if configured.
+    /// Returns the measured time to first sample of the warm-up.
+    pub fn reset_io(&mut self) -> Option<Duration> {
+        let device = self.config.as_ref().and_then(Config::audio_device);
+        self.io.reset(self.audio_warmup, device)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Sampler {
+    state: Arc<Mutex<SamplerState>>,
+}
+
+#[derive(Debug)]
+struct SamplerState {
+    rng: StdRng,
+    remaining: HashMap<String, Vec<String>>,
+    draws: Vec<String>,
+    origin: Instant,
+}
+
+impl Default for SamplerState {
+    fn default() -> Self {
+        SamplerState {
+            rng: StdRng::seed_from_u64(0),
+            remaining: HashMap::new(),
+            draws: vec![],
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        let origin = Instant::now();
+        Sampler {
+            state: Arc::new(Mutex::new(SamplerState {
+                rng: StdRng::seed_from_u64(seed),
+                remaining: HashMap::new(),
+                draws: vec![format!("{}  SEED  {}", event_time(origin), seed)],
+                origin,
+            })),
+        }
+    }
+
+    pub fn draw(&self, pool: &str, items: &[String]) -> String {
+        let mut state = self.state.lock().unwrap();
+        let SamplerState { rng, remaining, draws, origin } = &mut *state;
+
+        let is_first = !remaining.contains_key(pool);
+        let remaining = remaining.entry(pool.to_string()).or_default();
+        if remaining.is_empty() {
+            if !is_first {
+                draws.push(format!("{}  EXHAUSTED  {}", event_time(*origin), pool));
+            }
+            *remaining = items.to_vec();
+            remaining.shuffle(rng);
+            draws.push(format!("{}  SHUFFLE  {}", event_time(*origin), pool));
+        }
+
+        let item = remaining.pop().unwrap();
+        draws.push(format!("{}  DRAW  {}  {}", event_time(*origin), pool, item));
+        item
+    }
+
+    pub fn draws(&self) -> Vec<String> {
+        self.state.lock().unwrap().draws.clone()
     }
 }
 
@@ -257,11 +757,29 @@ pub struct IO {
 }
 
 impl IO {
-    pub fn reset(&mut self) {
-        let (stream, stream_handle) =
-            OutputStream::try_default().expect("Failed to open output stream");
+    pub fn reset(&mut self, warm_up: u32, device: Option<&str>) -> Option<Duration> {
+        let (stream, stream_handle) = sound::open_output(device)
+            .or_else(|e| match device {
+                Some(_) => {
+                    println!("Warning: {}; falling back to the default audio output", e);
+                    sound::open_output(None)
+                }
+                None => Err(e),
+            })
+            .expect("Failed to open output stream");
         self.audio_stream = Some(stream);
         self.audio_stream_handle = Some(stream_handle);
+
+        if warm_up == 0 {
+            return None;
+        }
+        match sound::warm_up(self.audio_stream_handle.as_ref().unwrap(), Duration::from_millis(warm_up as u64)) {
+            Ok(latency) => Some(latency),
+            Err(e) => {
+                println!("Audio warm-up failed: {}", e);
+                None
+            }
+        }
     }
 
     pub fn audio_stream(&self) -> OutputStreamHandle {
@@ -286,3 +804,88 @@ impl Clone for IO {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn maps_each_layout_one_to_one() {
+        for layout in [KeyboardLayout::Qwerty, KeyboardLayout::Azerty, KeyboardLayout::Qwertz] {
+            let mapped: HashSet<_> = KEY_CODES.iter().map(|&key| format!("{:?}", layout.to_qwerty(key))).collect();
+            assert_eq!(mapped.len(), KEY_CODES.len(), "{:?} maps two keys to the same position", layout);
+        }
+    }
+
+    #[test]
+    fn maps_azerty_keys_to_their_qwerty_position() {
+        let azerty = KeyboardLayout::Azerty;
+        assert_eq!(azerty.to_qwerty(KeyCode::A), KeyCode::Q);
+        assert_eq!(azerty.to_qwerty(KeyCode::M), KeyCode::Semicolon);
+        assert_eq!(azerty.to_qwerty(KeyCode::Semicolon), KeyCode::Comma);
+        assert_eq!(azerty.to_qwerty(KeyCode::Colon), KeyCode::Period);
+        assert_eq!(azerty.to_qwerty(KeyCode::Apostrophe), KeyCode::Key4);
+        assert_eq!(azerty.to_qwerty(KeyCode::Key1), KeyCode::Key1);
+        assert_eq!(azerty.to_qwerty(KeyCode::Space), KeyCode::Space);
+    }
+
+    fn items(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn samples_each_item_once_before_any_repeats() {
+        let sampler = Sampler::new(7);
+        let pool = items(&["a", "b", "c"]);
+        let mut first: Vec<_> = (0..3).map(|_| sampler.draw("letters", &pool)).collect();
+        first.sort();
+        assert_eq!(first, pool);
+        let mut second: Vec<_> = (0..3).map(|_| sampler.draw("letters", &pool)).collect();
+        second.sort();
+        assert_eq!(second, pool);
+    }
+
+    #[test]
+    fn keeps_pools_apart_and_reproducible() {
+        let (one, two) = (Sampler::new(7), Sampler::new(7));
+        let (letters, digits) = (items(&["a", "b", "c", "d"]), items(&["1", "2"]));
+        for _ in 0..6 {
+            assert_eq!(one.draw("letters", &letters), two.draw("letters", &letters));
+            assert!(digits.contains(&one.draw("digits", &digits)));
+            two.draw("digits", &digits);
+        }
+    }
+
+    #[test]
+    fn logs_draws_in_order_with_reshuffles() {
+        let sampler = Sampler::new(3);
+        let pool = items(&["a", "b"]);
+        let drawn: Vec<_> = (0..3).map(|_| sampler.draw("letters", &pool)).collect();
+        let events: Vec<_> = sampler.draws()
+            .iter()
+            .map(|line| line.split("  ").skip(2).collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(events, vec![
+            "SEED 3".to_string(),
+            "SHUFFLE letters".to_string(),
+            format!("DRAW letters {}", drawn[0]),
+            format!("DRAW letters {}", drawn[1]),
+            "EXHAUSTED letters".to_string(),
+            "SHUFFLE letters".to_string(),
+            format!("DRAW letters {}", drawn[2]),
+        ]);
+    }
+
+    #[test]
+    fn accepts_only_single_keys_as_the_barrier_key() {
+        for key in ["Space", "f5", "Enter"] {
+            let global: Global = serde_yaml::from_str(&format!("barrier: {{ key: {} }}", key)).unwrap();
+            assert!(global.verify().is_ok(), "{}", key);
+        }
+        for key in ["Spacebar", "Ctrl+B", "\"\""] {
+            let global: Global = serde_yaml::from_str(&format!("barrier: {{ key: {} }}", key)).unwrap();
+            assert!(global.verify().unwrap_err().contains("Barrier key"), "{}", key);
+        }
+    }
+}
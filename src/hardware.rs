@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::Mutex;
+use std::sync::mpsc::TryRecvError;
+use std::time::Duration;
+
+use crate::comm::{Message, Receiver, wait_for_resume};
+
+// Serial ports are opened (and configured) on first use, and kept open for the session
+static PORTS: Mutex<BTreeMap<String, File>> = Mutex::new(BTreeMap::new());
+
+/// Sends a trigger code to external hardware (e.g. an EEG amplifier or eye tracker) over a
+/// serial port, opening the port at the given baud rate if it is not open yet.
+pub fn send_trigger(port: &str, baud: u32, code: u8) -> Result<(), String> {
+    let mut ports = PORTS.lock().unwrap();
+    if !ports.contains_key(port) {
+        ports.insert(port.to_string(), open_serial(port, baud, false)?);
+    }
+    let file = ports.get_mut(port).unwrap();
+    let result = file.write_all(&[code]).and_then(|()| file.flush());
+    if let Err(e) = result {
+        // Reopen the port on the next trigger, e.g. if the device was reconnected
+        ports.remove(port);
+        return Err(format!("Failed to write trigger to serial port {}: {}", port, e));
+    }
+    Ok(())
+}
+
+/// Waits for trigger bytes equal to `code` from a serial port (e.g. scanner pulses), calling
+/// `on_pulse` for each one until it returns true. Returns false if interrupted through `inbox`.
+pub fn wait_for_serial(
+    port: &str,
+    baud: u32,
+    code: u8,
+    inbox: &Receiver,
+    mut on_pulse: impl FnMut() -> bool
+) -> Result<bool, String> {
+    let mut file = open_serial(port, baud, true)?;
+    let mut buffer = [0u8; 64];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(n) => {
+                for _ in buffer[..n].iter().filter(|&&byte| byte == code) {
+                    if on_pulse() {
+                        return Ok(true);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(format!("Failed to read from serial port {}: {}", port, e)),
+        }
+        match inbox.try_recv() {
+            Ok(Message::Pause) => {
+                if !wait_for_resume(inbox)? {
+                    return Ok(false);
+                }
+            }
+            Err(TryRecvError::Empty) => std::thread::sleep(Duration::from_millis(1)),
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// Whether a baud rate can be used for serial triggers on this platform.
+pub fn supports_baud(baud: u32) -> bool {
+    baud_constant(baud).is_some()
+}
+
+/// Opens a serial port for writing, or for non-blocking reading if `input` is set.
+#[cfg(unix)]
+fn open_serial(port: &str, baud: u32, input: bool) -> Result<File, String> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let flags = if input { libc::O_NOCTTY | libc::O_NONBLOCK } else { libc::O_NOCTTY };
+    let file = OpenOptions::new()
+        .read(input)
+        .write(!input)
+        .custom_flags(flags)
+        .open(port)
+        .map_err(|e| format!("Failed to open serial port {}: {}", port, e))?;
+    let speed = baud_constant(baud)
+        .ok_or_else(|| format!("Unsupported baud rate for serial port {}: {}", port, baud))?;
+
+    // Raw 8N1 output at the requested speed
+    let fd = file.as_raw_fd();
+    let mut tty: libc::termios = unsafe { std::mem::zeroed() };
+    let configured = unsafe {
+        libc::tcgetattr(fd, &mut tty) == 0 && {
+            libc::cfmakeraw(&mut tty);
+            tty.c_cflag |= libc::CREAD | libc::CLOCAL;
+            libc::cfsetispeed(&mut tty, speed) == 0
+                && libc::cfsetospeed(&mut tty, speed) == 0
+                && libc::tcsetattr(fd, libc::TCSANOW, &tty) == 0
+        }
+    };
+    if !configured {
+        return Err(format!("Failed to configure serial port {}: {}", port, std::io::Error::last_os_error()));
+    }
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn open_serial(port: &str, _baud: u32, input: bool) -> Result<File, String> {
+    // The port is used with the line settings configured for the device, e.g. `\\.\COM3`,
+    // and reads block until a byte arrives
+    OpenOptions::new()
+        .read(input)
+        .write(!input)
+        .open(port)
+        .map_err(|e| format!("Failed to open serial port {}: {}", port, e))
+}
+
+#[cfg(unix)]
+fn baud_constant(baud: u32) -> Option<libc::speed_t> {
+    Some(match baud {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        _ => return None,
+    })
+}
+
+#[cfg(not(unix))]
+fn baud_constant(baud: u32) -> Option<u32> {
+    Some(baud)
+}
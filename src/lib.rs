@@ -3,10 +3,24 @@ pub mod app;
 pub mod block;
 pub mod comm;
 pub mod config;
+pub mod consent;
+pub mod console;
 pub mod dispatch;
+pub mod environment;
+pub mod error;
+pub mod hardware;
+pub mod logger;
+pub mod markup;
+pub mod pseudonym;
+pub mod remote;
+pub mod replay;
+pub mod scaffold;
+pub mod session;
+pub mod simulate;
 pub mod sound;
 pub mod style;
 pub mod task;
+pub mod upload;
 pub mod util;
 pub mod global;
 
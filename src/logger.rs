@@ -0,0 +1,215 @@
+//! Output of a session. Every file under the session directory is written through here, so
+//! that it is listed (with its checksum) in `manifest.yml` and ends up in the format and with
+//! the durability it needs:
+//!
+//! - records that must survive a crash (events, partial answers) are appended as JSON lines
+//!   right away (`append_line`), or through a file kept open by their writer (`open_log`, for
+//!   the input recording);
+//! - summaries that other files depend on (`task.log`, `events.log`, `session.json`) are
+//!   written right away (`write_to_file`, `write_json`);
+//! - bulk logs of blocks and actions are written on background threads (`async_write_to_file`,
+//!   `async_write_csv`), which `write_manifest` waits for before it lists the files;
+//! - the manifest itself is written on a background thread too, and `wait_for_output` waits
+//!   for all of these before the process exits or the output is uploaded.
+
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// Registry of all output files produced during the session, of pending asynchronous writes,
+// and of the manifest being written
+static OUTPUTS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+static PENDING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+static MANIFEST: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+pub fn register_output(filename: &str) {
+    OUTPUTS.lock().unwrap().insert(filename.to_string());
+}
+
+pub fn async_write_to_file<T>(filename: String, data: T, err: &'static str)
+where
+    T: Send + Serialize + 'static
+{
+    register_output(&filename);
+    let handle = std::thread::spawn(move || {
+        // A failed write (e.g. on a full disk) is reported without bringing down the session
+        let result = File::create(&filename)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_yaml::to_writer(file, &data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            println!("{} ({}): {}", err, filename, e);
+        }
+    });
+    PENDING.lock().unwrap().push(handle);
+}
+
+/// Writes data to a file as YAML, right away.
+pub fn write_to_file<T: Serialize>(filename: &str, data: &T) -> Result<(), String> {
+    register_output(filename);
+    File::create(filename)
+        .map_err(|e| e.to_string())
+        .and_then(|file| serde_yaml::to_writer(file, data).map_err(|e| e.to_string()))
+}
+
+/// Like `write_to_file`, but as pretty-printed JSON.
+pub fn write_json<T: Serialize>(filename: &str, data: &T) -> Result<(), String> {
+    register_output(filename);
+    File::create(filename)
+        .map_err(|e| e.to_string())
+        .and_then(|file| serde_json::to_writer_pretty(file, data).map_err(|e| e.to_string()))
+}
+
+/// Appends a record to a file as a line of JSON, right away, so that it is kept even if the
+/// process dies soon after.
+pub fn append_line<T: Serialize>(filename: &str, data: &T) -> Result<(), String> {
+    register_output(filename);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(filename)
+        .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(data).unwrap()))
+        .map_err(|e| e.to_string())
+}
+
+/// Opens a file to append to, for a writer that keeps it open (and flushes it itself).
+pub fn open_log(filename: &str) -> std::io::Result<File> {
+    register_output(filename);
+    OpenOptions::new().create(true).append(true).open(filename)
+}
+
+/// Like `async_write_to_file`, but writes the rows as a CSV table with a header row.
+pub fn async_write_csv<T>(filename: String, rows: Vec<T>, err: &'static str)
+where
+    T: Send + Serialize + 'static
+{
+    register_output(&filename);
+    let handle = std::thread::spawn(move || {
+        let result = csv::Writer::from_path(&filename)
+            .map_err(|e| e.to_string())
+            .and_then(|mut writer| {
+                for row in &rows {
+                    writer.serialize(row).map_err(|e| e.to_string())?;
+                }
+                writer.flush().map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            println!("{} ({}): {}", err, filename, e);
+        }
+    });
+    PENDING.lock().unwrap().push(handle);
+}
+
+/// Events of a session or block. Each event is appended to `events.jsonl` as it happens (as a
+/// JSON array of its fields, one event per line), so that a crash loses none of them, and the
+/// whole list is summarized in `events.log` when the block or session ends.
+#[derive(Debug, Default, Clone)]
+pub struct EventLog {
+    events: Vec<String>,
+    dir: Option<PathBuf>,
+}
+
+impl EventLog {
+    /// Sets the directory the event stream is written to, e.g. after it is renamed.
+    pub fn set_dir(&mut self, dir: &str) {
+        self.dir = Some(PathBuf::from(dir));
+    }
+
+    pub fn push(&mut self, event: String) {
+        if let Some(dir) = &self.dir {
+            // Opened for each event, since the directory can be renamed during the session
+            let path = dir.join("events.jsonl");
+            let fields: Vec<&str> = event.split("  ").collect();
+            if let Err(e) = append_line(path.to_str().unwrap(), &fields) {
+                println!("Warning: Failed to append event to {:?}: {}", path, e);
+            }
+        }
+        self.events.push(event);
+    }
+
+    pub fn to_vec(&self) -> Vec<String> {
+        self.events.clone()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Writes the summary of all events so far to `events.log`.
+    pub fn write_summary(&self) {
+        if let Some(dir) = &self.dir {
+            let path = dir.join("events.log");
+            if let Err(e) = write_to_file(path.to_str().unwrap(), &self.events) {
+                println!("Warning: Failed to write event log to {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Lists all registered output files with their size and checksum in `manifest.yml` under
+/// the session directory. The files are hashed on a background thread, once pending writes
+/// (and any earlier manifest) are done; `wait_for_output` waits for it.
+pub fn write_manifest(log_dir: &str) {
+    let log_dir = log_dir.to_string();
+    let mut manifest = MANIFEST.lock().unwrap();
+    let previous = manifest.take();
+    *manifest = Some(std::thread::spawn(move || {
+        if let Some(previous) = previous {
+            previous.join().ok();
+        }
+        wait_for_pending();
+
+        let outputs: Vec<_> = OUTPUTS.lock().unwrap().iter().cloned().collect();
+        let entries: Vec<_> = outputs
+            .iter()
+            .filter_map(|filename| {
+                let mut file = File::open(filename).ok()?;
+                let mut content = vec![];
+                file.read_to_end(&mut content).ok()?;
+                let path = Path::new(filename).strip_prefix(&log_dir)
+                    .unwrap_or(Path::new(filename));
+                Some(ManifestEntry {
+                    path: path.to_str().unwrap().to_string(),
+                    size: content.len() as u64,
+                    sha256: format!("{:x}", Sha256::digest(&content)),
+                })
+            })
+            .collect();
+
+        let filename = Path::new(&log_dir).join("manifest.yml");
+        let result = File::create(&filename)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_yaml::to_writer(file, &entries).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            println!("Failed to write output manifest to file ({:?}): {}", filename, e);
+        }
+    }));
+}
+
+fn wait_for_pending() {
+    let pending: Vec<_> = PENDING.lock().unwrap().drain(..).collect();
+    for handle in pending {
+        handle.join().ok();
+    }
+}
+
+/// Waits for pending writes and the manifest, e.g. before the process exits or the output
+/// is uploaded.
+pub fn wait_for_output() {
+    wait_for_pending();
+    let manifest = MANIFEST.lock().unwrap().take();
+    if let Some(manifest) = manifest {
+        manifest.join().ok();
+    }
+}
@@ -1,20 +1,164 @@
 use std::env;
+use std::fs::File;
 use std::path::PathBuf;
 use iced::{Application, Settings, window};
 
 use task_runner::app::App;
-use task_runner::task::Task;
-
-fn main() -> Result<(), String> {
-    let args = env::args();
-    let task_dir = match args.len() {
-        1 => env::current_exe().unwrap().parent().unwrap().to_path_buf(),
-        2 => PathBuf::from(args.skip(1).next().unwrap()),
-        _ => panic!("Usage example: ./task-runner [task_dir]"),
+use task_runner::error::Error;
+use task_runner::logger;
+use task_runner::replay::Replay;
+use task_runner::scaffold;
+use task_runner::simulate::Timeline;
+use task_runner::task::{Options, Task};
+
+const USAGE: &str = "\
+Usage: task-runner [run|check] [OPTIONS] [TASK_DIR]
+       task-runner new <DIR>
+
+Runs the task in TASK_DIR (by default, the directory of the executable).
+
+Commands:
+  run                   Run the task (default)
+  check                 Load and verify the task, without running it
+  new                   Create a minimal working task in DIR to start from
+
+Options:
+  --output-dir <DIR>    Create the session output directory in DIR, instead of TASK_DIR/output
+  --subject <ID>        Fill in the subject ID of the session
+  --block <BLOCK>       Skip the start screen, run BLOCK (a number or title) once the
+                        session begins, and exit when it ends
+  --seed <SEED>         Use this random seed, instead of the one in task.yml
+  --counterbalance <N>  Present the blocks in row N of the Latin square of block orders
+                        (with `block_order: latin_square`), instead of the row given by
+                        the number at the end of the subject ID
+  --fullscreen          Run in fullscreen mode
+  --set <KEY=VALUE>     Override a setting of task.yml (can be repeated): `fullscreen`,
+                        `output_dir`, `audio` (mono_and_trigger or stereo), `audio_device`,
+                        or a configuration option by name
+  --replay <FILE>       Feed back the inputs recorded in the input.log of a session, at
+                        the times they were recorded, with its random seed
+  --export-graph <DIR>  Write the action dependency graph of each block to DIR/block-<N>.dot
+                        (Graphviz format), without running the task
+  --simulate            Run the task (or only the block given with --block) headlessly,
+                        ending each action after its nominal duration and answering
+                        questions at random, and write the usual output
+  --timeline <FILE>     Scripted responses for --simulate, by action ID
+  -h, --help            Print this help
+  -V, --version         Print the version
+
+Settings can also be given as environment variables named TASK_RUNNER_<KEY>, e.g.
+TASK_RUNNER_AUDIO_DEVICE; those given with --set take precedence.
+
+Exit status (with --block): 0 if the block completed, 1 after an error, and 2 if the
+block was interrupted or the session timed out.";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut args = pico_args::Arguments::from_env();
+    if args.contains(["-h", "--help"]) {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    if args.contains(["-V", "--version"]) {
+        println!("task-runner {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    let replay: Option<PathBuf> = args.opt_value_from_str("--replay").map_err(usage)?;
+    let mut options = Options {
+        output_dir: args.opt_value_from_str("--output-dir").map_err(usage)?,
+        subject: args.opt_value_from_str("--subject").map_err(usage)?,
+        block: args.opt_value_from_str("--block").map_err(usage)?,
+        seed: args.opt_value_from_str("--seed").map_err(usage)?,
+        counterbalance: args.opt_value_from_str("--counterbalance").map_err(usage)?,
+        fullscreen: args.contains("--fullscreen"),
+        overrides: {
+            let mut overrides = vec![];
+            for setting in args.values_from_str::<_, String>("--set").map_err(usage)? {
+                let (key, value) = setting.split_once('=')
+                    .ok_or_else(|| usage(format!("expected KEY=VALUE for --set: {}", setting)))?;
+                overrides.push((key.trim().to_string(), value.to_string()));
+            }
+            overrides
+        },
+        env_overrides: env_overrides(),
+        replay: replay.map(|file| Replay::load(&file)).transpose().map_err(describe)?,
+        dry_run: false,
     };
-    let task = Task::new(task_dir)?;
+    let export_graph: Option<PathBuf> = args.opt_value_from_str("--export-graph").map_err(usage)?;
+    let simulate = args.contains("--simulate");
+    let timeline: Option<PathBuf> = args.opt_value_from_str("--timeline").map_err(usage)?;
+    let mut free: Vec<String> = args.finish()
+        .into_iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect();
+    if let Some(arg) = free.iter().find(|arg| arg.starts_with('-')) {
+        return Err(usage(format!("unknown option: {}", arg)));
+    }
+    if free.first().map(String::as_str) == Some("new") {
+        let dir = match &free[1..] {
+            [dir] => PathBuf::from(dir),
+            [] => return Err(usage("missing directory for the new task")),
+            _ => return Err(usage("too many arguments")),
+        };
+        scaffold::create(&dir)?;
+        println!("Created a new task in {:?}; run it with `task-runner {}`", dir, dir.display());
+        return Ok(());
+    }
+    let check = match free.first().map(String::as_str) {
+        Some("check") => { free.remove(0); true }
+        Some("run") => { free.remove(0); false }
+        _ => false,
+    };
+    let task_dir = match free.as_slice() {
+        [] => env::current_exe().unwrap().parent().unwrap().to_path_buf(),
+        [dir] => PathBuf::from(dir),
+        _ => return Err(usage("too many arguments")),
+    };
+    options.dry_run = check || export_graph.is_some();
+
+    let mut task = Task::with_options(task_dir, options).map_err(describe)?;
+    task.global().verify()?;
+    if let Some(dir) = export_graph {
+        for file in task.export_graphs(&dir).map_err(describe)? {
+            println!("Wrote {}", file.display());
+        }
+        return Ok(());
+    }
+    if simulate {
+        let timeline: Timeline = match timeline {
+            Some(file) => {
+                let file = File::open(&file)
+                    .map_err(|_| format!("Failed to open timeline file: {:?}", file))?;
+                serde_yaml::from_reader(file)
+                    .map_err(|e| format!("Failed to read timeline file: {}", e))?
+            }
+            None => Timeline::new(),
+        };
+        let blocks = match task.start_block() {
+            Some(block) => vec![block],
+            None => task.block_sequence(),
+        };
+        for block in blocks {
+            println!("Block {}", block);
+            for line in task.simulate(block, &timeline, true).map_err(describe)? {
+                println!("{}", line);
+            }
+        }
+        logger::wait_for_output();
+        println!("Output written to: {}", task.log_dir());
+        return Ok(());
+    }
     let global = task.global();
-    global.verify();
+    if check {
+        println!("{}: OK", task.title());
+        return Ok(());
+    }
 
     App::run(Settings {
         default_font: None,
@@ -27,6 +171,7 @@ fn main() -> Result<(), String> {
             resizable: global.resizable(),
             always_on_top: false,
             icon: None,
+            // No way to pick the display (or position) of the window in iced 0.3
             ..Default::default()
         },
         flags: task,
@@ -44,3 +189,29 @@ fn main() -> Result<(), String> {
         ),
     })
 }
+
+/// Message for an error in loading the task, with a hint on where to look for its cause.
+fn describe(error: Error) -> String {
+    let hint = match &error {
+        Error::Yaml(_) => "Check the syntax of task.yml, and of the templates it uses.",
+        Error::MissingResource(_) => "Resource files are looked up in the `resources` directory of the task, and templates in `templates`.",
+        Error::InvalidGraph(_) => "Check the `id`, `after`, `with`, and `branch_on` fields of the block's actions.",
+        Error::Audio(_) => "Check that the audio file is not corrupt, and is in a supported format (e.g. WAV).",
+        Error::Io(..) => "Check that the task directory is readable, and that its `output` directory is writable.",
+        Error::Invalid(_) => return error.to_string(),
+    };
+    format!("{}\n{}", error, hint)
+}
+
+/// Settings given as `TASK_RUNNER_<KEY>` environment variables, with lowercase keys.
+fn env_overrides() -> Vec<(String, String)> {
+    let mut overrides: Vec<_> = env::vars()
+        .filter_map(|(name, value)| name.strip_prefix("TASK_RUNNER_").map(|key| (key.to_lowercase(), value)))
+        .collect();
+    overrides.sort();
+    overrides
+}
+
+fn usage(error: impl std::fmt::Display) -> String {
+    format!("{}\n\n{}", error, USAGE)
+}
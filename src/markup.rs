@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::sync::RwLock;
+use iced::{Align, Color, Column, Font, HorizontalAlignment, Length, Row, Text};
+use serde::{Serialize, Deserialize};
+
+use crate::comm::Message;
+use crate::global::Global;
+use crate::style::parse_color;
+use crate::util::resource;
+
+// Fonts of bold and italic spans, loaded from the task resources
+static FONTS: RwLock<(Option<Font>, Option<Font>)> = RwLock::new((None, None));
+
+/// Enables a limited rich text syntax in instruction prompts and the task description:
+///
+/// * `# Heading`, `## Heading`, and `### Heading` lines
+/// * `**bold**` and `*italic*` spans, drawn with the given fonts (the default font has no
+///   bold or italic variant, so without them these spans are only unmarked)
+/// * `{red}colored{/}` spans, with a color name or `#RRGGBB`
+/// * `* item` and `- item` bullet lines
+///
+/// Line breaks are kept, and an empty line separates paragraphs.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RichText {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    bold_font: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    italic_font: Option<String>,
+}
+
+impl RichText {
+    /// Loads the bold and italic fonts, which are kept for the rest of the session.
+    pub fn load_fonts(&self, task_dir: &Path) -> Result<(), String> {
+        let load = |file: &Option<String>, name: &'static str| -> Result<Option<Font>, String> {
+            match file {
+                Some(file) => {
+                    let path = resource(task_dir, file)?;
+                    let bytes = std::fs::read(&path)
+                        .map_err(|e| format!("Failed to read font file {:?}: {}", path, e))?;
+                    Ok(Some(Font::External { name, bytes: Box::leak(bytes.into_boxed_slice()) }))
+                }
+                None => Ok(None),
+            }
+        };
+        *FONTS.write().unwrap() = (load(&self.bold_font, "bold")?, load(&self.italic_font, "italic")?);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    color: Option<Color>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    text: String,
+    format: Format,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    heading: u8,
+    spans: Vec<Span>,
+}
+
+fn parse(text: &str) -> Vec<Line> {
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+            if (1..=3).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+                Line { heading: hashes as u8, spans: parse_spans(trimmed[hashes..].trim()) }
+            } else if let Some(item) = trimmed.strip_prefix("* ").or_else(|| trimmed.strip_prefix("- ")) {
+                Line { heading: 0, spans: parse_spans(&format!("\u{2022} {}", item.trim())) }
+            } else {
+                Line { heading: 0, spans: parse_spans(line) }
+            }
+        })
+        .collect()
+}
+
+/// Splits a line into spans of uniform format. Markers that do not form valid markup (e.g. a
+/// `*` followed by a space, or braces around something other than a color) are kept as text.
+fn parse_spans(line: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut format = Format::default();
+    let mut colors = vec![];
+    let mut text = String::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let mut toggled = format;
+        let marker = if let Some(after) = rest.strip_prefix("**") {
+            toggled.bold = !format.bold;
+            (format.bold || after.starts_with(|c: char| !c.is_whitespace())).then_some(2)
+        } else if let Some(after) = rest.strip_prefix('*') {
+            toggled.italic = !format.italic;
+            (format.italic || after.starts_with(|c: char| !c.is_whitespace())).then_some(1)
+        } else if rest.starts_with("{/}") && !colors.is_empty() {
+            colors.pop();
+            toggled.color = colors.last().copied();
+            Some(3)
+        } else if let Some((color, len)) = color_marker(rest) {
+            colors.push(color);
+            toggled.color = Some(color);
+            Some(len)
+        } else {
+            None
+        };
+        match marker {
+            Some(len) => {
+                if !text.is_empty() {
+                    spans.push(Span { text: std::mem::take(&mut text), format });
+                }
+                format = toggled;
+                rest = &rest[len..];
+            }
+            None => {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !text.is_empty() || spans.is_empty() {
+        spans.push(Span { text, format });
+    }
+    spans
+}
+
+fn color_marker(text: &str) -> Option<(Color, usize)> {
+    let name = text.strip_prefix('{')?.split('}').next()?;
+    if text.starts_with("{{") || name.len() > 16 {
+        return None;
+    }
+    parse_color(name).ok().map(|color| (color, name.len() + 2))
+}
+
+fn text(span: &Span, text: String, size: u16, color: Color) -> Text {
+    let (bold, italic) = *FONTS.read().unwrap();
+    let font = match (span.format.bold, span.format.italic) {
+        (true, _) => bold,
+        (_, true) => italic,
+        _ => None,
+    };
+    let text = Text::new(text)
+        .size(size)
+        .color(span.format.color.unwrap_or(color));
+    match font {
+        Some(font) => text.font(font),
+        None => text,
+    }
+}
+
+/// Lays out rich text as a column of lines. Lines with a single format wrap by themselves;
+/// lines that mix formats are wrapped word by word, using an estimate of the text width.
+pub fn view<'a>(
+    markup: &str,
+    size: u16,
+    color: Color,
+    alignment: HorizontalAlignment,
+    global: &Global,
+) -> Column<'a, Message> {
+    let align = match alignment {
+        HorizontalAlignment::Left => Align::Start,
+        HorizontalAlignment::Center => Align::Center,
+        HorizontalAlignment::Right => Align::End,
+    };
+    let (width, _) = global.content_pixels();
+    let mut column = Column::new()
+        .width(Length::Fill)
+        .align_items(align);
+    for line in parse(markup) {
+        let size = match line.heading {
+            1 => size * 3 / 2,
+            2 => size * 5 / 4,
+            3 => size * 9 / 8,
+            _ => size,
+        };
+        if let [span] = line.spans.as_slice() {
+            column = column.push(text(span, span.text.clone(), size, color)
+                .width(Length::Fill)
+                .horizontal_alignment(alignment));
+            continue;
+        }
+
+        // Roughly half an em per character in the default font
+        let char_width = size as f32 * 0.55;
+        let mut row = Row::new();
+        let mut used = 0.0;
+        for span in &line.spans {
+            for word in span.text.split_inclusive(' ') {
+                let word_width = word.chars().count() as f32 * char_width;
+                if used > 0.0 && used + word_width > width {
+                    column = column.push(row);
+                    row = Row::new();
+                    used = 0.0;
+                }
+                row = row.push(text(span, word.to_string(), size, color));
+                used += word_width;
+            }
+        }
+        column = column.push(row);
+    }
+    column
+}
@@ -0,0 +1,107 @@
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+/// Replaces subject IDs with pseudonyms before they are written anywhere (output directory
+/// name, session logs, input recording). The pseudonym is a hash of the ID with a site salt,
+/// so the same subject gets the same pseudonym across sessions at a site. Optionally, each
+/// pseudonym is paired with its subject ID in a local linkage file, in which the IDs are
+/// encrypted with a passphrase (using the system `openssl`).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pseudonymize {
+    #[serde(default, skip_serializing)]
+    salt: String,
+    /// Linkage file, relative to the task directory (which keeps it out of the output)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    linkage: Option<String>,
+    #[serde(skip)]
+    passphrase: Option<String>,
+    #[serde(skip)]
+    linkage_path: Option<PathBuf>,
+}
+
+impl Pseudonymize {
+    pub fn init(&mut self, task_dir: &Path) -> Result<(), Error> {
+        if self.salt.is_empty() {
+            return Err(Error::Invalid("Pseudonymization needs a site salt (`salt`, or `--set pseudonym_salt=...`)".to_string()));
+        }
+        if let Some(linkage) = &self.linkage {
+            if self.passphrase.is_none() {
+                return Err(Error::Invalid("The linkage file needs a passphrase (`--set linkage_passphrase=...` or TASK_RUNNER_LINKAGE_PASSPHRASE)".to_string()));
+            }
+            self.linkage_path = Some(task_dir.join(linkage));
+        }
+        Ok(())
+    }
+
+    pub fn set_salt(&mut self, salt: &str) {
+        self.salt = salt.to_string();
+    }
+
+    pub fn set_passphrase(&mut self, passphrase: &str) {
+        self.passphrase = Some(passphrase.to_string()).filter(|passphrase| !passphrase.is_empty());
+    }
+
+    /// Pseudonym of a subject ID: the first 16 hex digits of its salted SHA-256 hash.
+    pub fn pseudonym(&self, subject: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update([0]);
+        hasher.update(subject.as_bytes());
+        format!("{:x}", hasher.finalize())[..16].to_string()
+    }
+
+    /// Adds the pair of a pseudonym and its (encrypted) subject ID to the linkage file, unless
+    /// it is listed there already. Each line holds the pseudonym and the ID encrypted with
+    /// `openssl enc -aes-256-cbc -pbkdf2 -a -A`, which the same command with `-d` decrypts.
+    pub fn link(&self, subject: &str, pseudonym: &str) -> Result<(), Error> {
+        let path = match &self.linkage_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Ok(file) = std::fs::File::open(path) {
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| Error::io(format!("Failed to read linkage file {:?}: {}", path, e), e))?;
+                if line.split_whitespace().next() == Some(pseudonym) {
+                    return Ok(());
+                }
+            }
+        }
+
+        let encrypted = self.encrypt(subject)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| Error::io(format!("Failed to open linkage file {:?}: {}", path, e), e))?;
+        writeln!(file, "{}  {}", pseudonym, encrypted)
+            .map_err(|e| Error::io(format!("Failed to write to linkage file {:?}: {}", path, e), e))
+    }
+
+    fn encrypt(&self, subject: &str) -> Result<String, Error> {
+        // The passphrase is handed over in the environment of openssl, not on its command line
+        let mut child = std::process::Command::new("openssl")
+            .args(["enc", "-aes-256-cbc", "-pbkdf2", "-salt", "-a", "-A", "-pass", "env:LINKAGE_PASSPHRASE"])
+            .env("LINKAGE_PASSPHRASE", self.passphrase.as_deref().unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::io(format!("Failed to run openssl to encrypt the subject ID: {}", e), e))?;
+        child.stdin.take().unwrap().write_all(subject.as_bytes())
+            .map_err(|e| Error::io(format!("Failed to encrypt the subject ID: {}", e), e))?;
+        let output = child.wait_with_output()
+            .map_err(|e| Error::io(format!("Failed to encrypt the subject ID: {}", e), e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::Io(format!("Failed to encrypt the subject ID: {}", stderr.trim()), None));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
@@ -0,0 +1,360 @@
+use std::any::TypeId;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use iced_native::subscription::Recipe;
+use iced_futures::futures;
+use sha1::{Digest, Sha1};
+
+use crate::comm::{Message, Value};
+use crate::console;
+
+// Clients beyond this many are turned away
+const MAX_CLIENTS: usize = 8;
+
+/// Link to operator machines outside the booth. The status of the task, as shown by the
+/// operator console, is served to read-only monitors over WebSocket (e.g. a browser in the
+/// control room): each time it changes, it is sent to every monitor as a JSON text message.
+/// Monitors present the shared token of the task in the URL, as in `ws://<ip>:<port>/?token=...`.
+/// Connections that do not open with a WebSocket handshake are control connections instead,
+/// which take one command per line:
+///
+/// * `auth <token>` has to come first, with the shared token of the task (`remote_token`)
+/// * `start <block>` starts a block, as if it was selected on screen
+/// * `interrupt` aborts the running block at once, without the confirmation asked after the abort key
+/// * `status` asks for the state of the task, as shown by the operator console
+///
+/// Each command is answered with `OK` once it is passed on to the task (followed by the
+/// status as JSON for `status`), or `ERR <reason>`. A connection that sends any other
+/// command before it is authenticated is answered with an error and closed.
+/// Commands that do not apply to the current state of the task (e.g. starting a block while
+/// another one is running) are ignored with a warning. Connections are polled along with the
+/// rest of the subscriptions, so at most a few clients (`MAX_CLIENTS`) are served at once.
+pub struct RemoteLink {
+    address: String,
+    token: String,
+}
+
+impl RemoteLink {
+    pub fn new(address: &str, token: &str) -> Self {
+        RemoteLink { address: address.to_string(), token: token.to_string() }
+    }
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    // Waiting for the first line, which tells monitors and control connections apart
+    New,
+    Control,
+    Monitor,
+}
+
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    kind: Kind,
+    authenticated: bool,
+    // Status version last sent to a monitor
+    sent: usize,
+}
+
+struct Remote {
+    listener: TcpListener,
+    token: String,
+    clients: Vec<Client>,
+    pending: VecDeque<Message>,
+}
+
+impl Remote {
+    fn bind(address: &str, token: &str) -> Result<Self, String> {
+        let listener = TcpListener::bind(address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+            .map_err(|e| format!("Failed to listen for remote control on {}: {}", address, e))?;
+        println!("Listening for remote control on {}", address);
+        Ok(Remote { listener, token: token.to_string(), clients: vec![], pending: VecDeque::new() })
+    }
+
+    fn poll(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((_, peer)) if self.clients.len() >= MAX_CLIENTS => {
+                    println!("Warning: Turned away remote connection from {} (too many clients)", peer);
+                }
+                Ok((stream, peer)) if stream.set_nonblocking(true).is_ok() => {
+                    println!("Remote client connected from {}", peer);
+                    self.clients.push(Client { stream, buffer: vec![], kind: Kind::New, authenticated: false, sent: 0 });
+                }
+                Ok(_) => (),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    println!("Warning: Failed to accept remote connection: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let version = console::version();
+        let status = console::status().map(|status| serde_json::to_string(&status).unwrap());
+        let token = &self.token;
+        let pending = &mut self.pending;
+        self.clients.retain_mut(|client| {
+            let mut chunk = [0u8; 256];
+            loop {
+                match client.stream.read(&mut chunk) {
+                    Ok(0) => return false,
+                    Ok(n) => client.buffer.extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                    Err(_) => return false,
+                }
+            }
+
+            if client.kind == Kind::New {
+                if client.buffer.starts_with(b"GET ") {
+                    let end = match find(&client.buffer, b"\r\n\r\n") {
+                        Some(end) => end,
+                        None => return client.buffer.len() < 8192,
+                    };
+                    let request = String::from_utf8_lossy(&client.buffer[..end]).to_string();
+                    client.buffer.clear();
+                    if request_token(&request) != Some(token.as_str()) {
+                        let _ = write!(client.stream, "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n");
+                        return false;
+                    }
+                    return match handshake(&request) {
+                        Ok(response) => {
+                            client.kind = Kind::Monitor;
+                            client.stream.write_all(response.as_bytes()).is_ok()
+                        }
+                        Err(e) => {
+                            let _ = write!(client.stream, "HTTP/1.1 400 Bad Request\r\nContent-Length: {}\r\n\r\n{}", e.len(), e);
+                            false
+                        }
+                    };
+                } else if client.buffer.contains(&b'\n') {
+                    client.kind = Kind::Control;
+                } else {
+                    return client.buffer.len() < 8192;
+                }
+            }
+
+            if client.kind == Kind::Monitor {
+                // Monitors are read-only, so anything they send is dropped (a close frame is
+                // followed by the end of the connection)
+                client.buffer.clear();
+                return match &status {
+                    Some(status) if client.sent != version => {
+                        client.sent = version;
+                        client.stream.write_all(&text_frame(status)).is_ok()
+                    }
+                    _ => true,
+                };
+            }
+
+            while let Some(end) = client.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = client.buffer.drain(..=end).collect();
+                let command = String::from_utf8_lossy(&line).trim().to_string();
+                if !client.authenticated {
+                    let reply = match command.split_once(' ') {
+                        Some((cmd, given)) if cmd.eq_ignore_ascii_case("auth") && given.trim() == token => {
+                            client.authenticated = true;
+                            "OK"
+                        }
+                        _ => "ERR not authenticated",
+                    };
+                    if writeln!(client.stream, "{}", reply).is_err() || !client.authenticated {
+                        return false;
+                    }
+                    continue;
+                }
+                let reply = match parse(&command) {
+                    _ if command.eq_ignore_ascii_case("status") => match console::status() {
+                        Some(status) => format!("OK {}", serde_json::to_string(&status).unwrap()),
+                        None => "ERR status not available yet".to_string(),
+                    },
+                    Ok(messages) => {
+                        pending.extend(messages.into_iter().map(|msg| Message::Remote(Box::new(msg))));
+                        "OK".to_string()
+                    }
+                    Err(e) => format!("ERR {}", e),
+                };
+                if writeln!(client.stream, "{}", reply).is_err() {
+                    return false;
+                }
+            }
+            true
+        });
+    }
+}
+
+fn parse(command: &str) -> Result<Vec<Message>, String> {
+    let words: Vec<_> = command.split_whitespace().collect();
+    match words.as_slice() {
+        [] => Ok(vec![]),
+        [cmd, block] if cmd.eq_ignore_ascii_case("start") => {
+            match block.parse::<u16>() {
+                Ok(block) if block > 0 => Ok(vec![Message::UIEvent(block, Value::Null)]),
+                _ => Err(format!("invalid block number: {}", block)),
+            }
+        }
+        [cmd] if cmd.eq_ignore_ascii_case("interrupt") => Ok(vec![Message::Interrupt]),
+        _ => Err(format!("unknown command: {}", command)),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Token given in the query of an HTTP request, as in `GET /?token=... HTTP/1.1`.
+fn request_token(request: &str) -> Option<&str> {
+    let target = request.lines().next()?.split_whitespace().nth(1)?;
+    let (_, query) = target.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| *name == "token")
+        .map(|(_, value)| value)
+}
+
+/// Response to the opening handshake of a WebSocket connection (RFC 6455, section 4.2).
+fn handshake(request: &str) -> Result<String, String> {
+    let mut lines = request.lines();
+    if !lines.next().is_some_and(|line| line.starts_with("GET ")) {
+        return Err("expected a WebSocket upgrade request".to_string());
+    }
+    let key = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key"))
+        .map(|(_, value)| value.trim())
+        .ok_or_else(|| "missing Sec-WebSocket-Key".to_string())?;
+
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    Ok(format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        base64(&hasher.finalize())
+    ))
+}
+
+/// Unmasked WebSocket frame that carries a complete text message.
+fn text_frame(text: &str) -> Vec<u8> {
+    let len = text.len();
+    let mut frame = vec![0x81];
+    if len < 126 {
+        frame.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+/// Standard (padded) base64 encoding, as used for the handshake accept key.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+impl<H, I> Recipe<H, I> for RemoteLink
+    where
+        H: Hasher,
+{
+    type Output = Message;
+
+    fn hash(&self, state: &mut H) {
+        struct Marker;
+        TypeId::of::<Marker>().hash(state);
+        self.address.hash(state);
+        self.token.hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: futures::stream::BoxStream<'static, I>,
+    ) -> futures::stream::BoxStream<'static, Self::Output> {
+        let remote = match Remote::bind(&self.address, &self.token) {
+            Ok(remote) => Some(remote),
+            Err(e) => {
+                println!("Warning: {}", e);
+                None
+            }
+        };
+        Box::pin(futures::stream::unfold(
+            remote,
+            |remote| async {
+                let mut remote = remote?;
+                if remote.pending.is_empty() {
+                    remote.poll();
+                }
+                match remote.pending.pop_front() {
+                    Some(message) => Some((message, Some(remote))),
+                    None => {
+                        std::thread::sleep(Duration::from_millis(1));
+                        Some((Message::Null, Some(remote)))
+                    }
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn accepts_the_handshake_of_rfc_6455() {
+        let request = "GET /?token=secret HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\n\
+                       Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n";
+        let response = handshake(request).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(handshake("GET / HTTP/1.1\r\nHost: server.example.com\r\n").is_err());
+        assert!(handshake("POST / HTTP/1.1\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n").is_err());
+    }
+
+    #[test]
+    fn reads_the_token_of_a_request() {
+        assert_eq!(request_token("GET /?token=secret HTTP/1.1\r\nHost: x"), Some("secret"));
+        assert_eq!(request_token("GET /?view=full&token=secret HTTP/1.1"), Some("secret"));
+        assert_eq!(request_token("GET / HTTP/1.1"), None);
+        assert_eq!(request_token("GET /?view=full HTTP/1.1"), None);
+    }
+
+    #[test]
+    fn parses_control_commands() {
+        assert!(matches!(parse("start 2").unwrap().as_slice(), [Message::UIEvent(2, Value::Null)]));
+        assert!(matches!(parse("INTERRUPT").unwrap().as_slice(), [Message::Interrupt]));
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("start 0").is_err());
+        assert!(parse("stop").is_err());
+    }
+}
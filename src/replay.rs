@@ -0,0 +1,282 @@
+use std::any::TypeId;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use iced_native::subscription::Recipe;
+use iced_futures::futures;
+use serde::{Deserialize, Serialize};
+
+use crate::comm::{Code, Message, Value};
+use crate::error::Error;
+use crate::global::KeyChord;
+use crate::logger::open_log;
+
+/// Name of the input recording in the session output directory.
+pub const RECORDING: &str = "input.log";
+
+/// First line of a recording, with what is needed to reproduce the session besides its inputs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    task: String,
+    version: String,
+    seed: u64,
+}
+
+/// An input of the subject or the experimenter, as recorded for replay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Input {
+    Ui { code: Code, value: Value },
+    Key { chord: String },
+    Release { key: String },
+    Cursor { x: f32, y: f32 },
+    ConfirmAbort(bool),
+    RemoteStart(Code),
+    RemoteInterrupt,
+}
+
+impl Input {
+    fn from_message(message: &Message) -> Option<Self> {
+        match message {
+            Message::UIEvent(code, value) => Some(Input::Ui { code: *code, value: value.clone() }),
+            Message::KeyChord(key_code, modifiers) => {
+                Some(Input::Key { chord: KeyChord::new(*key_code, *modifiers).to_string() })
+            }
+            Message::KeyRelease(key_code) => Some(Input::Release { key: format!("{:?}", key_code) }),
+            Message::CursorMoved(x, y) => Some(Input::Cursor { x: *x, y: *y }),
+            Message::ConfirmAbort(confirmed) => Some(Input::ConfirmAbort(*confirmed)),
+            Message::Remote(message) => match message.as_ref() {
+                Message::UIEvent(block, Value::Null) => Some(Input::RemoteStart(*block)),
+                Message::Interrupt => Some(Input::RemoteInterrupt),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn into_message(self) -> Result<Message, String> {
+        Ok(match self {
+            Input::Ui { code, value } => Message::UIEvent(code, value),
+            Input::Key { chord } => {
+                let parsed = KeyChord::parse(&chord)?;
+                let key_code = parsed.key_code()
+                    .ok_or_else(|| format!("Unknown key in recording: {}", chord))?;
+                Message::KeyChord(key_code, parsed.modifiers())
+            }
+            Input::Release { key } => {
+                let key_code = KeyChord::parse(&key)?.key_code()
+                    .ok_or_else(|| format!("Unknown key in recording: {}", key))?;
+                Message::KeyRelease(key_code)
+            }
+            Input::Cursor { x, y } => Message::CursorMoved(x, y),
+            Input::ConfirmAbort(confirmed) => Message::ConfirmAbort(confirmed),
+            Input::RemoteStart(block) => Message::Remote(Box::new(Message::UIEvent(block, Value::Null))),
+            Input::RemoteInterrupt => Message::Remote(Box::new(Message::Interrupt)),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    at: f64,
+    input: Input,
+}
+
+/// Whether a message is an input that is recorded (and replaced by the recording in a replay).
+pub fn is_input(message: &Message) -> bool {
+    Input::from_message(message).is_some()
+}
+
+// Longest time that recorded inputs are kept in memory before they are written out
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends the inputs of a session to the recording as they arrive. They are buffered, and
+/// written out at least every `FLUSH_INTERVAL` and whenever the session logs are written (at
+/// the end of each block), so that the recording survives a crash.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    file: Option<BufWriter<File>>,
+    flushed: Option<Instant>,
+}
+
+impl Recorder {
+    /// Records an input, timed from `origin`. Starts the recording (in `log_dir`) if needed.
+    pub fn record(&mut self, message: &Message, origin: Instant, log_dir: &str, header: (&str, &str, u64)) {
+        let input = match Input::from_message(message) {
+            Some(input) => input,
+            None => return,
+        };
+        if self.file.is_none() {
+            self.file = Self::open(log_dir, header)
+                .map_err(|e| println!("Warning: Failed to record input: {}", e))
+                .ok();
+        }
+        if let Some(file) = &mut self.file {
+            let entry = Entry { at: origin.elapsed().as_secs_f64() * 1000.0, input };
+            if let Err(e) = writeln!(file, "{}", serde_json::to_string(&entry).unwrap()) {
+                println!("Warning: Failed to record input: {}", e);
+            }
+        }
+        if self.flushed.is_none_or(|flushed| flushed.elapsed() >= FLUSH_INTERVAL) {
+            self.flush();
+        }
+    }
+
+    /// Writes out the inputs recorded so far.
+    pub fn flush(&mut self) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = file.flush() {
+                println!("Warning: Failed to record input: {}", e);
+            }
+        }
+        self.flushed = Some(Instant::now());
+    }
+
+    fn open(log_dir: &str, (task, version, seed): (&str, &str, u64)) -> std::io::Result<BufWriter<File>> {
+        let path = Path::new(log_dir).join(RECORDING);
+        let is_new = !path.exists();
+        let mut file = BufWriter::new(open_log(path.to_str().unwrap())?);
+        if is_new {
+            let header = Header { task: task.to_string(), version: version.to_string(), seed };
+            writeln!(file, "{}", serde_json::to_string(&header).unwrap())?;
+        }
+        Ok(file)
+    }
+
+    /// Closes the recording (e.g. before its directory is renamed); it is reopened on the next input.
+    pub fn close(&mut self) {
+        self.flush();
+        self.file = None;
+    }
+}
+
+/// A recorded session: its random seed, and its inputs with their times from the start.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    seed: u64,
+    inputs: Arc<Vec<(Duration, Message)>>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)
+            .map_err(|e| Error::io(format!("Failed to open input recording: {:?}", path), e))?;
+        let mut lines = BufReader::new(file).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)
+                .map_err(|e| Error::Invalid(format!("Invalid header of input recording: {}", e)))?,
+            None => return Err(Error::Invalid(format!("Input recording is empty: {:?}", path))),
+        };
+        let mut inputs = vec![];
+        for (i, line) in lines.enumerate() {
+            let entry: Entry = serde_json::from_str(&line?)
+                .map_err(|e| Error::Invalid(format!("Invalid input recording at line {}: {}", i + 2, e)))?;
+            let message = entry.input.into_message().map_err(Error::Invalid)?;
+            inputs.push((Duration::from_secs_f64(entry.at / 1000.0), message));
+        }
+        println!("Replaying {} inputs recorded for {} {} (seed {})", inputs.len(), header.task, header.version, header.seed);
+        Ok(Replay { seed: header.seed, inputs: Arc::new(inputs) })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// Feeds the inputs of a recording back to the task at the times they were recorded, timed
+/// from `origin` (the start of the session, as in the recording).
+pub struct ReplayLink {
+    replay: Replay,
+    origin: Instant,
+}
+
+impl ReplayLink {
+    pub fn new(replay: &Replay, origin: Instant) -> Self {
+        ReplayLink { replay: replay.clone(), origin }
+    }
+}
+
+impl<H, I> Recipe<H, I> for ReplayLink
+    where
+        H: Hasher,
+{
+    type Output = Message;
+
+    fn hash(&self, state: &mut H) {
+        struct Marker;
+        TypeId::of::<Marker>().hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: futures::stream::BoxStream<'static, I>,
+    ) -> futures::stream::BoxStream<'static, Self::Output> {
+        let origin = self.origin;
+        Box::pin(futures::stream::unfold(
+            (self.replay.inputs, 0),
+            move |(inputs, next)| async move {
+                let (at, message) = inputs.get(next)?.clone();
+                match at.checked_sub(origin.elapsed()) {
+                    Some(wait) if !wait.is_zero() => {
+                        std::thread::sleep(wait.min(Duration::from_millis(1)));
+                        Some((Message::Null, (inputs, next)))
+                    }
+                    _ => Some((Message::Replay(Box::new(message)), (inputs, next + 1))),
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iced::keyboard::{KeyCode, Modifiers};
+    use super::*;
+
+    #[test]
+    fn replays_the_recorded_inputs_in_order() {
+        let dir = std::env::temp_dir().join("task-runner-tests").join("replay");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let shift = Modifiers { shift: true, ..Default::default() };
+        let inputs = [
+            Message::UIEvent(0x10, Value::String("S01".to_string())),
+            Message::Tick,
+            Message::KeyChord(KeyCode::F, shift),
+            Message::KeyRelease(KeyCode::F),
+            Message::CursorMoved(12.5, 40.0),
+            Message::ConfirmAbort(false),
+            Message::Remote(Box::new(Message::UIEvent(2, Value::Null))),
+            Message::Remote(Box::new(Message::Interrupt)),
+        ];
+        let mut recorder = Recorder::default();
+        let origin = Instant::now();
+        for message in &inputs {
+            recorder.record(message, origin, dir.to_str().unwrap(), ("T", "1.0", 42));
+        }
+        recorder.close();
+
+        let replay = Replay::load(&dir.join(RECORDING)).unwrap();
+        assert_eq!(replay.seed(), 42);
+        let expected: Vec<_> = inputs.iter().filter(|&message| is_input(message)).map(|message| format!("{:?}", message)).collect();
+        let replayed: Vec<_> = replay.inputs.iter().map(|(_, message)| format!("{:?}", message)).collect();
+        assert_eq!(replayed, expected);
+        assert_eq!(replayed.len(), inputs.len() - 1);
+        assert!(replay.inputs.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    #[test]
+    fn rejects_recordings_with_unknown_keys() {
+        let dir = std::env::temp_dir().join("task-runner-tests").join("replay-invalid");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(RECORDING);
+        std::fs::write(&path, concat!(
+            r#"{"task":"T","version":"1.0","seed":1}"#, "\n",
+            r#"{"at":5.0,"input":{"key":{"chord":"Ctrl+Nope"}}}"#, "\n",
+        )).unwrap();
+        assert!(Replay::load(&path).unwrap_err().to_string().contains("Unknown key in recording"));
+    }
+}
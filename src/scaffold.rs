@@ -0,0 +1,88 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+
+const TASK: &str = r#"title: New Task
+version: 1.0
+description: < description.txt
+
+# Global user interface options; see examples/Skeleton/task.yml for all of them
+global:
+  window_size: 900 x 780 # Outer size of the program window in pixels
+  content_size: 720 x 0.82 # Inner size of content, either as pixels or fraction of outer window
+  font_scale: 1.2 # Text size scaling, a value between 0.5 and 3.0
+
+# A list of experiment blocks, each containing a title and a list of actions to perform
+blocks:
+  - title: First Block
+    actions:
+      - type: instruction
+        prompt: "Welcome! Press the button to continue."
+
+      - type: instruction
+        prompt: Listen to the sound.
+        timer: 1500 # milliseconds
+
+      - type: audio
+        source: beep.wav # Relative to "resources" directory
+
+      # Templates (in the "templates" directory) package a list of actions, and replace
+      # parameters in the form of {{param_name}} with the given values
+      - type: template
+        source: question
+        params: { prompt: Did you hear the sound? }
+
+      - type: instruction
+        prompt: The block is complete.
+        timer: 2000
+"#;
+
+const TEMPLATE: &str = r#"- type: question
+  list:
+    - type: single_choice
+      prompt: {{prompt}}
+      options: [ "Yes", "No" ]
+"#;
+
+const DESCRIPTION: &str = "\
+A new task, created from the scaffold of task-runner.
+
+Edit task.yml to add blocks and actions, put stimulus files in resources/, and reusable \
+lists of actions in templates/.
+";
+
+/// Creates a minimal working task in `dir`, which must not exist or be empty: a task.yml with
+/// one block, a description, a silent placeholder sound, and an example template.
+pub fn create(dir: &Path) -> Result<(), Error> {
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+        return Err(Error::Invalid(format!("Directory is not empty: {:?}", dir)));
+    }
+    fs::create_dir_all(dir.join("resources"))?;
+    fs::create_dir_all(dir.join("templates"))?;
+    fs::write(dir.join("task.yml"), TASK)?;
+    fs::write(dir.join("resources").join("description.txt"), DESCRIPTION)?;
+    fs::write(dir.join("resources").join("beep.wav"), silence(44100, 500))?;
+    fs::write(dir.join("templates").join("question.yml"), TEMPLATE)?;
+    Ok(())
+}
+
+/// Mono 16-bit WAV file of silence, to be replaced by an actual stimulus.
+fn silence(sample_rate: u32, duration: u32) -> Vec<u8> {
+    let data_len = sample_rate * duration / 1000 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.resize(44 + data_len as usize, 0);
+    wav
+}
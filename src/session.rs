@@ -0,0 +1,169 @@
+use iced::{Column, Length, Row, Text, button, TextInput, text_input};
+use iced_native::Space;
+use serde::{Serialize, Deserialize};
+
+use crate::comm::{Code, Message, Value};
+use crate::global::Global;
+use crate::style::{self, button};
+
+/// Identifies a session by subject ID and task-defined metadata fields, entered by the
+/// operator before the first block. The subject ID is part of the output directory name.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    fields: Vec<SessionField>,
+    #[serde(skip)]
+    subject_handle: text_input::State,
+    #[serde(skip)]
+    handles: [button::State; 2],
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionField {
+    name: String,
+    #[serde(default, skip_serializing)]
+    prompt: String,
+    #[serde(default, skip_serializing)]
+    required: bool,
+    #[serde(default)]
+    value: String,
+    #[serde(skip)]
+    handle: text_input::State,
+}
+
+impl Session {
+    pub fn init(&mut self) -> Result<(), String> {
+        let mut names = vec![];
+        for field in &mut self.fields {
+            if field.name == "subject" || names.contains(&field.name) {
+                return Err(format!("Duplicate session field: `{}`", field.name));
+            }
+            names.push(field.name.clone());
+            if field.prompt.is_empty() {
+                field.prompt = field.name.clone();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_subject(&mut self, subject: &str) {
+        self.subject = subject.to_string();
+    }
+
+    pub fn subject(&self) -> &str {
+        self.subject.trim()
+    }
+
+    /// The reason the session cannot be started yet, if any.
+    fn incomplete(&self) -> Option<String> {
+        if self.subject().is_empty() {
+            Some("Subject ID is required".to_string())
+        } else if !self.subject().chars().all(|c| c.is_ascii_alphanumeric() || "_-".contains(c)) {
+            Some("Only alphanumeric (a-z|A-Z|0-9), '-', and '_' are allowed in subject IDs".to_string())
+        } else {
+            self.fields
+                .iter()
+                .find(|field| field.required && field.value.trim().is_empty())
+                .map(|field| format!("`{}` is required", field.prompt))
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.incomplete().is_none()
+    }
+
+    pub fn view(&mut self, global: &Global) -> Column<'_, Message> {
+        let missing = self.incomplete();
+        let mut content = Column::new()
+            .width(Length::Fill)
+            .spacing(60)
+            .align_items(global.alignment())
+            .push(Text::new("Session")
+                .size(global.text_size("XLARGE"))
+                .horizontal_alignment(global.horizontal_alignment()))
+            .push(field_view(
+                "Subject ID",
+                &self.subject,
+                &mut self.subject_handle,
+                0x10,
+                global));
+
+        for (i, field) in self.fields.iter_mut().enumerate() {
+            content = content.push(field_view(
+                &field.prompt,
+                &field.value,
+                &mut field.handle,
+                (0x11 + i) as Code,
+                global));
+        }
+        content = content.push(Space::with_height(Length::Fill));
+        if let Some(missing) = missing.as_ref() {
+            content = content.push(Text::new(missing)
+                .size(global.text_size("NORMAL"))
+                .color(style::parse_color("red").unwrap()));
+        }
+
+        let [h_cancel, h_start] = &mut self.handles;
+        let e_cancel = button(
+            h_cancel,
+            "Cancel",
+            global.text_size("LARGE"))
+            .on_press(Message::UIEvent(0x01, Value::Null))
+            .style(style::Button::Secondary)
+            .width(Length::Units(200))
+            .padding(15);
+        let mut e_start = button(
+            h_start,
+            "Start!",
+            global.text_size("LARGE"))
+            .style(style::Button::Primary)
+            .width(Length::Units(200))
+            .padding(15);
+        if missing.is_none() {
+            e_start = e_start.on_press(Message::UIEvent(0x03, Value::Null));
+        }
+
+        content.push(Row::new()
+            .push(e_cancel)
+            .push(Space::with_width(Length::Fill))
+            .push(e_start))
+    }
+
+    pub fn update(&mut self, code: Code, value: Value) -> Result<(), String> {
+        match (code, value) {
+            (0x10, Value::String(s)) => {
+                self.subject = s;
+            }
+            (code, Value::String(s)) if code >= 0x11 && ((code - 0x11) as usize) < self.fields.len() => {
+                self.fields[(code - 0x11) as usize].value = s;
+            }
+            (code, value) => return Err(format!("Invalid session field code or value type: {} {:?}", code, value)),
+        }
+        Ok(())
+    }
+}
+
+fn field_view<'a>(
+    prompt: &str,
+    value: &str,
+    handle: &'a mut text_input::State,
+    code: Code,
+    global: &Global,
+) -> Column<'a, Message> {
+    Column::new()
+        .align_items(global.alignment())
+        .spacing(25)
+        .push(Text::new(prompt)
+            .size(global.text_size("LARGE")))
+        .push(TextInput::new(
+            handle,
+            "",
+            value,
+            move |s| Message::UIEvent(code, Value::String(s)))
+            .size(global.text_size("LARGE"))
+            .width(Length::Units(400)))
+}
@@ -0,0 +1,139 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::mpsc;
+use std::time::Duration;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::Deserialize;
+
+use crate::action::ID;
+use crate::block::Block;
+use crate::comm::Message;
+use crate::dispatch::{Dispatcher, Status};
+use crate::error::Error;
+use crate::global::Global;
+use crate::sound::Playback;
+
+/// A scripted timeline of responses, keyed by action ID. Each entry gives the response to an
+/// action (in the same form as it is written to the output files) and its delay from the onset
+/// of the action, in milliseconds.
+pub type Timeline = HashMap<ID, Scripted>;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scripted {
+    after: u32,
+    #[serde(default)]
+    response: Vec<String>,
+}
+
+/// Delay from the onset of an action to its automatic response, if it has no scripted one.
+const AUTO_RESPONSE_DELAY: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    Complete(ID),
+    Respond(ID),
+    Timeout(ID),
+}
+
+/// Runs a block headlessly on a virtual clock, without a display or an audio device. Actions
+/// end on their own timers (or the duration of their audio), on scripted responses, or on
+/// timeouts. With `auto_respond`, actions that wait on the subject and have no scripted
+/// response get a random one (drawn from the session seed) a second after their onset.
+/// Returns a trace of the simulated events.
+pub fn run(block: Block, global: &Global, timeline: &Timeline, auto_respond: bool) -> Result<Vec<String>, Error> {
+    let (writer, _) = mpsc::channel();
+    let mut dispatcher = Dispatcher::new(writer);
+    let mut queue = BinaryHeap::new();
+    let mut trace = vec![];
+    let mut now = Duration::ZERO;
+    let mut onsets = HashMap::new();
+    let mut seq = 0;
+    let mut rng = StdRng::seed_from_u64(global.seed());
+    let mut responses = HashMap::new();
+
+    let mut step = dispatcher.begin(block);
+    loop {
+        for id in &step.start {
+            let block = dispatcher.block_mut().unwrap();
+            block.start(id, global);
+            onsets.insert(id.clone(), now);
+            trace.push(format!("{:>10.1} ms  START  {}", now.as_secs_f64() * 1000.0, id));
+
+            let action = block.action(id)?;
+            let mut events = vec![];
+            if let Some(timeout) = action.timeout() {
+                events.push((now + timeout, Event::Timeout(id.clone())));
+            }
+            if let Some(duration) = action.duration(global)? {
+                events.push((now + duration, Event::Complete(id.clone())));
+            }
+            if let Some(scripted) = timeline.get(id) {
+                let after = Duration::from_millis(scripted.after as u64);
+                responses.insert(id.clone(), scripted.response.clone());
+                events.push((now + after, Event::Respond(id.clone())));
+            } else if let Some(response) = action.random_response(&mut rng).filter(|_| auto_respond) {
+                responses.insert(id.clone(), response);
+                let delay = AUTO_RESPONSE_DELAY.max(action.earliest_response());
+                events.push((now + delay, Event::Respond(id.clone())));
+            }
+            for (t, event) in events {
+                seq += 1;
+                queue.push(Reverse((t, seq, event)));
+            }
+        }
+
+        match step.status {
+            Status::Complete => break,
+            Status::Deadlock => return Err(Error::InvalidGraph("Arrived at a deadlock; unable to reach some actions".to_string())),
+            Status::Running => (),
+        }
+
+        let (t, event) = loop {
+            match queue.pop() {
+                Some(Reverse((t, _, event))) => {
+                    let id = match &event {
+                        Event::Complete(id) | Event::Respond(id) | Event::Timeout(id) => id,
+                    };
+                    if dispatcher.active().contains(id) {
+                        break (t, event);
+                    }
+                }
+                None => {
+                    let mut waiting: Vec<_> = dispatcher.active().iter().cloned().collect();
+                    waiting.sort();
+                    return Err(Error::Invalid(format!("Simulation stalled waiting for a response from: {}", waiting.join(", "))));
+                }
+            }
+        };
+        now = t;
+
+        let block = dispatcher.block_mut().unwrap();
+        let id = match event {
+            Event::Complete(id) => {
+                trace.push(format!("{:>10.1} ms  COMPLETE  {}", now.as_secs_f64() * 1000.0, id));
+                if block.action(&id)?.plays_audio() {
+                    block.playback(&id, Playback::Completed);
+                }
+                id
+            }
+            Event::Respond(id) => {
+                let response = &responses[&id];
+                trace.push(format!("{:>10.1} ms  RESPOND  {}  {:?}", now.as_secs_f64() * 1000.0, id, response));
+                block.action_mut(&id)?.set_response(response)?;
+                block.respond_after(&id, now - onsets[&id]);
+                id
+            }
+            Event::Timeout(id) => {
+                trace.push(format!("{:>10.1} ms  TIMEOUT  {}", now.as_secs_f64() * 1000.0, id));
+                block.time_out(&id);
+                id
+            }
+        };
+        step = dispatcher.finish(id);
+    }
+
+    dispatcher.update(Message::BlockComplete, global);
+    Ok(trace)
+}
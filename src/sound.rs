@@ -1,33 +1,160 @@
-use rodio::{Decoder, OutputStreamHandle, Sample, Sink, Source};
+use rodio::{Decoder, DeviceTrait, OutputStream, OutputStreamHandle, Sample, Sink, Source};
+use rodio::cpal::traits::HostTrait;
+use rodio::source::Zero;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::TryRecvError;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::comm::{Comm, Message};
+use crate::comm::{Comm, Message, wait_for_resume};
 
-pub fn play_audio(comm: Comm, src: &Path, trigger: Option<&Path>, stream_handle: OutputStreamHandle) -> Result<(), ()> {
-    let sink = Sink::try_new(&stream_handle)
-        .expect("Failed to open sink stream");
+// Audio decoded ahead of the running block, so that playback does not wait on the decoder
+static PRELOADED: Mutex<BTreeMap<PathBuf, Preloaded>> = Mutex::new(BTreeMap::new());
 
-    let file = BufReader::new(File::open(src)
-        .expect(&format!("File not found: {:?}", src)));
-    let source = Decoder::new(file).unwrap();
+/// Trigger channel played on the right channel alongside mono audio.
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    None,
+    /// A file with the same sampling rate and length as the audio
+    File(PathBuf),
+    /// Square pulses at the onset of the audio, and at the event times (ms) listed in a
+    /// `.trig.yml` file next to the audio file, if there is one
+    Pulses {
+        width_ms: u32,
+        amplitude: f32,
+    },
+    /// A silent channel, which keeps the audio on the left channel only
+    Silent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Playback {
+    Completed,
+    Interrupted,
+    Failed(String),
+}
+
+impl fmt::Display for Playback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Playback::Completed => write!(f, "completed"),
+            Playback::Interrupted => write!(f, "interrupted"),
+            Playback::Failed(e) => write!(f, "failed: {}", e),
+        }
+    }
+}
+
+/// Plays an audio file (with its trigger, if given) a number of times in a row, or in a loop
+/// until interrupted if `repeats` is None. Each playback has a sink of its own, which is
+/// mixed with any other audio playing at the same time.
+pub fn play_audio(
+    comm: Comm,
+    src: &Path,
+    trigger: &Trigger,
+    repeats: Option<u32>,
+    volume: f32,
+    stream_handle: OutputStreamHandle
+) -> Playback {
+    let sink = match Sink::try_new(&stream_handle) {
+        Ok(sink) => sink,
+        Err(e) => return Playback::Failed(format!("Failed to open sink stream: {}", e)),
+    };
 
-    match trigger {
-        Some(path) => {
-            println!("Using trigger file: {:?}", path);
-            let file = BufReader::new(File::open(path).unwrap());
-            let trigger = Decoder::new(file).unwrap();
-            sink.append(Triggered::new(source, trigger))
+    if let Trigger::File(path) = trigger {
+        println!("Using trigger file: {:?}", path);
+    }
+    match repeats {
+        Some(n) => {
+            for _ in 0..n {
+                match load_with_trigger(src, trigger, volume) {
+                    Ok(source) => sink.append(source),
+                    Err(e) => return Playback::Failed(e),
+                }
+            }
         }
         None => {
-            sink.append(source);
+            match load_with_trigger(src, trigger, volume) {
+                Ok(source) => sink.append(source.repeat_infinite()),
+                Err(e) => return Playback::Failed(e),
+            }
         }
     }
 
+    wait_for_sink(&comm, &sink)
+}
+
+fn load_with_trigger(src: &Path, trigger: &Trigger, volume: f32) -> Result<Box<dyn Source<Item = i16> + Send>, String> {
+    // The volume only applies to the audio, so that the trigger channel keeps its level
+    let source = load(src)?.amplify(volume);
+    Ok(match trigger {
+        Trigger::None => Box::new(source),
+        Trigger::File(path) => Box::new(Triggered::new(source, load(path)?)),
+        Trigger::Pulses { width_ms, amplitude } => {
+            let pulses = PulseTrain::new(source.sample_rate(), &trigger_events(src)?, *width_ms, *amplitude);
+            Box::new(Triggered::with_pulses(source, pulses))
+        }
+        Trigger::Silent => {
+            let pulses = PulseTrain::new(source.sample_rate(), &[], 0, 0.0);
+            Box::new(Triggered::with_pulses(source, pulses))
+        }
+    })
+}
+
+/// Onset times (ms) of the trigger pulses of an audio file: its start, followed by the event
+/// times listed in its `.trig.yml` file, if there is one.
+fn trigger_events(src: &Path) -> Result<Vec<f64>, String> {
+    let path = src.with_extension("trig.yml");
+    let mut events = vec![0.0];
+    if path.exists() {
+        let file = File::open(&path)
+            .map_err(|e| format!("Failed to open trigger events file {:?}: {}", path, e))?;
+        let times: Vec<f64> = serde_yaml::from_reader(file)
+            .map_err(|e| format!("Failed to read trigger events file {:?}: {}", path, e))?;
+        if times.iter().any(|&t| t < 0.0) {
+            return Err(format!("Trigger event times should not be negative: {:?}", path));
+        }
+        events.extend(times);
+    }
+    Ok(events)
+}
+
+/// Plays audio files back-to-back in a single sink, with the given silent intervals
+/// between the end of each file and the start of the next, so that clip onsets are
+/// sample-accurate relative to the start of playback.
+pub fn play_sequence(
+    comm: Comm,
+    sources: &[PathBuf],
+    intervals: &[Duration],
+    volume: f32,
+    stream_handle: OutputStreamHandle
+) -> Playback {
+    let sink = match Sink::try_new(&stream_handle) {
+        Ok(sink) => sink,
+        Err(e) => return Playback::Failed(format!("Failed to open sink stream: {}", e)),
+    };
+
+    for (i, src) in sources.iter().enumerate() {
+        let source = match load(src) {
+            Ok(source) => source.amplify(volume),
+            Err(e) => return Playback::Failed(e),
+        };
+        let (channels, rate) = (source.channels(), source.sample_rate());
+        sink.append(source);
+        if let Some(&interval) = intervals.get(i) {
+            sink.append(Zero::<i16>::new(channels, rate)
+                .take_duration(interval));
+        }
+    }
+
+    wait_for_sink(&comm, &sink)
+}
+
+fn wait_for_sink(comm: &Comm, sink: &Sink) -> Playback {
     while !sink.empty() {
         thread::sleep(Duration::from_millis(1));
         match comm.1.try_recv() {
@@ -35,36 +162,153 @@ pub fn play_audio(comm: Comm, src: &Path, trigger: Option<&Path>, stream_handle:
             Ok(Message::Interrupt) |
             Err(TryRecvError::Disconnected) => {
                 sink.stop();
-                return Err(());
+                return Playback::Interrupted;
             },
+            Ok(Message::Pause) => {
+                sink.pause();
+                match wait_for_resume(&comm.1) {
+                    Ok(true) => sink.play(),
+                    Ok(false) => {
+                        sink.stop();
+                        return Playback::Interrupted;
+                    }
+                    Err(e) => {
+                        sink.stop();
+                        return Playback::Failed(e);
+                    }
+                }
+            }
             Err(TryRecvError::Empty) => (),
-            _ => panic!("Unexpected message received"),
+            Ok(msg) => {
+                sink.stop();
+                return Playback::Failed(format!("Unexpected message received by playback: {:?}", msg));
+            }
+        }
+    }
+    Playback::Completed
+}
+
+/// Names of the audio output devices available on the default host.
+pub fn output_devices() -> Vec<String> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Opens an output stream on the named device, or on the default device if none is named.
+pub fn open_output(device: Option<&str>) -> Result<(OutputStream, OutputStreamHandle), String> {
+    let name = match device {
+        Some(name) => name,
+        None => return OutputStream::try_default()
+            .map_err(|e| format!("Failed to open default audio output: {}", e)),
+    };
+    let device = rodio::cpal::default_host()
+        .output_devices()
+        .map_err(|e| format!("Failed to list audio output devices: {}", e))?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+        .ok_or_else(|| format!("Audio output device not found: {}", name))?;
+    OutputStream::try_from_device(&device)
+        .map_err(|e| format!("Failed to open audio output device {}: {}", name, e))
+}
+
+/// Plays a brief silence to prime the output device before the first real stimulus, and
+/// returns the time it took for the device to request the first sample.
+pub fn warm_up(stream_handle: &OutputStreamHandle, duration: Duration) -> Result<Duration, String> {
+    let sink = Sink::try_new(stream_handle)
+        .map_err(|e| format!("Failed to open sink stream: {}", e))?;
+
+    let first_sample = Arc::new(Mutex::new(None));
+    let marker = first_sample.clone();
+    let source = Zero::<f32>::new(1, 44100)
+        .take_duration(duration)
+        .periodic_access(duration + Duration::from_secs(1), move |_| {
+            marker.lock().unwrap().get_or_insert_with(Instant::now);
+        });
+
+    let start = Instant::now();
+    sink.append(source);
+    sink.sleep_until_end();
+
+    let first_sample = *first_sample.lock().unwrap();
+    first_sample
+        .map(|t| t - start)
+        .ok_or_else(|| "Output device did not request any samples".to_string())
+}
+
+/// Duration of an audio file, counting its samples if the format does not report one.
+pub fn duration(path: &Path) -> Result<Duration, String> {
+    let source = decode(path)?;
+    if let Some(duration) = source.total_duration() {
+        return Ok(duration);
+    }
+    let rate = source.sample_rate() as f64 * source.channels() as f64;
+    let samples = source.count() as f64;
+    Ok(Duration::from_secs_f64(samples / rate))
+}
+
+/// Decodes audio files into memory ahead of playback, each one kept once decoded (until
+/// `clear_preloaded`), so that a file that fails to decode does not keep the others from
+/// being preloaded. Files that are not preloaded are decoded while they play.
+pub fn preload(paths: &[PathBuf]) -> Result<(), String> {
+    let mut errors = vec![];
+    for path in paths {
+        if PRELOADED.lock().unwrap().contains_key(path) {
+            continue;
         }
+        match decode(path) {
+            Ok(source) => {
+                let preloaded = Preloaded::new(source);
+                PRELOADED.lock().unwrap().insert(path.clone(), preloaded);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
     }
-    Ok(())
+}
+
+/// Drops the preloaded audio, e.g. once the block it was decoded for ends.
+pub fn clear_preloaded() {
+    PRELOADED.lock().unwrap().clear();
+}
+
+fn load(path: &Path) -> Result<Clip, String> {
+    match PRELOADED.lock().unwrap().get(path) {
+        Some(preloaded) => Ok(Clip::Preloaded(preloaded.clone())),
+        None => decode(path).map(|source| Clip::Streamed(Box::new(source))),
+    }
+}
+
+fn decode(path: &Path) -> Result<Decoder<BufReader<File>>, String> {
+    let file = File::open(path)
+        .map_err(|_| format!("File not found: {:?}", path))?;
+    Decoder::new(BufReader::new(file))
+        .map_err(|e| format!("Failed to decode audio file {:?}: {}", path, e))
 }
 
 #[derive(Clone, Debug)]
-pub struct Triggered<I>
+pub struct Triggered<I, T = I>
 where
     I: Source,
     I::Item: Sample,
+    T: Source<Item = I::Item>,
 {
     input: I,
-    trigger: I,
+    trigger: T,
     current_channel: u16,
 }
 
-impl<I> Triggered<I>
+impl<I, T> Triggered<I, T>
 where
     I: Source,
     I::Item: Sample,
+    T: Source<Item = I::Item>,
 {
-    pub fn new(input: I, trigger: I) -> Triggered<I>
-    where
-        I: Source,
-        I::Item: Sample,
-    {
+    pub fn new(input: I, trigger: T) -> Triggered<I, T> {
         assert_eq!(
             input.channels(),
             1,
@@ -88,7 +332,34 @@ where
             current_channel: 0,
         }
     }
+}
 
+impl<I> Triggered<I, PulseTrain>
+where
+    I: Source<Item = i16>,
+{
+    /// Pairs the audio with a synthesized trigger, which lasts as long as the audio.
+    pub fn with_pulses(input: I, trigger: PulseTrain) -> Self {
+        assert_eq!(
+            input.channels(),
+            1,
+            "When using a trigger, audio signal should be mono"
+        );
+
+        Triggered {
+            input,
+            trigger,
+            current_channel: 0,
+        }
+    }
+}
+
+impl<I, T> Triggered<I, T>
+where
+    I: Source,
+    I::Item: Sample,
+    T: Source<Item = I::Item>,
+{
     /// Returns a reference to the inner source.
     #[inline]
     pub fn inner(&self) -> &I {
@@ -107,11 +378,11 @@ where
         self.input
     }
 }
-
-impl<I> Iterator for Triggered<I>
+impl<I, T> Iterator for Triggered<I, T>
 where
     I: Source,
     I::Item: Sample,
+    T: Source<Item = I::Item>,
 {
     type Item = I::Item;
 
@@ -142,17 +413,19 @@ where
     }
 }
 
-impl<I> ExactSizeIterator for Triggered<I>
+impl<I, T> ExactSizeIterator for Triggered<I, T>
 where
     I: Source + ExactSizeIterator,
     I::Item: Sample,
+    T: Source<Item = I::Item>,
 {
 }
 
-impl<I> Source for Triggered<I>
+impl<I, T> Source for Triggered<I, T>
 where
     I: Source,
     I::Item: Sample,
+    T: Source<Item = I::Item>,
 {
     #[inline]
     fn current_frame_len(&self) -> Option<usize> {
@@ -174,3 +447,161 @@ where
         self.input.total_duration()
     }
 }
+
+/// Audio that is either decoded in memory or streamed from its file.
+pub enum Clip {
+    Preloaded(Preloaded),
+    Streamed(Box<Decoder<BufReader<File>>>),
+}
+
+impl Iterator for Clip {
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        match self {
+            Clip::Preloaded(source) => source.next(),
+            Clip::Streamed(source) => source.next(),
+        }
+    }
+}
+
+impl Source for Clip {
+    fn current_frame_len(&self) -> Option<usize> {
+        match self {
+            Clip::Preloaded(source) => source.current_frame_len(),
+            Clip::Streamed(source) => source.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match self {
+            Clip::Preloaded(source) => source.channels(),
+            Clip::Streamed(source) => source.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Clip::Preloaded(source) => source.sample_rate(),
+            Clip::Streamed(source) => source.sample_rate(),
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self {
+            Clip::Preloaded(source) => source.total_duration(),
+            Clip::Streamed(source) => source.total_duration(),
+        }
+    }
+}
+
+/// Decoded samples of an audio file, shared between plays of the same file.
+#[derive(Clone)]
+pub struct Preloaded {
+    samples: Arc<Vec<i16>>,
+    channels: u16,
+    sample_rate: u32,
+    position: usize,
+}
+
+impl Preloaded {
+    fn new(source: Decoder<BufReader<File>>) -> Self {
+        let (channels, sample_rate) = (source.channels(), source.sample_rate());
+        Preloaded {
+            samples: Arc::new(source.collect()),
+            channels,
+            sample_rate,
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for Preloaded {
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for Preloaded {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let frames = self.samples.len() / self.channels.max(1) as usize;
+        Some(Duration::from_secs_f64(frames as f64 / self.sample_rate as f64))
+    }
+}
+
+/// Mono trigger signal of square pulses starting at the given times (ms). It does not end on
+/// its own, and is meant to be paired with the audio it marks.
+pub struct PulseTrain {
+    sample_rate: u32,
+    pulses: Vec<(usize, usize)>,
+    level: i16,
+    current: usize,
+    position: usize,
+}
+
+impl PulseTrain {
+    pub fn new(sample_rate: u32, onsets_ms: &[f64], width_ms: u32, amplitude: f32) -> Self {
+        let to_sample = |ms: f64| (ms * sample_rate as f64 / 1000.0).round() as usize;
+        let mut pulses: Vec<_> = onsets_ms.iter()
+            .map(|&onset| (to_sample(onset), to_sample(onset + width_ms as f64)))
+            .collect();
+        pulses.sort_unstable();
+        PulseTrain {
+            sample_rate,
+            pulses,
+            level: (amplitude * i16::MAX as f32) as i16,
+            current: 0,
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for PulseTrain {
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        while self.current < self.pulses.len() && self.pulses[self.current].1 <= self.position {
+            self.current += 1;
+        }
+        let on = self.pulses.get(self.current).is_some_and(|&(start, _)| start <= self.position);
+        self.position += 1;
+        Some(if on { self.level } else { 0 })
+    }
+}
+
+impl Source for PulseTrain {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -1,6 +1,247 @@
-use iced::{button, HorizontalAlignment, Text, VerticalAlignment};
+use std::sync::RwLock;
+use iced::{button, Align, Color, Column, Element, HorizontalAlignment, Length, Row, Text, VerticalAlignment};
+use serde::{Deserialize, Serialize};
 
-pub use style::Button;
+use crate::global::{Global, TEXT_SIZES};
+
+pub use style::{Button, Container};
+
+// Colors of the user interface, set from the task theme when the app starts
+static PALETTE: RwLock<Palette> = RwLock::new(Palette::DEFAULT);
+
+/// Task-defined colors of the user interface (names or #RRGGBB), e.g. for a dark theme.
+/// Colors that are not specified keep their default: black text on a white background,
+/// with blue (primary) and gray (secondary) buttons.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    background: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    primary: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    secondary: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    button_text: Option<String>,
+}
+
+impl Theme {
+    pub fn palette(&self) -> Result<Palette, String> {
+        let color = |value: &Option<String>, default: Color| {
+            value.as_deref().map(parse_color).unwrap_or(Ok(default))
+        };
+        let default = Palette::DEFAULT;
+        let button_text = color(&self.button_text, default.button_text)?;
+        Ok(Palette {
+            background: color(&self.background, default.background)?,
+            text: color(&self.text, default.text)?,
+            primary: color(&self.primary, default.primary)?,
+            secondary: color(&self.secondary, default.secondary)?,
+            button_text,
+            button_hover_text: if self.button_text.is_some() { button_text } else { default.button_hover_text },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub background: Color,
+    pub text: Color,
+    pub primary: Color,
+    pub secondary: Color,
+    pub button_text: Color,
+    pub button_hover_text: Color,
+}
+
+impl Palette {
+    const DEFAULT: Palette = Palette {
+        background: Color::WHITE,
+        text: Color::BLACK,
+        primary: Color::from_rgb(0.11, 0.42, 0.87),
+        secondary: Color::from_rgb(0.5, 0.5, 0.5),
+        button_text: Color::from_rgb(0.933, 0.933, 0.933),
+        button_hover_text: Color::WHITE,
+    };
+}
+
+pub fn set_palette(palette: Palette) {
+    *PALETTE.write().unwrap() = palette;
+}
+
+pub fn palette() -> Palette {
+    *PALETTE.read().unwrap()
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextStyle {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    text_size: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    alignment: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    color: Option<String>,
+}
+
+impl TextStyle {
+    pub fn verify(&self) -> Result<(), String> {
+        if let Some(size) = &self.text_size {
+            if !TEXT_SIZES.contains(&size.to_uppercase().as_str()) {
+                return Err(format!("Text size should be one of: {:?}", TEXT_SIZES));
+            }
+        }
+        if let Some(align) = &self.alignment {
+            if alignment(align).is_none() {
+                return Err(format!("Invalid text alignment value: {}", align));
+            }
+        }
+        if let Some(color) = &self.color {
+            parse_color(color)?;
+        }
+        Ok(())
+    }
+
+    pub fn text_size(&self, global: &Global, default: &str) -> u16 {
+        global.text_size(self.text_size.as_deref().unwrap_or(default))
+    }
+
+    pub fn alignment(&self, global: &Global) -> Align {
+        match &self.alignment {
+            Some(align) => alignment(align).unwrap(),
+            None => global.alignment(),
+        }
+    }
+
+    pub fn horizontal_alignment(&self, global: &Global) -> HorizontalAlignment {
+        match &self.alignment {
+            Some(align) => horizontal_alignment(align).unwrap(),
+            None => global.horizontal_alignment(),
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match &self.color {
+            Some(color) => parse_color(color).unwrap(),
+            None => palette().text,
+        }
+    }
+}
+
+/// Whether a grid of buttons is filled row by row (`horizontal`) or column by column (`vertical`).
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+/// Layout of a set of buttons, three per row by default.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Grid {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    columns: Option<usize>,
+    #[serde(default="default_button_width")]
+    button_width: u16,
+    #[serde(default)]
+    orientation: Orientation,
+}
+
+pub fn default_button_width() -> u16 {
+    200
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid::new(None, default_button_width(), Orientation::default())
+    }
+}
+
+impl Grid {
+    pub fn new(columns: Option<usize>, button_width: u16, orientation: Orientation) -> Self {
+        Grid { columns, button_width, orientation }
+    }
+
+    pub fn verify(&self) -> Result<(), String> {
+        if self.columns == Some(0) {
+            return Err("Number of button columns should be positive".to_string());
+        }
+        if self.button_width == 0 {
+            return Err("Button width should be positive".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn button_width(&self) -> Length {
+        Length::Units(self.button_width)
+    }
+
+    /// Arranges elements in rows of at most `columns` elements, in the order of the grid.
+    pub fn arrange<'a, T: 'a>(&self, elements: Vec<Element<'a, T>>) -> Column<'a, T> {
+        let count = elements.len();
+        let columns = self.columns.unwrap_or(3).min(count.max(1));
+        let rows = count.div_ceil(columns);
+        let mut elements: Vec<_> = elements.into_iter().map(Some).collect();
+        let mut grid = Column::new()
+            .spacing(40)
+            .align_items(Align::Center);
+        for row in 0..rows {
+            let mut controls = Row::new()
+                .spacing(60)
+                .align_items(Align::Center);
+            for column in 0..columns {
+                let index = match self.orientation {
+                    Orientation::Horizontal => row * columns + column,
+                    Orientation::Vertical => column * rows + row,
+                };
+                if let Some(element) = elements.get_mut(index).and_then(Option::take) {
+                    controls = controls.push(element);
+                }
+            }
+            grid = grid.push(controls);
+        }
+        grid
+    }
+}
+
+pub fn alignment(value: &str) -> Option<Align> {
+    match value.to_uppercase().as_str() {
+        "START" | "LEFT" => Some(Align::Start),
+        "CENTER" => Some(Align::Center),
+        "END" | "RIGHT" => Some(Align::End),
+        _ => None,
+    }
+}
+
+pub fn horizontal_alignment(value: &str) -> Option<HorizontalAlignment> {
+    match value.to_uppercase().as_str() {
+        "START" | "LEFT" => Some(HorizontalAlignment::Left),
+        "CENTER" => Some(HorizontalAlignment::Center),
+        "END" | "RIGHT" => Some(HorizontalAlignment::Right),
+        _ => None,
+    }
+}
+
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let color = match value.to_lowercase().as_str() {
+        "black" => Color::BLACK,
+        "white" => Color::WHITE,
+        "gray" | "grey" => Color::from_rgb(0.5, 0.5, 0.5),
+        "red" => Color::from_rgb(1.0, 0.0, 0.0),
+        "green" => Color::from_rgb(0.0, 1.0, 0.0),
+        "blue" => Color::from_rgb(0.0, 0.0, 1.0),
+        hex if hex.starts_with('#') && hex.len() == 7 && hex.is_ascii() => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i+2], 16)
+                .or(Err(format!("Invalid hex color: {}", value)));
+            Color::from_rgb8(channel(1)?, channel(3)?, channel(5)?)
+        }
+        _ => return Err(format!("Invalid color (expected a name or #RRGGBB): {}", value)),
+    };
+    Ok(color)
+}
 
 pub fn button<'a, T: Clone>(
     state: &'a mut button::State,
@@ -18,7 +259,23 @@ pub fn button<'a, T: Clone>(
 }
 
 mod style {
-    use iced::{button, Background, Color, Vector};
+    use iced::{button, container, Background, Color, Vector};
+    use super::palette;
+
+    /// Themed background and default text color of the whole window, with the background
+    /// replaced by the given color if any (e.g. the `background_color` of an action).
+    pub struct Container(pub Option<Color>);
+
+    impl container::StyleSheet for Container {
+        fn style(&self) -> container::Style {
+            let palette = palette();
+            container::Style {
+                text_color: Some(palette.text),
+                background: Some(Background::Color(self.0.unwrap_or(palette.background))),
+                ..container::Style::default()
+            }
+        }
+    }
 
     pub enum Button {
         Primary,
@@ -34,8 +291,8 @@ mod style {
         fn active(&self) -> button::Style {
             button::Style {
                 background: Some(Background::Color(match self {
-                    Button::Primary => Color::from_rgb(0.11, 0.42, 0.87),
-                    Button::Secondary => Color::from_rgb(0.5, 0.5, 0.5),
+                    Button::Primary => palette().primary,
+                    Button::Secondary => palette().secondary,
                     Button::Destructive => Color::from_rgb(0.8, 0.2, 0.2),
                     Button::Inactive => Color::WHITE,
                     Button::Active => Color::from_rgb(1.0, 0.9, 0.0),
@@ -56,7 +313,7 @@ mod style {
                 text_color: match self {
                     Button::Inactive | Button::Active => Color::BLACK,
                     Button::Todo | Button::Done => Color::BLACK,
-                    _ => Color::from_rgb8(0xEE, 0xEE, 0xEE),
+                    _ => palette().button_text,
                 },
                 ..button::Style::default()
             }
@@ -71,7 +328,7 @@ mod style {
                 text_color: match self {
                     Button::Inactive | Button::Active => Color::BLACK,
                     Button::Todo | Button::Done => Color::BLACK,
-                    _ => Color::WHITE,
+                    _ => palette().button_hover_text,
                 },
                 shadow_offset: Vector::new(1.0, 2.0),
                 ..self.active()
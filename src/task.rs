@@ -3,18 +3,32 @@ use std::fmt::Debug;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use iced::{Column, Command, Element, Length, Row, Text, button, Align};
+use std::time::{Duration, Instant};
+use iced::{Color, Column, Command, Element, HorizontalAlignment, Length, Row, Text, button, Align};
 use iced_native::Space;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 use serde::{Serialize, Deserialize};
 
 use crate::block::Block;
 use crate::comm::{Message, Value};
 use crate::config::Config;
+use crate::consent::Consent;
+use crate::console::{self, Snapshot};
 use crate::dispatch::Dispatcher;
+use crate::environment::Environment;
+use crate::error::Error;
+use crate::markup;
+use crate::pseudonym::Pseudonymize;
+use crate::replay::{self, Recorder, Replay};
+use crate::session::Session;
 use crate::style::{self, button};
-use crate::util::{resource, timestamp};
+use crate::logger::{async_write_to_file, write_to_file, write_manifest, EventLog};
+use crate::util::{resource, timestamp, event_time, free_space};
 use crate::global::Global;
+use crate::simulate::{self, Timeline};
+use crate::upload::Upload;
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -25,6 +39,25 @@ pub struct Task {
     description: String,
     #[serde(default)]
     configuration: Config,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    consent: Option<Consent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session: Option<Session>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pseudonymize: Option<Pseudonymize>,
+    #[serde(default)]
+    debrief: Debrief,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    upload: Option<Upload>,
+    #[serde(default)]
+    unlock: Unlock,
+    #[serde(default)]
+    block_order: BlockSequence,
+    /// Realized order of the blocks (by number), if they are rearranged for the session
+    #[serde(skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    sequence: Vec<usize>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    counterbalance: Option<usize>,
     #[serde(default)]
     blocks: Vec<Block>,
     #[serde(default)]
@@ -32,32 +65,149 @@ pub struct Task {
     #[serde(skip)]
     progress: Vec<bool>,
     #[serde(skip)]
+    practiced: Vec<bool>,
+    #[serde(skip)]
     dispatcher: Option<Dispatcher>,
     #[serde(skip)]
     state: State,
     #[serde(skip)]
     log_dir: String,
     #[serde(skip)]
-    events: Vec<String>,
+    events: EventLog,
     #[serde(skip)]
     active_block: Option<usize>,
+    #[serde(skip)]
+    started: Option<Instant>,
+    #[serde(skip)]
+    low_disk: Option<u64>,
+    #[serde(skip)]
+    abort_dialog: Option<[button::State; 2]>,
+    #[serde(skip)]
+    options: Options,
+    #[serde(skip)]
+    in_session: bool,
+    #[serde(skip)]
+    next_block: Option<usize>,
+    #[serde(skip)]
+    exit: Option<i32>,
+    #[serde(skip)]
+    recorder: Recorder,
+    /// Whether the session already ran out of time, which ends it only once
+    #[serde(skip)]
+    timed_out: bool,
+}
+
+/// Whether blocks can be started in any order (apart from their own `requires`), or only one
+/// after another, each one unlocked by completing (or practicing) the one presented before it.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unlock {
+    #[default]
+    Free,
+    Sequential,
+}
+
+/// How the blocks are arranged for a session: as listed, shuffled (from the session seed), or
+/// in a row of a balanced Latin square picked by the counterbalance index of the subject.
+/// Practice blocks stay in place.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockSequence {
+    #[default]
+    Listed,
+    Shuffle,
+    LatinSquare,
+}
+
+/// Final screen of the session, shown once every block (other than practice blocks) is
+/// complete. The completion code is derived from the session ID, e.g. for the subject to enter
+/// on a recruitment platform.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Debrief {
+    #[serde(default="default::debrief_message")]
+    message: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    completion_code: bool,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+}
+
+impl Default for Debrief {
+    fn default() -> Self {
+        Debrief {
+            message: default::debrief_message(),
+            completion_code: false,
+            code: None,
+        }
+    }
+}
+
+mod default {
+    pub fn debrief_message() -> String {
+        "The session is complete. Thank you!".to_string()
+    }
+}
+
+/// Settings given on the command line, which take precedence over the task file.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Directory that the session output directory is created in, instead of `output`
+    pub output_dir: Option<PathBuf>,
+    /// Subject ID, filled in on the session form (or used directly if there is none)
+    pub subject: Option<String>,
+    /// Block (by number or title) started as soon as the session begins, skipping the start
+    /// screen; the task exits when it ends
+    pub block: Option<String>,
+    /// Random seed of the session, instead of the one in the task file
+    pub seed: Option<u64>,
+    /// Row of the Latin square of block orders, instead of the one derived from the subject ID
+    pub counterbalance: Option<usize>,
+    pub fullscreen: bool,
+    /// Settings that take precedence over the task file, as `(key, value)` pairs applied in
+    /// order: `fullscreen`, `output_dir`, or a configuration setting (see `Config::set`)
+    pub overrides: Vec<(String, String)>,
+    /// Settings from the environment, applied before `overrides`; unlike those, keys that are
+    /// not settings of the task are skipped with a warning
+    pub env_overrides: Vec<(String, String)>,
+    /// Recorded session whose inputs are fed back instead of live ones
+    pub replay: Option<Replay>,
+    /// Loads and verifies the task without writing anything: the session output directory is
+    /// not created, and the subject ID is neither pseudonymized nor added to the linkage file
+    pub dry_run: bool,
 }
 
 #[derive(Debug, Clone)]
 enum State {
+    Consent,
     Startup {
         handles: [button::State; 2]
     },
     Configure {
         config: Config,
     },
+    Identify {
+        session: Session,
+    },
     Selection {
         handles: [button::State; 64],
     },
+    Barrier {
+        block: usize,
+    },
+    Break {
+        remaining: u32,
+    },
     Starting {
         wait_for: u16,
     },
     Started,
+    Complete,
+    Upload,
+    Error {
+        message: String,
+        handles: [button::State; 2],
+    },
 }
 
 impl Default for State {
@@ -69,40 +219,136 @@ impl Default for State {
 }
 
 impl Task {
-    pub fn new(task_dir: PathBuf) -> Result<Self, String> {
+    pub fn new(task_dir: PathBuf) -> Result<Self, Error> {
+        Self::with_options(task_dir, Options::default())
+    }
+
+    pub fn with_options(task_dir: PathBuf, mut options: Options) -> Result<Self, Error> {
         let file = task_dir.join("task.yml");
         let file = File::open(&file)
-            .or(Err(format!("Failed to open YAML file: {:?}", file)))?;
+            .map_err(|e| Error::io(format!("Failed to open YAML file: {:?}", file), e))?;
         let mut task: Task = serde_yaml::from_reader(file)
-            .or_else(|e| Err(format!(
-                "Failed to read YAML file at line {}: {}",
-                e.location().unwrap().line(), e)))?;
+            .map_err(|e| Error::Yaml(match e.location() {
+                Some(location) => format!("Failed to read YAML file at line {}: {}", location.line(), e),
+                None => format!("Failed to read YAML file: {}", e),
+            }))?;
 
         if task.description.starts_with("<") {
             let file = resource(&task_dir, &task.description[1..].trim())?;
             let mut file = File::open(file)
-                .or(Err("Failed to open task description file".to_string()))?;
+                .map_err(|e| Error::io("Failed to open task description file".to_string(), e))?;
             task.description.clear();
             file.read_to_string(&mut task.description)
-                .or(Err("Failed to read task description file".to_string()))?;
+                .map_err(|e| Error::io("Failed to read task description file".to_string(), e))?;
+        }
+
+        if task.debrief.message.starts_with("<") {
+            let file = resource(&task_dir, task.debrief.message[1..].trim())?;
+            task.debrief.message = std::fs::read_to_string(&file)
+                .map_err(|e| Error::io(format!("Failed to read debrief file {:?}: {}", file, e), e))?;
+        }
+
+        let env_overrides = options.env_overrides.iter().map(|setting| (setting, true));
+        for ((key, value), from_env) in env_overrides.chain(options.overrides.iter().map(|setting| (setting, false))) {
+            let known = matches!(key.as_str(), "fullscreen" | "output_dir" | "remote_token" | "upload_token"
+                | "pseudonym_salt" | "linkage_passphrase") || task.configuration.is_setting(key);
+            if from_env && !known {
+                println!("Warning: Ignored TASK_RUNNER_{}; `{}` is not a setting of the task", key.to_uppercase(), key);
+                continue;
+            }
+            match key.as_str() {
+                "fullscreen" => {
+                    options.fullscreen = value.trim().parse()
+                        .map_err(|_| Error::Invalid(format!("Invalid value for `fullscreen`: {} (use true or false)", value)))?;
+                }
+                "output_dir" => options.output_dir = Some(PathBuf::from(value)),
+                "remote_token" => task.global.set_remote_token(value),
+                "upload_token" => match &mut task.upload {
+                    Some(upload) => upload.set_token(value),
+                    None => println!("Warning: Ignored upload token; the task has no `upload` section"),
+                },
+                "pseudonym_salt" | "linkage_passphrase" => match &mut task.pseudonymize {
+                    Some(pseudonymize) if key == "pseudonym_salt" => pseudonymize.set_salt(value),
+                    Some(pseudonymize) => pseudonymize.set_passphrase(value),
+                    None => println!("Warning: Ignored `{}`; the task has no `pseudonymize` section", key),
+                },
+                _ => task.configuration.set(key, value).map_err(Error::Invalid)?,
+            }
         }
 
         let name = format!("session-{}", timestamp());
-        task.log_dir = task_dir.join("output")
+        let output_dir = options.output_dir.clone().unwrap_or_else(|| task_dir.join("output"));
+        task.log_dir = output_dir
             .join(name).to_str().unwrap().to_string();
-        std::fs::create_dir_all(&task.log_dir)
-            .or(Err("Failed to create output directory for task".to_string()))?;
+        if !options.dry_run {
+            std::fs::create_dir_all(&task.log_dir)
+                .map_err(|e| Error::io("Failed to create output directory for task".to_string(), e))?;
+        }
+        task.events.set_dir(&task.log_dir);
 
+        if let Some(consent) = &mut task.consent {
+            consent.init(&task_dir)?;
+            task.state = State::Consent;
+        }
+        task.configuration.init().map_err(Error::Invalid)?;
+        if let Some(upload) = &mut task.upload {
+            upload.init()?;
+        }
+        if let Some(rich_text) = task.global.rich_text() {
+            rich_text.load_fonts(&task_dir).map_err(Error::MissingResource)?;
+        }
+        if let Some(session) = &mut task.session {
+            session.init().map_err(Error::Invalid)?;
+        }
+        if let Some(pseudonymize) = &mut task.pseudonymize {
+            pseudonymize.init(&task_dir)?;
+        }
+        if let Some(seed) = options.seed.or(options.replay.as_ref().map(Replay::seed)) {
+            task.global.set_seed(seed);
+        }
+        task.global.init_sampler();
         for (i, block) in task.blocks.iter_mut().enumerate() {
-            block.init(i+1, &task_dir)?;
+            block.init(i+1, &task_dir, &task.configuration, &task.global)?;
         }
         task.progress = vec![false; task.blocks.len()];
+        task.practiced = vec![false; task.blocks.len()];
+        for block in &task.blocks {
+            for &i in block.requires() {
+                if i == 0 || i > task.blocks.len() || i == block.id() {
+                    return Err(Error::Invalid(format!("Invalid block prerequisite for block {}: {}", block.id(), i)));
+                }
+            }
+        }
+
+        if let Some(block) = &options.block {
+            task.next_block = Some(task.find_block(block)?);
+        }
+        task.options = options;
+        if let Some(subject) = task.options.subject.clone() {
+            if subject.is_empty() || !subject.chars().all(|c| c.is_ascii_alphanumeric() || "_-".contains(c)) {
+                return Err(Error::Invalid(format!("Only alphanumeric (a-z|A-Z|0-9), '-', and '_' are allowed in subject IDs: {:?}", subject)));
+            }
+            match &mut task.session {
+                // The form shows the ID as given, which is pseudonymized once it is submitted
+                Some(session) => session.set_subject(&subject),
+                None if task.options.dry_run => (),
+                None => {
+                    let subject = task.pseudonymize(&subject)?;
+                    task.options.subject = Some(subject.clone());
+                    task.tag_log_dir(&subject);
+                }
+            }
+        }
 
         task.global.set_dir(task_dir.to_str().unwrap());
+        task.started = Some(Instant::now());
+        task.check_disk_space();
         Ok(task)
     }
 
     pub fn update(&mut self, message: Message) -> Command<Message> {
+        let locked: Vec<bool> = (1..=self.blocks.len()).map(|i| self.is_locked(i)).collect();
+        let refuses_start = self.refuses_start();
         let state = &mut self.state;
         let is_active = self.dispatcher.is_some()
             && self.dispatcher.as_ref().unwrap().is_active();
@@ -126,22 +372,36 @@ impl Task {
             }
             Message::UIEvent(code, value) => {
                 match (state, code, value.clone()) {
+                    (State::Consent, 0x01, _) => {
+                        self.decide_consent(false);
+                        self.write_session_logs();
+                        self.exit = Some(2);
+                        Command::none()
+                    }
+                    (State::Consent, 0x02, _) if self.consent.as_ref().is_some_and(Consent::is_agreed) => {
+                        self.decide_consent(true);
+                        self.state = State::default();
+                        self.launch()
+                    }
+                    (State::Consent, _, _) => {
+                        match self.consent.as_mut().unwrap().update(code, value) {
+                            Ok(()) => Command::none(),
+                            Err(e) => self.fail(e),
+                        }
+                    }
                     (State::Startup { .. }, 0x01, _) => {
                         self.state = State::Configure {
                             config: self.configuration.clone(),
                         };
                         Command::none()
                     }
-                    (State::Startup { .. }, 0x02, _) => {
-                        self.state = State::Selection {
-                            handles: [button::State::new(); 64],
-                        };
-                        self.global.set_config(&self.configuration);
-                        let file = File::create(Path::new(&self.log_dir).join("task.log")).unwrap();
-                        serde_yaml::to_writer(file, &self)
-                            .expect("Failed to write task configuration log to file");
+                    (State::Startup { .. }, 0x02, _) |
+                    (State::Configure { .. }, 0x03, _) if refuses_start => {
                         Command::none()
                     }
+                    (State::Startup { .. }, 0x02, _) => {
+                        self.identify()
+                    }
                     (State::Configure { .. }, 0x01, _) => {
                         self.state = State::Startup {
                             handles: [button::State::new(); 2]
@@ -154,26 +414,78 @@ impl Task {
                     }
                     (State::Configure { config, .. }, 0x03, _) => {
                         self.configuration = config.clone();
-                        self.global.set_config(&self.configuration);
-                        self.state = State::Selection {
-                            handles: [button::State::new(); 64],
+                        self.identify()
+                    }
+                    (State::Configure { config, .. }, _, _) => {
+                        match config.update(code, value) {
+                            Ok(()) => Command::none(),
+                            Err(e) => self.fail(e),
+                        }
+                    }
+                    (State::Identify { .. }, 0x01, _) => {
+                        self.state = State::Startup {
+                            handles: [button::State::new(); 2]
                         };
-                        let file = File::create(Path::new(&self.log_dir).join("task.log")).unwrap();
-                        serde_yaml::to_writer(file, &self)
-                            .expect("Failed to write task configuration log to file");
                         Command::none()
                     }
-                    (State::Configure { config, .. }, _, _) => {
-                        config.update(code, value);
+                    (State::Identify { session }, 0x03, _) if session.is_complete() => {
+                        let mut session = session.clone();
+                        match self.pseudonymize(session.subject()) {
+                            Ok(subject) => session.set_subject(&subject),
+                            Err(e) => return self.fail(e.to_string()),
+                        }
+                        self.tag_log_dir(session.subject());
+                        self.session = Some(session);
+                        self.begin_session();
+                        self.autostart()
+                    }
+                    (State::Identify { .. }, 0x03, _) => {
+                        Command::none()
+                    }
+                    (State::Identify { session }, _, _) => {
+                        match session.update(code, value) {
+                            Ok(()) => Command::none(),
+                            Err(e) => self.fail(e),
+                        }
+                    }
+                    (State::Error { .. }, 0x01, _) => {
+                        self.write_session_logs();
+                        self.exit = Some(1);
+                        Command::none()
+                    }
+                    (State::Error { .. }, 0x02, _) => {
+                        self.state = if self.in_session {
+                            State::Selection { handles: [button::State::new(); 64] }
+                        } else {
+                            State::default()
+                        };
+                        Command::none()
+                    }
+                    (State::Selection { .. }, i, Value::Null) if i >= 1 && locked[i as usize - 1] => {
+                        self.events.push(format!("{}  LOCKED  {}", self.now(), i));
+                        Command::none()
+                    }
+                    (State::Selection { .. }, i, Value::Null) if self.global.barrier().is_some() => {
+                        self.state = State::Barrier {
+                            block: i as usize,
+                        };
                         Command::none()
                     }
                     (State::Selection { .. }, i, Value::Null) => {
-                        self.state = State::Starting {
-                            wait_for: 3000
+                        self.countdown(i)
+                    }
+                    // The break between blocks counts down, then the next block starts
+                    (State::Break { .. }, _, Value::Integer(t)) if t <= 0 => {
+                        self.state = State::Selection {
+                            handles: [button::State::new(); 64],
                         };
+                        self.autostart()
+                    }
+                    (State::Break { remaining }, _, Value::Integer(t)) => {
+                        *remaining = t as u32;
                         Command::perform(async {
                             std::thread::sleep(Duration::from_millis(100));
-                        }, move |()| Message::UIEvent(i, Value::Integer(2900)))
+                        }, move |()| Message::UIEvent(0, Value::Integer(t - 100)))
                     }
                     (State::Starting { .. }, i, Value::Integer(0)) => {
                         self.state = State::Started;
@@ -185,6 +497,22 @@ impl Task {
                             std::thread::sleep(Duration::from_millis(100));
                         }, move |()| Message::UIEvent(i, Value::Integer(t - 100)))
                     }
+                    (State::Complete, 0x01, _) if self.upload.is_some() => {
+                        self.upload.as_mut().unwrap().confirm();
+                        self.state = State::Upload;
+                        Command::none()
+                    }
+                    (State::Upload, _, _) if self.upload.as_ref().unwrap().is_busy() => {
+                        Command::none()
+                    }
+                    (State::Upload, 0x01, _) => {
+                        self.state = State::Complete;
+                        Command::none()
+                    }
+                    (State::Upload, 0x02, _) => {
+                        self.events.push(format!("{}  UPLOAD  {}", self.now(), self.upload.as_ref().unwrap().url()));
+                        self.upload.as_mut().unwrap().start(&self.log_dir)
+                    }
                     (State::Started { .. }, _, _) if is_active => {
                         self.dispatcher.as_mut().unwrap()
                             .update(Message::UIEvent(code, value), &self.global)
@@ -192,24 +520,98 @@ impl Task {
                     _ => Command::none(),
                 }
             }
+            // The confirmation dialog hides the block, so key presses are not passed on to it
+            Message::KeyPress(..) |
+            Message::KeyRelease(..) if self.abort_dialog.is_some() => {
+                Command::none()
+            }
+            Message::KeyPress(key_code) if matches!(state, State::Barrier { .. }) => {
+                let key = format!("{:?}", key_code);
+                match state {
+                    State::Barrier { block } if key.eq_ignore_ascii_case(self.global.barrier().unwrap().key()) => {
+                        let block = *block;
+                        self.events.push(format!("{}  BARRIER_RELEASE  {}", self.now(), block));
+                        self.countdown(block as u16)
+                    }
+                    _ => Command::none(),
+                }
+            }
             Message::Code(..) |
             Message::Value(..) |
             Message::KeyPress(..) |
-            Message::ActionComplete(..) => {
+            Message::KeyRelease(..) |
+            Message::CursorMoved(..) |
+            Message::MouseSample |
+            Message::ActionComplete(..) |
+            Message::Timeout(..) |
+            Message::Playback(..) |
+            Message::Probe(..) |
+            Message::ProbeComplete |
+            Message::Preloaded(..) => {
                 self.dispatcher.as_mut().unwrap().update(message, &self.global)
             }
+            Message::Remote(message) => {
+                match (*message, state) {
+                    (Message::UIEvent(i, Value::Null), State::Selection { .. }) if (1..=locked.len()).contains(&(i as usize)) => {
+                        self.update(Message::UIEvent(i, Value::Null))
+                    }
+                    // The operator does not need to confirm aborting a block
+                    (Message::Interrupt, State::Started) => {
+                        self.abort_block()
+                    }
+                    (Message::Interrupt, _) => {
+                        self.update(Message::Interrupt)
+                    }
+                    (message, _) => {
+                        println!("Warning: Ignored remote command that does not apply now: {:?}", message);
+                        Command::none()
+                    }
+                }
+            }
+            Message::Tick => {
+                self.check_disk_space();
+                match self.session_remaining() {
+                    Some(remaining) if remaining.is_zero() => self.timeout_session(),
+                    _ => Command::none(),
+                }
+            }
+            Message::Error(e) => {
+                self.fail(e)
+            }
+            Message::Uploaded(result) => {
+                match &result {
+                    Ok(n) => self.events.push(format!("{}  UPLOAD_COMPLETE  {}", self.now(), n)),
+                    Err(e) => self.events.push(format!("{}  UPLOAD_FAILED  {}", self.now(), e)),
+                }
+                self.events.write_summary();
+                self.upload.as_mut().unwrap().finish(result);
+                Command::none()
+            }
             Message::Interrupt => {
                 match state {
+                    State::Consent |
                     State::Startup { .. } |
-                    State::Selection { .. } => {
+                    State::Selection { .. } |
+                    State::Complete |
+                    State::Error { .. } => {
                         Command::none()
                     },
-                    State::Configure { .. } => {
+                    State::Upload if self.upload.as_ref().unwrap().is_busy() => {
+                        Command::none()
+                    }
+                    State::Upload => {
+                        self.state = State::Complete;
+                        Command::none()
+                    }
+                    State::Configure { .. } |
+                    State::Identify { .. } => {
                         self.state = State::Startup {
                             handles: [button::State::new(); 2]
                         };
                         Command::none()
                     }
+                    State::Barrier { .. } |
+                    State::Break { .. } |
                     State::Starting { .. } => {
                         self.state = State::Selection {
                             handles: [button::State::new(); 64],
@@ -217,45 +619,557 @@ impl Task {
                         Command::none()
                     }
                     State::Started => {
-                        if let Some(block) = self.active_block.take() {
-                            self.events.push(format!("{}  INTERRUPT  {}", timestamp(), block));
-                            let file = File::create(Path::new(&self.log_dir).join("events.log")).unwrap();
-                            serde_yaml::to_writer(file, &self.events)
-                                .expect("Failed to write interrupted block event log to file");
-
-                            self.state = State::Selection {
-                                handles: [button::State::new(); 64],
-                            };
-                            self.dispatcher.as_mut().unwrap().update(message, &self.global)
-                        } else {
-                            Command::none()
+                        if self.active_block.is_some() && self.abort_dialog.is_none() {
+                            self.abort_dialog = Some([button::State::new(); 2]);
                         }
+                        Command::none()
                     }
                 }
             }
+            Message::ConfirmAbort(confirmed) => {
+                self.abort_dialog = None;
+                if confirmed {
+                    self.abort_block()
+                } else {
+                    Command::none()
+                }
+            }
             Message::BlockComplete => {
+                self.abort_dialog = None;
                 self.state = State::Selection {
                     handles: [button::State::new(); 64],
                 };
                 if let Some(block) = self.active_block.take() {
-                    self.events.push(format!("{}  COMPLETE  {}", timestamp(), block));
-                    let file = File::create(Path::new(&self.log_dir).join("events.log")).unwrap();
-                    serde_yaml::to_writer(file, &self.events)
-                        .expect("Failed to write completed block event log to file");
+                    self.events.push(format!("{}  COMPLETE  {}{}", self.now(), block, self.practice_tag(block)));
+                    self.events.write_summary();
                 }
-                self.progress[self.dispatcher.as_ref().unwrap().block_id()-1] = true;
-                self.dispatcher.as_mut().unwrap().update(message, &self.global)
+                self.mark_complete(self.dispatcher.as_ref().unwrap().block_id());
+                let command = self.dispatcher.as_mut().unwrap().update(message, &self.global);
+                self.write_session_logs();
+                match self.advance() {
+                    Some(next) => Command::batch(vec![command, next]),
+                    None => command,
+                }
+            }
+            message => {
+                self.fail(format!("Asked to relay invalid message type: {:?}", message))
+            }
+        }
+    }
+
+    /// Stops the running block (keeping what it logged so far), and shows an error that the
+    /// experimenter can exit on or return to block selection from.
+    fn fail(&mut self, error: String) -> Command<Message> {
+        println!("Error: {}", error);
+        self.abort_dialog = None;
+        match self.active_block.take() {
+            Some(block) => {
+                self.events.push(format!("{}  ERROR  {}  {}", self.now(), block, error));
+                self.dispatcher.as_mut().unwrap().update(Message::Interrupt, &self.global);
+            }
+            None => self.events.push(format!("{}  ERROR  {}", self.now(), error)),
+        }
+        self.events.write_summary();
+        self.write_session_logs();
+        self.state = State::Error {
+            message: error,
+            handles: [button::State::new(); 2],
+        };
+        Command::none()
+    }
+
+    /// Skips to the session form (or the block selection if there is none) when a block is
+    /// given on the command line, and starts it once the session begins. With a consent form,
+    /// this waits until the subject agrees.
+    pub fn launch(&mut self) -> Command<Message> {
+        let block = match self.next_block {
+            Some(block) if !matches!(self.state, State::Consent) => block,
+            _ => return Command::none(),
+        };
+        if self.refuses_start() {
+            println!("Warning: Not starting block {} automatically; disk space is low", block);
+            return Command::none();
+        }
+        let command = self.identify();
+        match &self.state {
+            State::Identify { session } if session.is_complete() => {
+                self.update(Message::UIEvent(0x03, Value::Null))
             }
-            _ => {
-                panic!("Asked to relay invalid message type");
+            _ => command,
+        }
+    }
+
+    /// Starts the block given on the command line or, with `auto_advance`, the first block
+    /// that is neither complete nor locked.
+    fn autostart(&mut self) -> Command<Message> {
+        let block = self.next_block.take()
+            .or_else(|| self.next_incomplete().filter(|_| self.global.auto_advance()));
+        match block {
+            Some(block) => self.update(Message::UIEvent(block as u16, Value::Null)),
+            None => Command::none(),
+        }
+    }
+
+    /// Moves on after a block completes: to the next block with `auto_advance` (or to the end
+    /// of the session after the last one), and out of the task if it was launched into a block.
+    fn advance(&mut self) -> Option<Command<Message>> {
+        let launched = self.options.block.is_some();
+        if self.is_finished() {
+            self.finish_session();
+        } else if self.global.auto_advance() {
+            if self.next_incomplete().is_some() {
+                return Some(match self.global.block_break() {
+                    Some(pause) => self.take_break(pause.duration()),
+                    None => self.autostart(),
+                });
             }
+            self.state = State::Complete;
         }
+        if launched {
+            self.exit = Some(0);
+        }
+        None
+    }
+
+    /// Whether every block (other than practice blocks) is complete.
+    fn is_finished(&self) -> bool {
+        let mut blocks = self.blocks.iter().filter(|block| !block.is_practice()).peekable();
+        blocks.peek().is_some() && blocks.all(|block| self.progress[block.id()-1])
+    }
+
+    /// Ends the session on the debrief screen, with the completion code (if in use) logged.
+    fn finish_session(&mut self) {
+        if self.debrief.completion_code {
+            let session = Path::new(&self.log_dir).file_name().unwrap().to_str().unwrap();
+            self.debrief.code = Some(format!("{:08X}", fnv1a(session) as u32));
+        }
+        let code = self.debrief.code.as_ref().map(|code| format!("  {}", code)).unwrap_or_default();
+        self.events.push(format!("{}  SESSION_COMPLETE{}", self.now(), code));
+        self.events.write_summary();
+        self.write_task_log();
+        self.state = State::Complete;
+    }
+
+    fn take_break(&mut self, duration: Duration) -> Command<Message> {
+        let ms = duration.as_millis() as u32;
+        self.state = State::Break {
+            remaining: ms,
+        };
+        self.events.push(format!("{}  BREAK  {}", self.now(), ms));
+        let t = ms as i32;
+        Command::perform(async {}, move |()| Message::UIEvent(0, Value::Integer(t)))
+    }
+
+    fn next_incomplete(&self) -> Option<usize> {
+        self.sequence().into_iter().find(|&i| !self.progress[i-1] && !self.practiced[i-1] && !self.is_locked(i))
+    }
+
+    /// Marks the session events of practice blocks.
+    fn practice_tag(&self, block: usize) -> &'static str {
+        if self.blocks[block-1].is_practice() { "  PRACTICE" } else { "" }
+    }
+
+    /// Records a block as complete, or as practiced for a practice block, which can be run
+    /// again and is never shown as complete (but does unlock the blocks that require it).
+    fn mark_complete(&mut self, block: usize) {
+        if self.blocks[block-1].is_practice() {
+            self.practiced[block-1] = true;
+        } else {
+            self.progress[block-1] = true;
+        }
+    }
+
+    /// Finds a block by its number, or else by its title (ignoring case).
+    fn find_block(&self, block: &str) -> Result<usize, Error> {
+        match block.parse::<usize>() {
+            Ok(i) if i >= 1 && i <= self.blocks.len() => Ok(i),
+            Ok(i) => Err(Error::Invalid(format!("Invalid block number: {}", i))),
+            Err(_) => self.blocks.iter()
+                .find(|b| b.title().eq_ignore_ascii_case(block))
+                .map(Block::id)
+                .ok_or_else(|| Error::Invalid(format!("No block titled {:?}", block))),
+        }
+    }
+
+    /// Block given on the command line, if any.
+    pub fn start_block(&self) -> Option<usize> {
+        self.next_block
+    }
+
+    /// Numbers of the blocks in the order they are presented in the session, e.g. to simulate
+    /// them all.
+    pub fn block_sequence(&mut self) -> Vec<usize> {
+        self.arrange_blocks();
+        self.sequence()
+    }
+
+    pub fn replay(&self) -> Option<&Replay> {
+        self.options.replay.as_ref()
+    }
+
+    /// Whether a message is an input of the subject or the experimenter. The countdown before a
+    /// block and the break between blocks are driven by the task itself, so they are not inputs.
+    pub fn is_input(&self, message: &Message) -> bool {
+        replay::is_input(message) && !matches!(self.state, State::Starting { .. } | State::Break { .. })
+    }
+
+    /// Appends an input to the recording of the session.
+    pub fn record(&mut self, message: &Message) {
+        if !self.is_input(message) {
+            return;
+        }
+        let header = (self.title.as_str(), self.version.as_str(), self.global.seed());
+        // The subject ID is recorded as typed only if it is not pseudonymized
+        if let (State::Identify { .. }, Message::UIEvent(0x10, Value::String(subject)), Some(pseudonymize), None) =
+            (&self.state, message, &self.pseudonymize, &self.options.replay) {
+            let message = Message::UIEvent(0x10, Value::String(pseudonymize.pseudonym(subject.trim())));
+            self.recorder.record(&message, self.started.unwrap(), &self.log_dir, header);
+            return;
+        }
+        self.recorder.record(message, self.started.unwrap(), &self.log_dir, header);
+    }
+
+    pub fn fullscreen(&self) -> bool {
+        self.options.fullscreen
+    }
+
+    /// Exit status of the task once it should exit: 0 when the block it was launched into
+    /// completed, 1 after an error, and 2 when the block was interrupted or the session
+    /// timed out.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Interrupts the running block and returns to block selection.
+    fn abort_block(&mut self) -> Command<Message> {
+        self.abort_dialog = None;
+        if let Some(block) = self.active_block.take() {
+            self.events.push(format!("{}  INTERRUPT  {}", self.now(), block));
+            self.events.write_summary();
+
+            self.state = State::Selection {
+                handles: [button::State::new(); 64],
+            };
+            let command = self.dispatcher.as_mut().unwrap().update(Message::Interrupt, &self.global);
+            self.write_session_logs();
+            if self.options.block.is_some() {
+                self.exit = Some(2);
+            }
+            command
+        } else {
+            Command::none()
+        }
+    }
+
+    /// Writes the dependency graph of each block to `block-<id>.dot` in `dir`, and returns the
+    /// files written.
+    pub fn export_graphs(&self, dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        std::fs::create_dir_all(dir)?;
+        let mut files = vec![];
+        for block in &self.blocks {
+            let file = dir.join(format!("block-{}.dot", block.id()));
+            std::fs::write(&file, block.to_dot())?;
+            files.push(file);
+        }
+        Ok(files)
+    }
+
+    /// Runs a block headlessly with scripted (or, with `auto_respond`, random) responses,
+    /// writing the same output files as a regular run. Returns a trace of the simulated events.
+    pub fn simulate(&mut self, block: usize, timeline: &Timeline, auto_respond: bool) -> Result<Vec<String>, Error> {
+        if block == 0 || block > self.blocks.len() {
+            return Err(Error::Invalid(format!("Invalid block number: {}", block)));
+        }
+        self.global.set_config(&self.configuration);
+        self.write_task_log();
+        self.record_environment();
+
+        self.events.push(format!("{}  START  {}{}", self.now(), block, self.practice_tag(block)));
+        let trace = simulate::run(
+            self.blocks[block-1].clone().with_log_dir(&self.log_dir),
+            &self.global,
+            timeline,
+            auto_respond)?;
+        self.events.push(format!("{}  COMPLETE  {}{}", self.now(), block, self.practice_tag(block)));
+        self.mark_complete(block);
+        if self.is_finished() {
+            self.finish_session();
+        }
+
+        self.events.write_summary();
+        self.write_session_logs();
+        Ok(trace)
+    }
+
+    pub fn log_dir(&self) -> &str {
+        &self.log_dir
+    }
+
+    /// When the session started, which inputs are timed from.
+    pub fn started(&self) -> Option<Instant> {
+        self.started
+    }
+
+    pub fn session_remaining(&self) -> Option<Duration> {
+        let elapsed = self.started?.elapsed();
+        self.global.max_session()
+            .map(|max| max.saturating_sub(elapsed))
+    }
+
+    pub fn timeout_session(&mut self) -> Command<Message> {
+        if self.timed_out || matches!(self.state, State::Complete | State::Upload) {
+            return Command::none();
+        }
+        self.timed_out = true;
+        self.abort_dialog = None;
+        if let Some(block) = self.active_block.take() {
+            self.events.push(format!("{}  INTERRUPT  {}", self.now(), block));
+            self.dispatcher.as_mut().unwrap().update(Message::Interrupt, &self.global);
+        }
+        self.events.push(format!("{}  SESSION_TIMEOUT", self.now()));
+        self.events.write_summary();
+        self.write_session_logs();
+
+        self.state = State::Complete;
+        if self.options.block.is_some() {
+            self.exit = Some(2);
+        }
+        Command::none()
+    }
+
+    /// Checks the free space in the output directory against the configured threshold, and
+    /// warns the experimenter (once per low-space episode) if it falls below.
+    fn check_disk_space(&mut self) {
+        let threshold = match self.global.disk_space() {
+            Some(disk_space) => disk_space.warn_below(),
+            None => return,
+        };
+        let low = free_space(&self.log_dir)
+            .map(|bytes| bytes / (1024 * 1024))
+            .filter(|&mb| mb < threshold);
+        if let (Some(mb), None) = (low, self.low_disk) {
+            println!("Warning: only {} MB of free disk space left for session output", mb);
+            self.events.push(format!("{}  LOW_DISK_SPACE  {}MB", self.now(), mb));
+        }
+        self.low_disk = low;
+    }
+
+    /// Records whether the subject agreed to the consent form, in the session events and (with
+    /// the time of the decision) in the task log.
+    fn decide_consent(&mut self, agreed: bool) {
+        self.consent.as_mut().unwrap().decide(agreed);
+        let decision = if agreed { "AGREE" } else { "DECLINE" };
+        self.events.push(format!("{}  CONSENT  {}", self.now(), decision));
+        if !agreed {
+            // The session never begins, so these logs would not be written otherwise
+            self.events.write_summary();
+            self.write_task_log();
+        }
+    }
+
+    /// Asks for the session metadata if the task defines any, otherwise starts the session.
+    fn identify(&mut self) -> Command<Message> {
+        match &self.session {
+            Some(session) => {
+                self.state = State::Identify {
+                    session: session.clone(),
+                };
+                Command::none()
+            }
+            None => {
+                self.begin_session();
+                self.autostart()
+            }
+        }
+    }
+
+    fn begin_session(&mut self) {
+        self.in_session = true;
+        self.global.set_config(&self.configuration);
+        self.arrange_blocks();
+        self.state = State::Selection {
+            handles: [button::State::new(); 64],
+        };
+        self.write_task_log();
+        self.record_environment();
+    }
+
+    /// Writes a snapshot of the machine and setup of the session to `session.json`.
+    fn record_environment(&self) {
+        Environment::capture(Path::new(self.global.dir()), &self.global, &self.configuration, self.options.fullscreen)
+            .write(&self.log_dir);
+    }
+
+    /// Rearranges the blocks for the session according to `block_order`.
+    fn arrange_blocks(&mut self) {
+        let movable: Vec<usize> = self.blocks.iter()
+            .filter(|block| !block.is_practice())
+            .map(Block::id)
+            .collect();
+        let arranged = match self.block_order {
+            BlockSequence::Listed => return,
+            BlockSequence::Shuffle => {
+                let mut rng = StdRng::seed_from_u64(self.global.seed() ^ 0xB10C_0DE5);
+                let mut arranged = movable.clone();
+                arranged.shuffle(&mut rng);
+                arranged
+            }
+            BlockSequence::LatinSquare => {
+                let subject = self.session.as_ref().map(|s| s.subject().to_string())
+                    .or_else(|| self.options.subject.clone());
+                let index = match (self.options.counterbalance, subject) {
+                    (Some(index), _) => index,
+                    (None, Some(subject)) => counterbalance_index(&subject),
+                    (None, None) => {
+                        println!("Warning: No subject ID or --counterbalance to pick the block order; using the first one");
+                        0
+                    }
+                };
+                self.counterbalance = Some(index);
+                latin_square_row(movable.len(), index).into_iter().map(|i| movable[i]).collect()
+            }
+        };
+        let mut arranged = arranged.into_iter();
+        self.sequence = self.blocks.iter()
+            .map(|block| if block.is_practice() { block.id() } else { arranged.next().unwrap() })
+            .collect();
+        let order = self.sequence.iter().map(usize::to_string).collect::<Vec<_>>().join(" ");
+        self.events.push(format!("{}  BLOCK_ORDER  {}", self.now(), order));
+    }
+
+    /// Numbers of the blocks in the order they are presented.
+    fn sequence(&self) -> Vec<usize> {
+        if self.sequence.is_empty() {
+            (1..=self.blocks.len()).collect()
+        } else {
+            self.sequence.clone()
+        }
+    }
+
+    /// The subject ID to use in the session output: its pseudonym if the task pseudonymizes
+    /// subject IDs, which is paired with the ID in the linkage file (if any). The block order
+    /// of a Latin square still follows the entered ID. A replayed session already has the
+    /// pseudonym as its subject ID.
+    fn pseudonymize(&mut self, subject: &str) -> Result<String, Error> {
+        let pseudonymize = match &self.pseudonymize {
+            Some(pseudonymize) if self.options.replay.is_none() => pseudonymize,
+            _ => return Ok(subject.to_string()),
+        };
+        let pseudonym = pseudonymize.pseudonym(subject);
+        pseudonymize.link(subject, &pseudonym)?;
+        if self.block_order == BlockSequence::LatinSquare {
+            self.options.counterbalance.get_or_insert(counterbalance_index(subject));
+        }
+        Ok(pseudonym)
+    }
+
+    /// Adds the subject ID to the name of the (still empty) session output directory.
+    fn tag_log_dir(&mut self, subject: &str) {
+        let path = Path::new(&self.log_dir);
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let tagged = path.with_file_name(name.replacen("session-", &format!("session-{}-", subject), 1));
+        self.recorder.close();
+        match std::fs::rename(path, &tagged) {
+            Ok(()) => {
+                self.log_dir = tagged.to_str().unwrap().to_string();
+                self.events.set_dir(&self.log_dir);
+            }
+            Err(e) => println!("Warning: Failed to add subject ID to output directory name: {}", e),
+        }
+    }
+
+    /// Timestamp of a session event, with the time since the task was loaded.
+    fn now(&self) -> String {
+        event_time(self.started.unwrap())
+    }
+
+    /// Publishes the current state of the task for the operator console and remote monitors.
+    pub fn publish_status(&self) {
+        let state = match &self.state {
+            State::Consent => "consent",
+            State::Startup { .. } => "startup",
+            State::Configure { .. } => "configuration",
+            State::Identify { .. } => "session",
+            State::Selection { .. } => "block selection",
+            State::Barrier { .. } => "barrier",
+            State::Break { .. } => "break",
+            State::Starting { .. } => "countdown",
+            State::Started if self.is_active() => "running",
+            State::Started => "loading",
+            State::Complete => "complete",
+            State::Upload => "upload",
+            State::Error { .. } => "error",
+        };
+        let dispatcher = self.dispatcher.as_ref();
+        let block = dispatcher.and_then(Dispatcher::block);
+        let (running, upcoming) = dispatcher.map(Dispatcher::schedule).unwrap_or_default();
+        console::publish(Snapshot {
+            task: self.title(),
+            state: state.to_string(),
+            session_onset: self.started,
+            block: block.map(|block| format!("{}. {}", block.id(), block.title())),
+            block_onset: block.and_then(Block::onset),
+            running,
+            completed: dispatcher.map(Dispatcher::completed).unwrap_or_default(),
+            foreground: dispatcher.and_then(Dispatcher::foreground).cloned(),
+            last_key: dispatcher.and_then(Dispatcher::last_key).cloned(),
+            progress: dispatcher.and_then(Dispatcher::progress),
+            remaining: upcoming.len(),
+            upcoming: upcoming.into_iter().take(5).collect(),
+        });
+    }
+
+    fn refuses_start(&self) -> bool {
+        self.low_disk.is_some()
+            && self.global.disk_space().is_some_and(|d| d.refuse_start())
+    }
+
+    fn write_session_logs(&mut self) {
+        self.recorder.flush();
+        async_write_to_file(
+            Path::new(&self.log_dir).join("randomization.log").to_str().unwrap().to_string(),
+            self.global.draws(),
+            "Failed to write randomization log to file");
+        write_manifest(&self.log_dir);
+    }
+
+    fn write_task_log(&self) {
+        let path = Path::new(&self.log_dir).join("task.log");
+        if let Err(e) = write_to_file(path.to_str().unwrap(), self) {
+            println!("Warning: Failed to write task configuration log to {:?}: {}", path, e);
+        }
+    }
+
+    fn countdown(&mut self, block: u16) -> Command<Message> {
+        self.state = State::Starting {
+            wait_for: 3000
+        };
+        Command::perform(async {
+            std::thread::sleep(Duration::from_millis(100));
+        }, move |()| Message::UIEvent(block, Value::Integer(2900)))
+    }
+
+    /// Whether a block has prerequisite blocks that are not yet complete (or practiced). With
+    /// sequential unlocking, all blocks presented before it are prerequisites.
+    pub fn is_locked(&self, block: usize) -> bool {
+        let is_done = |i: usize| self.progress[i-1] || self.practiced[i-1];
+        if self.unlock == Unlock::Sequential
+            && !self.sequence().into_iter().take_while(|&i| i != block).all(is_done) {
+            return true;
+        }
+        self.blocks[block-1].requires()
+            .iter()
+            .any(|&i| !is_done(i))
     }
 
     pub fn has_dispatcher(&self) -> bool {
         self.dispatcher.is_some()
     }
 
+    pub fn tracks_mouse(&self) -> bool {
+        self.dispatcher.as_ref().is_some_and(Dispatcher::tracks_mouse)
+    }
+
     pub fn is_active(&self) -> bool {
         self.dispatcher.is_some() && self.dispatcher.as_ref().unwrap().is_active()
     }
@@ -267,22 +1181,40 @@ impl Task {
         if self.dispatcher.as_ref().unwrap().is_active() {
             panic!("Tried to start a new block when another one is still running");
         }
-        self.global.reset_io();
+        let latency = self.global.reset_io();
         self.active_block = Some(block);
-        self.events.push(format!("{}  START  {}", timestamp(), block));
-        let file = File::create(Path::new(&self.log_dir).join("events.log")).unwrap();
-        serde_yaml::to_writer(file, &self.events)
-            .expect("Failed to write block start event to file");
+        self.events.push(format!("{}  START  {}{}", self.now(), block, self.practice_tag(block)));
+        if let Some(latency) = latency {
+            println!("Audio time to first sample: {:.2} ms", latency.as_secs_f64() * 1000.0);
+            self.events.push(format!("{}  AUDIO_WARMUP  {:.2}", self.now(), latency.as_secs_f64() * 1000.0));
+        }
         let block = self.blocks[block-1].clone().with_log_dir(&self.log_dir);
         self.dispatcher.as_mut().unwrap().init(block, &self.global)
     }
 
+    /// Background color of the window set by the running block, if any.
+    pub fn backdrop(&self) -> Option<Color> {
+        match self.state {
+            State::Started if self.abort_dialog.is_none() => self.dispatcher.as_ref()?.backdrop(),
+            _ => None,
+        }
+    }
+
     pub fn view(&mut self) -> Column<Message> {
+        let remaining = self.session_remaining();
+        let locked: Vec<bool> = (1..=self.blocks.len()).map(|i| self.is_locked(i)).collect();
+        let sequence = self.sequence();
+        let refuses_start = self.refuses_start();
+        let low_disk = self.low_disk.filter(|_| !matches!(self.state, State::Started));
         let state = &mut self.state;
         let is_active = self.dispatcher.is_some()
             && self.dispatcher.as_ref().unwrap().is_active();
 
-        match state {
+        let content = match state {
+            State::Consent => {
+                self.consent.as_mut().unwrap().view(&self.global)
+            }
+
             State::Startup { handles: [h_config, h_start] } => {
                 let e_config: Element<Message> = if self.configuration.is_static() {
                     Space::with_width(Length::Units(200))
@@ -299,14 +1231,27 @@ impl Task {
                         .into()
                 };
 
-                let e_start = button(
+                let mut e_start = button(
                     h_config,
                     "Start!",
                     self.global.text_size("LARGE"))
-                    .on_press(Message::UIEvent(0x02, Value::Null))
                     .style(style::Button::Primary)
                     .width(Length::Units(200))
                     .padding(15);
+                if !refuses_start {
+                    e_start = e_start.on_press(Message::UIEvent(0x02, Value::Null));
+                }
+
+                let size = self.global.text_size("LARGE");
+                let description: Element<Message> = if self.global.rich_text().is_some() {
+                    markup::view(&self.description, size, style::palette().text, self.global.horizontal_alignment(), &self.global)
+                        .into()
+                } else {
+                    Text::new(&self.description)
+                        .size(size)
+                        .horizontal_alignment(self.global.horizontal_alignment())
+                        .into()
+                };
 
                 Column::new()
                     .width(Length::Fill)
@@ -317,9 +1262,7 @@ impl Task {
                         .push(Text::new("Instructions")
                             .size(self.global.text_size("XLARGE"))
                             .horizontal_alignment(self.global.horizontal_alignment()))
-                        .push(Text::new(&self.description)
-                            .size(self.global.text_size("LARGE"))
-                            .horizontal_alignment(self.global.horizontal_alignment())))
+                        .push(description))
                     .push(Space::with_height(Length::Fill))
                     .push(Row::new()
                         .push(e_config)
@@ -332,41 +1275,36 @@ impl Task {
                 config.view(&self.global)
             }
 
+            State::Identify { session } => {
+                session.view(&self.global)
+            }
+
             State::Selection { handles, .. } => {
-                let elements: Vec<_> = self
-                    .blocks
-                    .iter()
-                    .enumerate()
-                    .zip(&self.progress)
+                let elements: Vec<_> = sequence
+                    .into_iter()
+                    .map(|id| id - 1)
                     .zip(handles)
-                    .map(|(((i, block), is_done), h)| {
+                    .map(|(i, h)| {
+                        let block = &self.blocks[i];
+                        let style = if locked[i] {
+                            style::Button::Inactive
+                        } else if self.progress[i] {
+                            style::Button::Done
+                        } else {
+                            style::Button::Todo
+                        };
                         button(
                             h,
                             &block.title(),
                             self.global.text_size("XLARGE"))
                             .on_press(Message::UIEvent((i + 1) as u16, Value::Null))
-                            .style(if *is_done { style::Button::Done } else { style::Button::Todo })
-                            .width(Length::Units(200))
+                            .style(style)
+                            .width(self.global.block_grid().button_width())
                             .padding(15)
+                            .into()
                     })
                     .collect();
-
-                let mut rows = Column::new()
-                    .spacing(40)
-                    .align_items(Align::Center);
-                let mut controls = Row::new()
-                    .spacing(60)
-                    .align_items(Align::Center);
-                for (i, e) in elements.into_iter().enumerate() {
-                    if i > 0 && i % 3 == 0 {
-                        rows = rows.push(controls);
-                        controls = Row::new()
-                            .spacing(60)
-                            .align_items(Align::Center);
-                    }
-                    controls = controls.push(e);
-                }
-                rows = rows.push(controls);
+                let rows = self.global.block_grid().arrange(elements);
 
                 Column::new()
                     .width(Length::Fill)
@@ -380,6 +1318,35 @@ impl Task {
                     .push(Space::with_height(Length::Fill))
             }
 
+            State::Barrier { .. } => {
+                Column::new()
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .align_items(Align::Center)
+                    .push(Space::with_height(Length::Fill))
+                    .push(Text::new(self.global.barrier().unwrap().message())
+                        .size(self.global.text_size("XLARGE"))
+                        .horizontal_alignment(self.global.horizontal_alignment()))
+                    .push(Space::with_height(Length::Fill))
+            }
+
+            State::Break { remaining } => {
+                let pause = self.global.block_break().unwrap();
+                Column::new()
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .spacing(40)
+                    .align_items(Align::Center)
+                    .push(Space::with_height(Length::Fill))
+                    .push(Text::new(pause.message())
+                        .size(self.global.text_size("XLARGE"))
+                        .horizontal_alignment(self.global.horizontal_alignment()))
+                    .push(Text::new(
+                        format!("The next block starts in {}...", remaining.div_ceil(1000)))
+                        .size(self.global.text_size("LARGE")))
+                    .push(Space::with_height(Length::Fill))
+            }
+
             State::Starting { wait_for, .. } => {
                 Column::new()
                     .width(Length::Fill)
@@ -392,11 +1359,126 @@ impl Task {
                     .push(Space::with_height(Length::Fill))
             }
 
-            State::Started { .. } if is_active => {
-                self.dispatcher.as_mut().unwrap().view(&self.global)
+            State::Started { .. } if is_active => match &mut self.abort_dialog {
+                Some([h_yes, h_no]) => {
+                    let size = self.global.text_size("LARGE");
+                    Column::new()
+                        .width(Length::Fill)
+                        .height(Length::Fill)
+                        .spacing(40)
+                        .align_items(Align::Center)
+                        .push(Space::with_height(Length::Fill))
+                        .push(Text::new("Abort block?")
+                            .size(self.global.text_size("XLARGE")))
+                        .push(Row::new()
+                            .spacing(40)
+                            .push(button(h_yes, "Yes", size)
+                                .on_press(Message::ConfirmAbort(true))
+                                .style(style::Button::Destructive)
+                                .width(Length::Units(200))
+                                .padding(15))
+                            .push(button(h_no, "No", size)
+                                .on_press(Message::ConfirmAbort(false))
+                                .style(style::Button::Secondary)
+                                .width(Length::Units(200))
+                                .padding(15)))
+                        .push(Space::with_height(Length::Fill))
+                }
+                None => self.dispatcher.as_mut().unwrap().view(&self.global),
+            },
+
+            State::Complete => {
+                let size = self.global.text_size("XLARGE");
+                let message: Element<Message> = if self.global.rich_text().is_some() {
+                    markup::view(&self.debrief.message, size, style::palette().text, self.global.horizontal_alignment(), &self.global)
+                        .into()
+                } else {
+                    Text::new(&self.debrief.message)
+                        .size(size)
+                        .horizontal_alignment(self.global.horizontal_alignment())
+                        .into()
+                };
+                let mut content = Column::new()
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .spacing(40)
+                    .align_items(Align::Center)
+                    .push(Space::with_height(Length::Fill))
+                    .push(message);
+                if let Some(code) = &self.debrief.code {
+                    content = content
+                        .push(Text::new("Your completion code is:")
+                            .size(self.global.text_size("LARGE")))
+                        .push(Text::new(code.as_str())
+                            .size(self.global.text_size("XLARGE")));
+                }
+                content = content.push(Space::with_height(Length::Fill));
+                match &mut self.upload {
+                    Some(upload) => content.push(upload.offer(&self.global)),
+                    None => content,
+                }
+            }
+
+            State::Upload => {
+                self.upload.as_mut().unwrap().view(&self.global)
+            }
+
+            State::Error { message, handles: [h_exit, h_return] } => {
+                let size = self.global.text_size("LARGE");
+                let back = if self.in_session { "Return to block selection" } else { "Return to start" };
+                Column::new()
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .spacing(40)
+                    .align_items(Align::Center)
+                    .push(Space::with_height(Length::Fill))
+                    .push(Text::new("Something went wrong")
+                        .size(self.global.text_size("XLARGE")))
+                    .push(Text::new(message.as_str())
+                        .size(self.global.text_size("NORMAL"))
+                        .horizontal_alignment(HorizontalAlignment::Center))
+                    .push(Text::new("The logs recorded so far have been saved.")
+                        .size(self.global.text_size("SMALL")))
+                    .push(Row::new()
+                        .spacing(40)
+                        .push(button(h_exit, "Save & exit", size)
+                            .on_press(Message::UIEvent(0x01, Value::Null))
+                            .style(style::Button::Destructive)
+                            .width(Length::Units(300))
+                            .padding(15))
+                        .push(button(h_return, back, size)
+                            .on_press(Message::UIEvent(0x02, Value::Null))
+                            .style(style::Button::Secondary)
+                            .width(Length::Units(300))
+                            .padding(15)))
+                    .push(Space::with_height(Length::Fill))
             }
 
             _ => Column::new()
+        };
+
+        let content = match low_disk {
+            Some(mb) => Column::new()
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .push(Text::new(format!("Warning: only {} MB of free disk space left for output", mb))
+                    .size(self.global.text_size("SMALL"))
+                    .color(style::parse_color("red").unwrap()))
+                .push(content),
+            None => content,
+        };
+
+        match remaining {
+            Some(remaining) if self.global.debug_ui() => {
+                let secs = remaining.as_secs();
+                Column::new()
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .push(Text::new(format!("Session time left: {:02}:{:02}", secs / 60, secs % 60))
+                        .size(self.global.text_size("TINY")))
+                    .push(content)
+            }
+            _ => content,
         }
     }
 
@@ -408,3 +1490,210 @@ impl Task {
         &self.global
     }
 }
+
+/// Counterbalance index of a subject: the number that the subject ID ends with (e.g. 7 for
+/// "S07"), or else a hash of the ID.
+fn counterbalance_index(subject: &str) -> usize {
+    let digits = subject.len() - subject.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    match subject[subject.len() - digits..].parse::<usize>() {
+        Ok(number) => number,
+        Err(_) => fnv1a(subject) as usize,
+    }
+}
+
+/// FNV-1a hash, which (unlike the standard hasher) is stable across builds.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A row of a balanced Latin square of `n` conditions, in which each condition follows each
+/// other one equally often across rows. For an odd `n`, rows `n..2n` are the first `n` reversed.
+fn latin_square_row(n: usize, index: usize) -> Vec<usize> {
+    if n == 0 {
+        return vec![];
+    }
+    let rows = if n.is_multiple_of(2) { n } else { 2 * n };
+    let row = index % rows;
+    let mut order: Vec<usize> = (0..n)
+        .map(|j| if j.is_multiple_of(2) { (row + j / 2) % n } else { (row + n - j.div_ceil(2)) % n })
+        .collect();
+    if row >= n {
+        order.reverse();
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Checks that `rows` are permutations of `n` conditions, in which each condition takes each
+    /// position and follows each other one equally often.
+    fn assert_balanced(n: usize, rows: &[Vec<usize>]) {
+        let mut positions = HashMap::new();
+        let mut pairs = HashMap::new();
+        for row in rows {
+            let mut sorted = row.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..n).collect::<Vec<_>>(), "{:?} is not a permutation", row);
+            for (position, &condition) in row.iter().enumerate() {
+                *positions.entry((position, condition)).or_insert(0) += 1;
+            }
+            for pair in row.windows(2) {
+                *pairs.entry((pair[0], pair[1])).or_insert(0) += 1;
+            }
+        }
+        assert_eq!(positions.len(), n * n);
+        assert!(positions.values().all(|&count| count == rows.len() / n));
+        assert_eq!(pairs.len(), n * (n - 1));
+        assert!(pairs.values().all(|&count| count == rows.len() / n));
+    }
+
+    #[test]
+    fn balances_an_even_latin_square() {
+        let rows: Vec<_> = (0..4).map(|index| latin_square_row(4, index)).collect();
+        assert_eq!(rows[0], vec![0, 3, 1, 2]);
+        assert_balanced(4, &rows);
+        assert_eq!(latin_square_row(4, 5), rows[1]);
+    }
+
+    #[test]
+    fn balances_an_odd_latin_square_over_twice_the_rows() {
+        let rows: Vec<_> = (0..6).map(|index| latin_square_row(3, index)).collect();
+        assert_balanced(3, &rows);
+        assert_eq!(rows[3], rows[0].iter().rev().copied().collect::<Vec<_>>());
+        assert_eq!(latin_square_row(3, 6), rows[0]);
+        assert!(latin_square_row(0, 2).is_empty());
+    }
+
+    /// A task loaded from YAML in a fresh directory under the temporary directory.
+    fn load(name: &str, yaml: &str) -> Task {
+        let dir = std::env::temp_dir().join("task-runner-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("task.yml"), yaml).unwrap();
+        let options = Options {
+            output_dir: Some(dir.join("output")),
+            subject: Some("S01".to_string()),
+            ..Default::default()
+        };
+        Task::with_options(dir, options).unwrap()
+    }
+
+    fn count_events(task: &Task, event: &str) -> usize {
+        let events = std::fs::read_to_string(Path::new(task.log_dir()).join("events.log")).unwrap();
+        events.lines().filter(|line| line.contains(event)).count()
+    }
+
+    #[test]
+    fn locks_blocks_until_their_requirements_are_done() {
+        let mut task = load("requires", r#"
+            title: T
+            version: "1.0"
+            blocks:
+              - { title: A, actions: [ { type: nothing, timeout: 100 } ] }
+              - { title: B, actions: [ { type: nothing, timeout: 100 } ] }
+              - { title: C, requires: [ 1, 2 ], actions: [ { type: nothing, timeout: 100 } ] }
+        "#);
+        let _ = task.update(Message::UIEvent(0x02, Value::Null));
+        assert_eq!((1..=3).map(|i| task.is_locked(i)).collect::<Vec<_>>(), [false, false, true]);
+
+        let _ = task.update(Message::UIEvent(3, Value::Null));
+        assert!(matches!(task.state, State::Selection { .. }));
+        task.events.write_summary();
+        assert_eq!(count_events(&task, "LOCKED  3"), 1);
+
+        task.progress[0] = true;
+        assert!(task.is_locked(3));
+        task.practiced[1] = true;
+        assert!(!task.is_locked(3));
+    }
+
+    #[test]
+    fn unlocks_blocks_in_the_order_they_are_presented() {
+        let mut task = load("sequential", r#"
+            title: T
+            version: "1.0"
+            unlock: sequential
+            block_order: latin_square
+            blocks:
+              - { title: P, practice: true, actions: [ { type: nothing, timeout: 100 } ] }
+              - { title: A, actions: [ { type: nothing, timeout: 100 } ] }
+              - { title: B, actions: [ { type: nothing, timeout: 100 } ] }
+              - { title: C, actions: [ { type: nothing, timeout: 100 } ] }
+        "#);
+        let _ = task.update(Message::UIEvent(0x02, Value::Null));
+        let sequence = task.sequence();
+        assert_eq!(sequence[0], 1);
+        assert_ne!(sequence, [1, 2, 3, 4]);
+
+        task.practiced[0] = true;
+        for (position, &block) in sequence.iter().enumerate().skip(1) {
+            let unlocked: Vec<_> = sequence.iter().filter(|&&i| !task.is_locked(i)).copied().collect();
+            assert_eq!(unlocked, sequence[..=position]);
+            assert_eq!(task.next_incomplete(), Some(block));
+            task.progress[block - 1] = true;
+        }
+        assert_eq!(task.next_incomplete(), None);
+    }
+
+    #[test]
+    fn times_out_the_session_by_interrupting_the_running_block() {
+        let mut task = load("timeout-block", r#"
+            title: T
+            version: "1.0"
+            global: { max_session: 1 }
+            blocks:
+              - title: B
+                actions:
+                  - { type: nothing, timeout: 60000 }
+        "#);
+        let (writer, _reader) = std::sync::mpsc::channel();
+        let _ = task.update(Message::SetComms(writer));
+        let _ = task.update(Message::UIEvent(0x02, Value::Null));
+        assert!(matches!(task.state, State::Selection { .. }));
+        // Starts the block as `execute` does, without opening an audio device
+        let block = task.blocks[0].clone().with_log_dir(&task.log_dir);
+        let _ = task.dispatcher.as_mut().unwrap().init(block, &task.global);
+        task.active_block = Some(1);
+        task.state = State::Started;
+        let _ = task.update(Message::Tick);
+        assert!(matches!(task.state, State::Started));
+
+        task.started = Instant::now().checked_sub(Duration::from_secs(61));
+        let _ = task.update(Message::Tick);
+        assert!(matches!(task.state, State::Complete));
+        assert!(task.active_block.is_none() && !task.is_active());
+        assert_eq!(count_events(&task, "INTERRUPT  1"), 1);
+        assert_eq!(count_events(&task, "SESSION_TIMEOUT"), 1);
+    }
+
+    #[test]
+    fn times_out_the_session_once_and_keeps_the_upload_screen() {
+        let mut task = load("timeout", r#"
+            title: T
+            version: "1.0"
+            global: { max_session: 1 }
+            upload: { url: "https://example.org/uploads" }
+            blocks:
+              - title: B
+                actions:
+                  - { type: nothing, timeout: 100 }
+        "#);
+        task.started = Instant::now().checked_sub(Duration::from_secs(120));
+
+        let _ = task.update(Message::Tick);
+        assert!(matches!(task.state, State::Complete));
+        let _ = task.update(Message::Tick);
+        assert_eq!(count_events(&task, "SESSION_TIMEOUT"), 1);
+
+        let _ = task.update(Message::UIEvent(0x01, Value::Null));
+        assert!(matches!(task.state, State::Upload));
+        let _ = task.update(Message::Tick);
+        assert!(matches!(task.state, State::Upload));
+        assert_eq!(count_events(&task, "SESSION_TIMEOUT"), 1);
+    }
+}
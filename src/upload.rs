@@ -0,0 +1,266 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use iced::{Column, Command, Length, Row, Text, button, Align, HorizontalAlignment};
+use iced_native::Space;
+use serde::{Serialize, Deserialize};
+
+use crate::comm::{Message, Value};
+use crate::error::Error;
+use crate::global::Global;
+use crate::logger;
+use crate::style::{self, button};
+
+/// Destination that the output of a session is pushed to once it is complete, after the
+/// operator confirms it on the final screen. Files are sent one by one (using the system
+/// `curl`) to `<url>/<session>/<path>`, each retried a few times before the upload fails.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Upload {
+    /// Endpoint that accepts files by HTTP PUT, or an `sftp://` directory with the
+    /// `upload-sftp` feature
+    url: String,
+    /// Sent as a bearer token with HTTP uploads; kept out of the logs
+    #[serde(default, skip_serializing)]
+    token: Option<String>,
+    #[serde(default="default::retries")]
+    retries: u32,
+    #[serde(skip)]
+    status: Status,
+    #[serde(skip)]
+    handles: [button::State; 3],
+}
+
+#[derive(Debug, Clone, Default)]
+enum Status {
+    #[default]
+    Confirm,
+    Uploading,
+    Done(usize),
+    Failed(String),
+}
+
+impl Upload {
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.url = self.url.trim_end_matches('/').to_string();
+        if self.url.starts_with("http://") || self.url.starts_with("https://") {
+            Ok(())
+        } else if self.url.starts_with("sftp://") {
+            if cfg!(feature = "upload-sftp") {
+                Ok(())
+            } else {
+                Err(Error::Invalid(format!("SFTP uploads are only supported when built with the `upload-sftp` feature: {}", self.url)))
+            }
+        } else if self.url.starts_with("s3://") {
+            Err(Error::Invalid(format!("S3 buckets are not supported, upload to an HTTP endpoint in front of the bucket instead: {}", self.url)))
+        } else {
+            Err(Error::Invalid(format!("Upload URL has to start with http://, https://, or sftp://: {}", self.url)))
+        }
+    }
+
+    pub fn set_token(&mut self, token: &str) {
+        self.token = Some(token.to_string()).filter(|token| !token.is_empty());
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn is_busy(&self) -> bool {
+        matches!(self.status, Status::Uploading)
+    }
+
+    /// Shows the confirmation screen (again).
+    pub fn confirm(&mut self) {
+        self.status = Status::Confirm;
+    }
+
+    /// Uploads the session output directory in the background.
+    pub fn start(&mut self, log_dir: &str) -> Command<Message> {
+        self.status = Status::Uploading;
+        let dir = PathBuf::from(log_dir);
+        let upload = self.clone();
+        Command::perform(async move {
+            upload.upload_dir(&dir)
+        }, Message::Uploaded)
+    }
+
+    pub fn finish(&mut self, result: Result<usize, String>) {
+        self.status = match result {
+            Ok(n) => Status::Done(n),
+            Err(e) => Status::Failed(e),
+        };
+    }
+
+    fn upload_dir(&self, dir: &Path) -> Result<usize, String> {
+        logger::wait_for_output();
+        let session = dir.file_name().unwrap().to_str().unwrap();
+        let mut files = vec![];
+        list_files(dir, &mut files)
+            .map_err(|e| format!("Failed to list session output in {:?}: {}", dir, e))?;
+        for file in &files {
+            let path = file.strip_prefix(dir).unwrap().to_str().unwrap().replace('\\', "/");
+            let target = format!("{}/{}/{}", self.url, session, path);
+            let mut attempt = 0;
+            while let Err(e) = self.upload_file(file, &target) {
+                attempt += 1;
+                if attempt > self.retries {
+                    return Err(format!("Failed to upload {}: {}", path, e));
+                }
+                println!("Warning: Failed to upload {} (retrying {}/{}): {}", path, attempt, self.retries, e);
+                std::thread::sleep(Duration::from_secs(attempt as u64));
+            }
+        }
+        println!("Uploaded {} files to {}/{}", files.len(), self.url, session);
+        Ok(files.len())
+    }
+
+    fn upload_file(&self, file: &Path, target: &str) -> Result<(), String> {
+        let mut command = std::process::Command::new("curl");
+        command
+            .args(["--fail", "--silent", "--show-error", "--upload-file"])
+            .arg(file);
+        // The token is handed over on the standard input of curl, not on its command line
+        let header = match &self.token {
+            _ if target.starts_with("sftp://") => {
+                command.arg("--ftp-create-dirs");
+                None
+            }
+            Some(token) => {
+                command.args(["--header", "@-"]);
+                Some(format!("Authorization: Bearer {}\n", token))
+            }
+            None => None,
+        };
+        let mut child = command.arg(target)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run curl: {}", e))?;
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some(header) = header {
+            stdin.write_all(header.as_bytes())
+                .map_err(|e| format!("Failed to pass the upload token to curl: {}", e))?;
+        }
+        drop(stdin);
+        let output = child.wait_with_output()
+            .map_err(|e| format!("Failed to run curl: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+
+    /// Button on the final screen that leads to the confirmation.
+    pub fn offer(&mut self, global: &Global) -> iced::Button<'_, Message> {
+        button(&mut self.handles[2], "Upload results", global.text_size("NORMAL"))
+            .on_press(Message::UIEvent(0x01, Value::Null))
+            .style(style::Button::Secondary)
+            .width(Length::Units(300))
+            .padding(10)
+    }
+
+    pub fn view(&mut self, global: &Global) -> Column<'_, Message> {
+        let size = global.text_size("LARGE");
+        let [h_back, h_upload, _] = &mut self.handles;
+        let (title, message, back, upload) = match &self.status {
+            Status::Confirm => (
+                "Upload results?".to_string(),
+                format!("The output of this session will be sent to {}", self.url),
+                Some("Cancel"),
+                Some("Upload"),
+            ),
+            Status::Uploading => (
+                "Uploading...".to_string(),
+                format!("Sending the output of this session to {}", self.url),
+                None,
+                None,
+            ),
+            Status::Done(n) => (
+                "Upload complete".to_string(),
+                format!("{} files were sent to {}", n, self.url),
+                Some("Close"),
+                None,
+            ),
+            Status::Failed(e) => (
+                "Upload failed".to_string(),
+                e.clone(),
+                Some("Back"),
+                Some("Retry"),
+            ),
+        };
+
+        let mut buttons = Row::new()
+            .spacing(40);
+        if let Some(label) = back {
+            buttons = buttons.push(button(h_back, label, size)
+                .on_press(Message::UIEvent(0x01, Value::Null))
+                .style(style::Button::Secondary)
+                .width(Length::Units(200))
+                .padding(15));
+        }
+        if let Some(label) = upload {
+            buttons = buttons.push(button(h_upload, label, size)
+                .on_press(Message::UIEvent(0x02, Value::Null))
+                .style(style::Button::Primary)
+                .width(Length::Units(200))
+                .padding(15));
+        }
+
+        Column::new()
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .spacing(40)
+            .align_items(Align::Center)
+            .push(Space::with_height(Length::Fill))
+            .push(Text::new(title)
+                .size(global.text_size("XLARGE")))
+            .push(Text::new(message)
+                .size(global.text_size("NORMAL"))
+                .horizontal_alignment(HorizontalAlignment::Center))
+            .push(buttons)
+            .push(Space::with_height(Length::Fill))
+    }
+}
+
+/// Collects the files under a directory, in a stable order.
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+mod default {
+    pub fn retries() -> u32 {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init(url: &str) -> Result<(), Error> {
+        serde_yaml::from_str::<Upload>(&format!("url: {}", url)).unwrap().init()
+    }
+
+    #[test]
+    fn accepts_only_supported_upload_urls() {
+        assert!(init("https://example.org/uploads/").is_ok());
+        assert_eq!(init("sftp://user@example.org/uploads").is_ok(), cfg!(feature = "upload-sftp"));
+        assert!(init("s3://bucket/uploads").unwrap_err().to_string().contains("S3 buckets are not supported"));
+        assert!(init("ftp://example.org/uploads").is_err());
+    }
+}
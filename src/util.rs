@@ -1,26 +1,90 @@
-use std::fs::File;
 use std::path::{Path, PathBuf};
-use serde::Serialize;
+use std::time::Instant;
+#[cfg(feature = "remote-resources")]
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
 
 pub fn timestamp() -> String {
     let time = chrono::Utc::now();
     let millis = time.timestamp_subsec_millis();
-    format!("{}-{:02}-UTC", time.format("%Y-%m-%d-%H-%M-%S"), millis)
+    format!("{}-{:03}-UTC", time.format("%Y-%m-%d-%H-%M-%S"), millis)
+}
+
+/// Timestamp of a logged event: the wall-clock time, followed by the milliseconds elapsed
+/// since `origin` on the monotonic clock, which is what timing analyses should rely on.
+pub fn event_time(origin: Instant) -> String {
+    format!("{}  {:.3}", timestamp(), origin.elapsed().as_secs_f64() * 1000.0)
 }
 
-pub fn resource(task_dir: &Path, file: &str) -> Result<PathBuf, String> {
+/// Resolves a resource file. Relative paths are resolved under the `resources` directory of the
+/// task and cannot leave it. Absolute paths are used as-is, and http(s) URLs are downloaded to a
+/// cache directory if the `remote-resources` feature is enabled.
+pub fn resource(task_dir: &Path, file: &str) -> Result<PathBuf, Error> {
+    if file.starts_with("http://") || file.starts_with("https://") {
+        return remote_resource(task_dir, file);
+    }
+    if Path::new(file).is_absolute() {
+        let path = PathBuf::from(file);
+        return if path.is_file() {
+            Ok(path)
+        } else {
+            Err(Error::MissingResource(format!("Resource file not found at absolute path: {}", file)))
+        };
+    }
+
     let mut path = task_dir.join("resources").to_path_buf();
     for part in file.split('/') {
+        if part == ".." {
+            return Err(Error::MissingResource(format!("Relative resource path cannot leave the resources directory: {}", file)));
+        }
         path = path.join(part);
     }
     if path.exists() {
         Ok(path)
     } else {
-        Err(format!("Resource file not found: {}", path.to_str().unwrap()))
+        Err(Error::MissingResource(format!("Resource file not found: {}", path.to_str().unwrap())))
+    }
+}
+
+#[cfg(feature = "remote-resources")]
+fn remote_resource(task_dir: &Path, url: &str) -> Result<PathBuf, Error> {
+    let name = url.rsplit('/').next().unwrap_or_default();
+    let extension = Path::new(name).extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| format!(".{}", ext))
+        .unwrap_or_default();
+    let cache = task_dir.join("cache");
+    let path = cache.join(format!("{:x}{}", Sha256::digest(url.as_bytes()), extension));
+    if path.exists() {
+        return Ok(path);
+    }
+
+    std::fs::create_dir_all(&cache)
+        .map_err(|e| Error::io(format!("Failed to create resource cache directory: {:?}", cache), e))?;
+    let partial = path.with_extension("part");
+    let status = std::process::Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--output"])
+        .arg(&partial)
+        .arg(url)
+        .status()
+        .map_err(|e| Error::MissingResource(format!("Failed to run curl to download resource {}: {}", url, e)))?;
+    if !status.success() {
+        std::fs::remove_file(&partial).ok();
+        return Err(Error::MissingResource(format!("Failed to download resource {}: curl exited with {}", url, status)));
     }
+    std::fs::rename(&partial, &path)
+        .map_err(|e| Error::io(format!("Failed to move downloaded resource into cache: {:?}", path), e))?;
+    println!("Downloaded resource {} to {:?}", url, path);
+    Ok(path)
 }
 
-pub fn template(task_dir: &Path, file: &str) -> Result<PathBuf, String> {
+#[cfg(not(feature = "remote-resources"))]
+fn remote_resource(_task_dir: &Path, url: &str) -> Result<PathBuf, Error> {
+    Err(Error::MissingResource(format!("Resource URLs are only supported when built with the `remote-resources` feature: {}", url)))
+}
+
+pub fn template(task_dir: &Path, file: &str) -> Result<PathBuf, Error> {
     let mut path = task_dir.join("templates").to_path_buf();
     for part in file.split('/') {
         path = path.join(part);
@@ -30,26 +94,53 @@ pub fn template(task_dir: &Path, file: &str) -> Result<PathBuf, String> {
     } else if path.with_extension("yml").exists() {
         Ok(path.with_extension("yml"))
     } else {
-        Err(format!("Template file not found: {}", path.to_str().unwrap()))
+        Err(Error::MissingResource(format!("Template file not found: {}", path.to_str().unwrap())))
     }
 }
 
-pub fn output(log_dir: &str, id: &str) -> String {// Result<PathBuf, String> {
-    // let mut path = task_dir.join("output").to_path_buf();
-    // for part in file.split('/') {
-    //     path = path.join(part);
-    // }
+pub const OUTPUT_TOKENS: [&str; 4] = ["{id}", "{trial}", "{block}", "{ts}"];
+
+/// Output path prefix of an action, named by substituting the tokens of `pattern`.
+pub fn output(log_dir: &str, pattern: &str, id: &str, block: usize, trial: usize) -> String {
+    let name = pattern
+        .replace("{id}", id)
+        .replace("{trial}", &trial.to_string())
+        .replace("{block}", &block.to_string())
+        .replace("{ts}", &timestamp());
     Path::new(log_dir)
-        .join(format!("action-{}-{}", id, timestamp()))
+        .join(name)
         .to_str().unwrap().to_string()
 }
 
-pub fn async_write_to_file<T>(filename: String, data: T, err: &'static str)
-where
-    T: Send + Serialize + 'static
-{
-    std::thread::spawn(move || {
-        let file = File::create(filename).unwrap();
-        serde_yaml::to_writer(file, &data).expect(err);
-    });
+/// Checks that an output naming pattern only uses known tokens and filesystem-safe
+/// characters, and that it names the output of each action in a block uniquely.
+pub fn verify_output_pattern(pattern: &str) -> Result<(), Error> {
+    if !pattern.contains("{id}") && !pattern.contains("{trial}") {
+        return Err(Error::Invalid(format!("Output pattern should contain {{id}} or {{trial}} to be unique: {}", pattern)));
+    }
+    let mut literal = pattern.to_string();
+    for token in OUTPUT_TOKENS {
+        literal = literal.replace(token, "");
+    }
+    if let Some(c) = literal.chars().find(|c| !c.is_ascii_alphanumeric() && !"-_.".contains(*c)) {
+        return Err(Error::Invalid(format!("Invalid character in output pattern ({:?}): {}", c, pattern)));
+    }
+    Ok(())
+}
+
+/// Free space (in bytes) available to unprivileged users on the file system containing `dir`.
+#[cfg(unix)]
+pub fn free_space(dir: &str) -> Option<u64> {
+    let path = std::ffi::CString::new(dir).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } == 0 {
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_dir: &str) -> Option<u64> {
+    None
 }